@@ -1,93 +1,365 @@
 use crate::{
+    cursor::Position,
+    encoding::Encoding,
     file_type::FileType,
+    hex::HexView,
     highlighting::{HighlightType, HighlightingOptions},
+    history::{Change, History},
+    huge::{HugeFile, HUGE_FILE_SIZE, SAMPLE_SIZE},
+    options::{BufferOptions, Value},
+    theme::{Group, Theme},
 };
+use ropey::{Rope, RopeSlice};
 use std::{
     fs,
-    io::{self, Write},
+    io::{self, Read, Write},
+    os::unix::fs::{self as unix_fs, FileExt, MetadataExt},
+    path::{Path, PathBuf},
+    process,
 };
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// The tab stop of rows which aren't made by a buffer.
+const TAB_STOP: usize = 4;
 
 #[derive(Default)]
 pub struct Buffer {
+    /// Identifies the buffer for as long as it is open, unlike its index which changes when
+    /// buffers before it are closed. Set by the editor.
+    pub id: usize,
     pub save_location: Option<String>,
     pub file_type: FileType,
-    rows: Vec<Row>,
-    dirty: bool,
+    /// How lines end in the file. The text itself always uses `\n`.
+    pub file_format: FileFormat,
+    /// The character encoding of the file, which the text is converted from and to.
+    pub encoding: Encoding,
+    /// Whether the file starts with a byte order mark, which is kept out of the text.
+    pub bom: bool,
+    /// Whether the last line of the file has no line break, in which case none is added.
+    pub missing_final_newline: bool,
+    /// Whether the file didn't look like text, in which case the buffer only shows its
+    /// printable bytes.
+    pub binary: bool,
+    /// Whether the text can't be changed nor saved.
+    pub read_only: bool,
+    /// The raw bytes of the file when the buffer is shown in hex, which are edited and
+    /// saved in place of the text.
+    pub hex: Option<HexView>,
+    /// The file when it is too big to be read whole, in which case the text is empty and
+    /// rows are read from it.
+    huge: Option<HugeFile>,
+    pub options: BufferOptions,
+    text: Rope,
+    history: History,
+    highlighted_word: Option<String>,
+    /// Whether the file format or encoding was changed since the last save, which the
+    /// history of the text doesn't know about.
+    format_changed: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileFormat {
+    /// Lines end with `\n`.
+    #[default]
+    Unix,
+    /// Lines end with `\r\n`.
+    Dos,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
 }
 
 #[derive(Default)]
 pub struct Row {
     string: String,
     highlighting: Vec<HighlightType>,
+    selection: Option<(usize, usize)>,
+    len: usize,
+    tab_stop: usize,
 }
 
 impl Buffer {
+    /// Creates an empty buffer for a file which doesn't exist yet.
+    pub fn new_file(file_name: &str) -> Self {
+        Self {
+            save_location: Some(file_name.to_string()),
+            file_type: FileType::from(file_name),
+            ..Self::default()
+        }
+    }
+
     pub fn open(file_name: &str) -> Result<Self, io::Error> {
-        let contents = fs::read_to_string(file_name)?;
+        let file = fs::File::open(file_name)?;
+        let mut buffer = Self::new_file(file_name);
 
-        let mut rows = Vec::new();
+        // Huge files open at once and read-only, their lines being read as they are shown.
+        // Only those whose start looks like UTF-8 text are, as the others have to be
+        // decoded whole.
+        if file.metadata()?.len() >= HUGE_FILE_SIZE && starts_as_utf8(&file)? {
+            buffer.huge = Some(HugeFile::new(file)?);
+            buffer.read_only = true;
+            return Ok(buffer);
+        }
 
-        let file_type = FileType::from(file_name);
+        let mut bytes = Vec::new();
+        io::BufReader::new(file).read_to_end(&mut bytes)?;
+        let contents = buffer.decode_file(&bytes);
+        buffer.text = Rope::from_str(&contents);
+        buffer.read_only = buffer.binary;
 
-        for value in contents.lines() {
-            let mut row = Row::from(value);
-            row.highlight(file_type.clone().into());
-            rows.push(row);
-        }
+        Ok(buffer)
+    }
 
-        Ok(Self {
-            save_location: Some(file_name.to_string()),
-            file_type,
-            rows,
-            dirty: false,
-        })
+    /// Works out how the contents of a file store their text, and returns it. Files that
+    /// don't look like text give their printable ASCII bytes, and dots in place of the
+    /// others, which can't be saved back.
+    fn decode_file(&mut self, bytes: &[u8]) -> String {
+        let decoded = Encoding::detect(bytes).and_then(|(encoding, bom)| {
+            let start = if bom { encoding.bom().len() } else { 0 };
+            Some((encoding, bom, encoding.decode(&bytes[start..])?))
+        });
+        let Some((encoding, bom, mut contents)) = decoded else {
+            self.encoding = Encoding::default();
+            self.bom = false;
+            self.file_format = FileFormat::default();
+            self.missing_final_newline = false;
+            self.binary = true;
+
+            return bytes
+                .iter()
+                .map(|byte| match byte {
+                    b'\n' | b'\t' | b' '..=b'~' => *byte as char,
+                    _ => '.',
+                })
+                .collect();
+        };
+
+        // Files are only read as DOS ones when every line ends with `\r\n`, so that stray
+        // carriage returns are kept as they are.
+        let line_breaks = contents.matches('\n').count();
+        self.file_format = if line_breaks > 0 && contents.matches("\r\n").count() == line_breaks {
+            contents = contents.replace("\r\n", "\n");
+            FileFormat::Dos
+        } else {
+            FileFormat::Unix
+        };
+        self.encoding = encoding;
+        self.bom = bom;
+        self.missing_final_newline = !contents.is_empty() && !contents.ends_with('\n');
+        self.binary = false;
+
+        contents
     }
 
-    pub fn save(&mut self, save_location: &str) -> Result<(), io::Error> {
-        let mut file = fs::File::create(save_location)?;
+    /// Writes the buffer to `save_location` without ever leaving it half written: the text
+    /// goes to a temporary file next to it, which replaces it once it is safely on disk. The
+    /// previous contents are kept in `save_location~` when `backup` is set.
+    pub fn save(&mut self, save_location: &str, backup: bool) -> Result<(), io::Error> {
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the buffer is read-only",
+            ));
+        }
+
+        let contents = match &self.hex {
+            Some(hex) => hex.bytes().to_vec(),
+            None => self.encode()?,
+        };
+
+        // Write through symbolic links rather than replacing them.
+        let target =
+            fs::canonicalize(save_location).unwrap_or_else(|_| PathBuf::from(save_location));
+        let temporary = temporary_path(&target);
+
+        if let Err(e) = self.replace_file(&target, &temporary, &contents, backup) {
+            let _ = fs::remove_file(&temporary);
+            return Err(e);
+        }
+
         self.file_type = FileType::from(save_location);
+        match &mut self.hex {
+            Some(hex) => hex.mark_saved(),
+            None => {
+                self.history.mark_saved();
+                self.format_changed = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Shows the buffer in hex: the bytes of the file if the buffer is saved, or else those
+    /// saving it would write. The bytes of a binary file can be edited in hex.
+    pub fn enter_hex(&mut self) -> Result<(), io::Error> {
+        if self.huge.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                "the file is too big",
+            ));
+        }
+
+        let on_disk = match &self.save_location {
+            Some(save_location) if self.binary => Some(fs::read(save_location)?),
+            Some(save_location) if !self.is_dirty() => fs::read(save_location).ok(),
+            _ => None,
+        };
+        let saved = on_disk.is_some();
+        let bytes = match on_disk {
+            Some(bytes) => bytes,
+            None => self.encode()?,
+        };
+
+        self.hex = Some(HexView::new(bytes, saved));
+        if self.binary {
+            self.read_only = false;
+        }
+
+        Ok(())
+    }
+
+    /// Goes back to the text, decoded anew if the bytes were changed. Unsaved bytes which
+    /// aren't text are kept in hex, as the view of a binary file would lose them.
+    pub fn leave_hex(&mut self) -> Result<(), io::Error> {
+        let Some(hex) = self.hex.take() else {
+            return Ok(());
+        };
+
+        if hex.is_changed() {
+            let mut decoded = Self::default();
+            let contents = decoded.decode_file(hex.bytes());
+            if decoded.binary && hex.is_modified() {
+                self.hex = Some(hex);
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the bytes aren't text and aren't saved",
+                ));
+            }
+
+            self.encoding = decoded.encoding;
+            self.bom = decoded.bom;
+            self.file_format = decoded.file_format;
+            self.missing_final_newline = decoded.missing_final_newline;
+            self.binary = decoded.binary;
+            self.read_only = false;
+            self.set_text(&contents);
+        }
+
+        if !hex.is_modified() {
+            self.history.mark_saved();
+            self.format_changed = false;
+        }
+        self.read_only = self.binary;
+
+        Ok(())
+    }
+
+    /// Returns the contents of the file as they are written, with the line breaks, byte order
+    /// mark and encoding of the file.
+    fn encode(&self) -> Result<Vec<u8>, io::Error> {
+        let line_break = match self.file_format {
+            FileFormat::Unix => "\n",
+            FileFormat::Dos => "\r\n",
+        };
+
+        let mut text = self.text.to_string();
+        if self.file_format == FileFormat::Dos {
+            text = text.replace('\n', line_break);
+        }
+        if !self.is_empty() && !self.ends_with_newline() && !self.missing_final_newline {
+            text.push_str(line_break);
+        }
+
+        let mut contents = Vec::with_capacity(text.len());
+        if self.bom {
+            contents.extend_from_slice(self.encoding.bom());
+        }
+        contents.extend(self.encoding.encode(&text).map_err(|c| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} can't be written in {}", c, self.encoding),
+            )
+        })?);
+
+        Ok(contents)
+    }
+
+    fn replace_file(
+        &self,
+        target: &Path,
+        temporary: &Path,
+        contents: &[u8],
+        backup: bool,
+    ) -> Result<(), io::Error> {
+        let original = fs::metadata(target).ok();
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temporary)?;
+        file.write_all(contents)?;
 
-        for row in self.rows.iter_mut() {
-            file.write_all(row.as_bytes())?;
-            file.write_all(b"\n")?;
+        if let Some(original) = &original {
+            file.set_permissions(original.permissions())?;
+            // Only root can give the file away, but the group can often be kept anyway.
+            if unix_fs::fchown(&file, Some(original.uid()), Some(original.gid())).is_err() {
+                let _ = unix_fs::fchown(&file, None, Some(original.gid()));
+            }
+        }
+        file.sync_all()?;
 
-            let options = self.file_type.clone().into();
-            row.highlight(options);
+        if backup && original.is_some() {
+            let mut backup_path = target.as_os_str().to_owned();
+            backup_path.push("~");
+            fs::copy(target, backup_path)?;
         }
 
-        self.dirty = false;
+        fs::rename(temporary, target)?;
+
+        // Make the rename itself durable.
+        let directory = match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if let Ok(directory) = fs::File::open(directory) {
+            let _ = directory.sync_all();
+        }
 
         Ok(())
     }
 
-    pub fn insert(&mut self, c: char, at: &crate::cursor::Position) {
+    pub fn insert(&mut self, c: char, at: &Position) {
         if c == '\n' {
             self.insert_newline(at);
             return;
         }
 
-        match at.y.cmp(&self.len()) {
-            std::cmp::Ordering::Equal => {
-                let mut row = Row::default();
-                row.insert(0, c);
-                self.rows.push(row);
-            }
-            std::cmp::Ordering::Less => self.row_mut(at.y).unwrap().insert(at.x, c),
-            _ => (),
+        if at.y > self.len() {
+            return;
         }
 
-        self.highlight_row(at.y);
-        self.dirty = true;
+        self.insert_str(at, &c.to_string());
     }
 
-    pub fn delete(&mut self, at: &crate::cursor::Position, backspace: bool) {
+    pub fn delete(&mut self, at: &Position, backspace: bool) {
         if at.y >= self.len() {
             return;
         }
 
         if !backspace {
-            self.row_mut(at.y).unwrap().delete(at.x);
-            self.highlight_row(at.y);
+            if at.x < self.row(at.y).unwrap().len() {
+                self.delete_range(
+                    at,
+                    &Position {
+                        x: at.x + 1,
+                        y: at.y,
+                    },
+                );
+            }
             return;
         }
 
@@ -96,129 +368,617 @@ impl Buffer {
         }
 
         if at.x == 0 {
-            let string = self.row(at.y).unwrap().string.clone();
+            let previous = Position {
+                x: self.row(at.y - 1).unwrap().len(),
+                y: at.y - 1,
+            };
+            self.delete_range(&previous, at);
+        } else {
+            self.delete_range(
+                &Position {
+                    x: at.x - 1,
+                    y: at.y,
+                },
+                at,
+            );
+        }
+    }
 
-            self.row_mut(at.y - 1).unwrap().string.push_str(&string);
+    pub fn insert_newline(&mut self, at: &Position) {
+        self.insert_str(at, "\n");
+    }
 
-            self.rows.remove(at.y);
+    /// Inserts `text`, which may span several lines, and returns the position right after it.
+    /// Inserting past the last row starts a new row.
+    pub fn insert_str(&mut self, at: &Position, text: &str) -> Position {
+        if self.read_only {
+            return *at;
+        }
 
-            return;
+        let (at, text) = if at.y >= self.len() && !self.is_empty() && !self.ends_with_newline() {
+            (self.end_of_buffer(), format!("\n{}", text))
         } else {
-            self.row_mut(at.y).unwrap().delete(at.x.saturating_sub(1));
+            (self.clamp_position(at), text.to_string())
+        };
+
+        let index = self.char_index(&at);
+        let end = index + text.chars().count();
+        self.raw_insert(index, &text);
+        self.history.record(Change::Insert { at, index, text });
+
+        self.position(end)
+    }
+
+    /// Deletes the text between `start` (inclusive) and `end` (exclusive) and returns it.
+    pub fn delete_range(&mut self, start: &Position, end: &Position) -> String {
+        if self.read_only {
+            return String::new();
+        }
+
+        let index = self.char_index(start);
+        let text = self.raw_delete(index, self.char_index(end));
+        if !text.is_empty() {
+            self.history.record(Change::Delete {
+                at: self.position(index),
+                index,
+                text: text.clone(),
+            });
+        }
+        text
+    }
+
+    /// Replaces the text between `start` and `end` with `text` and returns the position
+    /// right after the inserted text.
+    pub fn replace_range(&mut self, start: &Position, end: &Position, text: &str) -> Position {
+        self.delete_range(start, end);
+        if text.is_empty() {
+            return *start;
         }
+        self.insert_str(start, text)
+    }
+
+    /// Groups every following edit into a single undo step until `end_transaction` is called.
+    pub fn begin_transaction(&mut self) {
+        self.history.begin();
+    }
 
-        self.highlight_row(at.y);
-        self.highlight_row(at.y.saturating_sub(1));
-        self.dirty = true;
+    pub fn end_transaction(&mut self) {
+        self.history.end();
     }
 
-    pub fn insert_newline(&mut self, at: &crate::cursor::Position) {
-        if let Some(row) = self.row(at.y) {
-            if at.x == row.len() {
-                self.rows.insert(at.y + 1, Row::default());
-                return;
+    /// Reverts the last transaction and returns the position where it happened.
+    pub fn undo(&mut self) -> Option<Position> {
+        if self.read_only {
+            return None;
+        }
+
+        let transaction = self.history.pop_undo()?;
+
+        for change in transaction.changes.iter().rev() {
+            match change {
+                Change::Insert { index, text, .. } => {
+                    self.raw_delete(*index, index + text.chars().count());
+                }
+                Change::Delete { index, text, .. } => self.raw_insert(*index, text),
             }
+        }
 
-            let row = self.row_mut(at.y).unwrap();
-            let new_row = Row::from(&row.string[at.x..]);
-            row.string = row.string[..at.x].to_string();
-            self.rows.insert(at.y + 1, new_row);
-            return;
+        let position = transaction.changes.first().map(Change::position);
+        self.history.push_redo(transaction);
+
+        position
+    }
+
+    /// Reapplies the last undone transaction and returns the position where it happened.
+    pub fn redo(&mut self) -> Option<Position> {
+        if self.read_only {
+            return None;
+        }
+
+        let transaction = self.history.pop_redo()?;
+
+        for change in transaction.changes.iter() {
+            match change {
+                Change::Insert { index, text, .. } => self.raw_insert(*index, text),
+                Change::Delete { index, text, .. } => {
+                    self.raw_delete(*index, index + text.chars().count());
+                }
+            }
+        }
+
+        let position = transaction.changes.first().map(Change::position);
+        self.history.push_undo(transaction);
+
+        position
+    }
+
+    fn raw_insert(&mut self, index: usize, text: &str) {
+        self.text.insert(index, text);
+    }
+
+    fn raw_delete(&mut self, start: usize, end: usize) -> String {
+        let end = end.min(self.text.len_chars());
+        if start >= end {
+            return String::new();
+        }
+
+        let removed = self.text.slice(start..end).to_string();
+        self.text.remove(start..end);
+        removed
+    }
+
+    /// Converts a row/grapheme position to a char index into the rope.
+    fn char_index(&self, at: &Position) -> usize {
+        if at.y >= self.text.len_lines() {
+            return self.text.len_chars();
+        }
+
+        let row = Row::from(line_content(self.text.line(at.y)));
+        self.text.line_to_char(at.y) + row.grapheme_to_char(at.x)
+    }
+
+    /// Converts a char index into the rope to a row/grapheme position.
+    fn position(&self, index: usize) -> Position {
+        let index = index.min(self.text.len_chars());
+        let y = self.text.char_to_line(index);
+        let row = Row::from(line_content(self.text.line(y)));
+
+        Position {
+            x: row.char_to_grapheme(index - self.text.line_to_char(y)),
+            y,
+        }
+    }
+
+    fn clamp_position(&self, at: &Position) -> Position {
+        if at.y >= self.text.len_lines() {
+            return self.end_of_buffer();
+        }
+
+        Position {
+            x: at
+                .x
+                .min(Row::from(line_content(self.text.line(at.y))).len()),
+            y: at.y,
+        }
+    }
+
+    fn ends_with_newline(&self) -> bool {
+        let len = self.text.len_chars();
+        len > 0 && self.text.char(len - 1) == '\n'
+    }
+
+    /// Finds the next occurrence of `query` strictly after (or before, when searching
+    /// backward) `at`, wrapping around the buffer ends.
+    pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
+        if query.is_empty() || self.is_empty() {
+            return None;
+        }
+        if let Some(huge) = &self.huge {
+            return self.find_huge(huge, query, at, direction);
+        }
+
+        let at = if at.y >= self.len() {
+            self.end_of_buffer()
+        } else {
+            *at
+        };
+        let len = self.len();
+
+        for offset in 0..=len {
+            let y = match direction {
+                SearchDirection::Forward => (at.y + offset) % len,
+                SearchDirection::Backward => (at.y + len - offset % len) % len,
+            };
+            let row = self.plain_row(y);
+            let mut matches = row
+                .as_str()
+                .match_indices(query)
+                .map(|(x, _)| row.byte_to_grapheme(x))
+                .collect::<Vec<_>>()
+                .into_iter();
+
+            let x = match direction {
+                SearchDirection::Forward if offset == 0 => matches.find(|&x| x > at.x),
+                SearchDirection::Backward if offset == 0 => matches.rfind(|&x| x < at.x),
+                SearchDirection::Forward => matches.next(),
+                SearchDirection::Backward => matches.next_back(),
+            };
+
+            if let Some(x) = x {
+                return Some(Position { x, y });
+            }
+        }
+
+        None
+    }
+
+    /// Finds `query` like [`Self::find`] in a huge file, in the part of it already scanned
+    /// for lines, so that a search never waits for the rest to be.
+    fn find_huge(
+        &self,
+        huge: &HugeFile,
+        query: &str,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<Position> {
+        let at = if at.y >= self.len() {
+            self.end_of_buffer()
+        } else {
+            *at
+        };
+        let offset = huge.line_start(at.y)? + self.plain_row(at.y).grapheme_to_byte(at.x) as u64;
+        let size = huge.scanned();
+        let found = match direction {
+            SearchDirection::Forward => huge
+                .find(query, offset + 1, size, false)
+                .or_else(|| huge.find(query, 0, offset + 1, false)),
+            SearchDirection::Backward => huge
+                .find(query, 0, offset, true)
+                .or_else(|| huge.find(query, offset, size, true)),
+        }?;
+
+        let y = huge.line_at(found);
+        let x = self
+            .plain_row(y)
+            .byte_to_grapheme((found - huge.line_start(y)?) as usize);
+        Some(Position { x, y })
+    }
+
+    pub fn highlighted_word(&self) -> Option<&str> {
+        self.highlighted_word.as_deref()
+    }
+
+    /// Sets the word whose occurrences are highlighted as matches in every row.
+    pub fn set_highlighted_word(&mut self, word: Option<String>) {
+        self.highlighted_word = word;
+    }
+
+    pub fn row(&self, index: usize) -> Option<Row> {
+        if let Some(huge) = &self.huge {
+            let mut row = Row::from(huge.line(index)?.as_str());
+            row.tab_stop = self.options.tabstop;
+            return Some(row);
+        }
+        if index >= self.len() {
+            return None;
+        }
+
+        Some(self.plain_row(index))
+    }
+
+    /// Returns the row at `index`, highlighted for the buffer's file type unless `syntax`
+    /// is off, and with the matches of the last search when `matches` is set. Only the
+    /// rows drawn are highlighted.
+    pub fn highlighted_row(&self, index: usize, matches: bool) -> Option<Row> {
+        let mut row = self.row(index)?;
+        let file_type = if self.options.syntax {
+            self.file_type.clone()
+        } else {
+            FileType::PlainText
+        };
+        let word = self.highlighted_word.as_deref().filter(|_| matches);
+        row.highlight(file_type.into(), word);
+        Some(row)
+    }
+
+    /// Returns the row at `index`, which exists.
+    fn plain_row(&self, index: usize) -> Row {
+        let mut row = match &self.huge {
+            Some(huge) => Row::from(huge.line(index).unwrap_or_default().as_str()),
+            None => Row::from(line_content(self.text.line(index))),
+        };
+        row.tab_stop = self.options.tabstop;
+        row
+    }
+
+    /// Returns the text between `start` (inclusive) and `end` (exclusive).
+    pub fn text_range(&self, start: &Position, end: &Position) -> String {
+        if self.huge.is_some() {
+            return self.huge_text_range(start, end);
+        }
+
+        let start = self.char_index(start);
+        let end = self.char_index(end);
+        if start >= end {
+            return String::new();
         }
 
-        self.rows.push(Row::default());
+        self.text.slice(start..end).to_string()
+    }
+
+    /// Gathers the text between `start` and `end` from the rows of a huge file.
+    fn huge_text_range(&self, start: &Position, end: &Position) -> String {
+        let mut text = String::new();
+
+        for y in start.y..=end.y {
+            let Some(row) = self.row(y) else {
+                break;
+            };
+            let from = if y == start.y {
+                start.x.min(row.len())
+            } else {
+                0
+            };
+            let to = if y == end.y {
+                end.x.min(row.len())
+            } else {
+                row.len()
+            };
+            if from < to {
+                text.push_str(&row.as_str()[row.grapheme_to_byte(from)..row.grapheme_to_byte(to)]);
+            }
+            if y < end.y {
+                text.push('\n');
+            }
+        }
 
-        self.highlight_row(at.y);
-        self.highlight_row(at.y + 1);
+        text
+    }
 
-        self.dirty = true;
+    /// Returns the position right after the last character of the buffer.
+    pub fn end_of_buffer(&self) -> Position {
+        match self.len().checked_sub(1) {
+            Some(last_row) => Position {
+                x: self.plain_row(last_row).len(),
+                y: last_row,
+            },
+            None => Position::default(),
+        }
     }
 
-    fn highlight_row(&mut self, at: usize) {
-        let options = self.file_type.clone().into();
-        self.row_mut(at).unwrap().highlight(options);
+    pub fn text(&self) -> String {
+        self.text.to_string()
     }
 
-    pub fn row(&self, index: usize) -> Option<&Row> {
-        self.rows.get(index)
+    /// Replaces the whole text with `text`, as a single change which can be undone.
+    pub fn set_text(&mut self, text: &str) {
+        self.begin_transaction();
+        let end = Position {
+            x: 0,
+            y: usize::MAX,
+        };
+        self.delete_range(&Position::default(), &end);
+        self.insert_str(&Position::default(), text);
+        self.end_transaction();
     }
 
-    pub fn row_mut(&mut self, index: usize) -> Option<&mut Row> {
-        self.rows.get_mut(index)
+    /// Returns the name the buffer is shown under.
+    pub fn name(&self) -> &str {
+        self.save_location.as_deref().unwrap_or("[scratch]")
     }
 
     pub fn is_dirty(&self) -> bool {
-        self.dirty
+        match &self.hex {
+            Some(hex) => hex.is_modified(),
+            None => self.history.is_modified() || self.format_changed,
+        }
+    }
+
+    pub fn set_file_format(&mut self, file_format: FileFormat) {
+        if file_format != self.file_format {
+            self.file_format = file_format;
+            self.format_changed = true;
+        }
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        if encoding != self.encoding {
+            self.encoding = encoding;
+            self.format_changed = true;
+        }
+    }
+
+    /// Makes the text editable or read-only. A huge file is read whole to be edited, while
+    /// binary files can only be edited in hex.
+    pub fn set_read_only(&mut self, read_only: bool) -> Result<(), io::Error> {
+        if read_only || !self.read_only {
+            self.read_only = read_only;
+            return Ok(());
+        }
+
+        if self.binary {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "binary files can only be edited in hex",
+            ));
+        }
+
+        if let Some(huge) = &self.huge {
+            let bytes = huge.read()?;
+            let contents = self.decode_file(&bytes);
+            if self.binary {
+                self.read_only = true;
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "binary files can only be edited in hex",
+                ));
+            }
+
+            self.text = Rope::from_str(&contents);
+            self.huge = None;
+        }
+
+        self.read_only = false;
+        Ok(())
+    }
+
+    /// Returns the value of the buffer option called `name`.
+    pub fn option(&self, name: &str) -> Option<Value> {
+        match name {
+            "fileencoding" => Some(Value::Text(self.encoding.to_string())),
+            "fileformat" => Some(Value::Text(self.file_format.to_string())),
+            "readonly" => Some(Value::Flag(self.read_only)),
+            _ => self.options.get(name),
+        }
+    }
+
+    /// Sets the buffer option called `name` to `value`, which is of the kind of the option.
+    pub fn set_option(&mut self, name: &str, value: Value) -> Result<(), String> {
+        if matches!(name, "fileencoding" | "fileformat") {
+            if self.hex.is_some() {
+                return Err("Not allowed in hex mode".to_string());
+            }
+            if self.read_only {
+                return Err("Buffer is read-only".to_string());
+            }
+        }
+
+        match (name, value) {
+            ("fileencoding", Value::Text(text)) => {
+                let encoding =
+                    Encoding::parse(&text).ok_or(format!("Invalid encoding: {}", text))?;
+                self.set_encoding(encoding);
+            }
+            ("fileformat", Value::Text(text)) => {
+                let file_format =
+                    FileFormat::parse(&text).ok_or(format!("Invalid file format: {}", text))?;
+                self.set_file_format(file_format);
+            }
+            ("readonly", Value::Flag(read_only)) => self
+                .set_read_only(read_only)
+                .map_err(|e| format!("Can't make the buffer editable: {}", e))?,
+            (name, value) => self.options.set(name, value)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn is_huge(&self) -> bool {
+        self.huge.is_some()
+    }
+
+    /// Returns how much of a huge file was scanned for lines, in percent, while it isn't
+    /// scanned whole.
+    pub fn indexing_progress(&self) -> Option<u64> {
+        self.huge
+            .as_ref()
+            .filter(|huge| !huge.is_indexed())
+            .map(HugeFile::progress)
+    }
+
+    /// Scans the next chunk of a huge file for lines.
+    pub fn index_chunk(&self) {
+        if let Some(huge) = &self.huge {
+            huge.index_chunk();
+        }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.rows.is_empty()
+        match &self.huge {
+            Some(huge) => huge.size() == 0,
+            None => self.text.len_chars() == 0,
+        }
     }
 
+    /// Returns the number of rows. A trailing newline terminates the last row rather than
+    /// starting a new one.
     pub fn len(&self) -> usize {
-        self.rows.len()
+        if let Some(huge) = &self.huge {
+            huge.len()
+        } else if self.is_empty() {
+            0
+        } else if self.ends_with_newline() {
+            self.text.len_lines() - 1
+        } else {
+            self.text.len_lines()
+        }
+    }
+}
+
+impl FileFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(Self::Unix),
+            "dos" => Some(Self::Dos),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for FileFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unix => write!(f, "unix"),
+            Self::Dos => write!(f, "dos"),
+        }
+    }
+}
+
+impl SearchDirection {
+    pub fn reversed(self) -> Self {
+        match self {
+            SearchDirection::Forward => SearchDirection::Backward,
+            SearchDirection::Backward => SearchDirection::Forward,
+        }
     }
 }
 
 impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = std::cmp::min(end, self.string.len());
-        let start = std::cmp::min(start, end);
+    /// Renders the part of the row between the display columns `start` and `end`.
+    pub fn render(&self, start: usize, end: usize, theme: &Theme) -> String {
         let mut result = String::new();
 
-        use termion::color::{Fg, Reset};
+        use termion::color::{Bg, Fg, Reset};
 
-        let mut current_highlight = &HighlightType::default();
-        for (index, c) in self
-            .string
-            .get(start..end)
-            .unwrap_or_default()
-            .to_string()
-            .chars()
-            .enumerate()
-        {
-            let highlighting_type = self.highlighting.get(index).unwrap_or(&HighlightType::None);
-
-            if highlighting_type != current_highlight {
-                current_highlight = highlighting_type;
-                let start_highlighting = format!("{}", Fg(highlighting_type.to_color()));
-                result.push_str(&start_highlighting);
-            } else {
-                result.push_str(&format!("{}", Fg(highlighting_type.to_color())));
+        let mut current_highlight = None;
+        let mut current_selected = false;
+        let mut column = 0;
+        for (index, grapheme) in self.string.graphemes(true).enumerate() {
+            let width = grapheme_width(grapheme, column, self.tab_stop);
+            let next_column = column + width;
+            if next_column > end {
+                break;
             }
 
-            result.push(c);
-        }
-        let end_highlight = format!("{}", Fg(Reset));
-        result.push_str(&end_highlight);
+            if next_column > start {
+                let highlighting_type =
+                    self.highlighting.get(index).unwrap_or(&HighlightType::None);
 
-        result
-    }
+                if current_highlight != Some(highlighting_type) {
+                    current_highlight = Some(highlighting_type);
+                    result.push_str(&format!("{}", Fg(highlighting_type.to_color(theme))));
+                }
 
-    pub fn insert(&mut self, at: usize, c: char) {
-        if at >= self.len() {
-            self.string.push(c);
-            return;
-        }
+                let selected = self
+                    .selection
+                    .is_some_and(|(start, end)| index >= start && index < end);
+                if selected != current_selected {
+                    current_selected = selected;
+                    if selected {
+                        result.push_str(&format!("{}", Bg(theme.color(Group::Selection))));
+                    } else {
+                        result.push_str(&format!("{}", Bg(Reset)));
+                    }
+                }
 
-        self.string.insert(at, c);
-    }
+                if column < start || grapheme == "\t" {
+                    // Wide graphemes cut by the left edge and tabs are drawn as blanks.
+                    result.push_str(&" ".repeat(next_column - column.max(start)));
+                } else if let Some(notation) = control_notation(grapheme) {
+                    result.push_str(&notation);
+                } else {
+                    result.push_str(grapheme);
+                }
+            }
 
-    pub fn delete(&mut self, at: usize) {
-        if at >= self.len() {
-            return;
+            column = next_column;
         }
+        let end_highlight = format!("{}{}", Fg(Reset), Bg(Reset));
+        result.push_str(&end_highlight);
+
+        // Blank out the rest of the width so whatever was drawn there before is erased.
+        let drawn = column.clamp(start, end) - start;
+        result.push_str(&" ".repeat((end - start).saturating_sub(drawn)));
 
-        let first_seg = &self.string[..at];
-        let second_seg = &self.string[at + 1..];
-        let mut final_string = String::new();
-        final_string.push_str(first_seg);
-        final_string.push_str(second_seg);
-        self.string = final_string;
+        result
     }
 
-    pub fn highlight(&mut self, options: HighlightingOptions) {
-        let chars = self.string.chars().collect::<Vec<char>>();
+    pub fn highlight(&mut self, options: HighlightingOptions, word: Option<&str>) {
+        let chars = self
+            .string
+            .graphemes(true)
+            .map(|grapheme| grapheme.chars().next().unwrap_or_default())
+            .collect::<Vec<char>>();
         let mut highlighting = Vec::new();
         let mut previous_is_separator = true;
         let mut in_string = false;
@@ -274,19 +1034,90 @@ impl Row {
 
             previous_is_separator = c.is_ascii_punctuation() || c.is_whitespace();
         }
+
+        if let Some(word) = word.filter(|word| !word.is_empty()) {
+            for (byte_index, _) in self.string.match_indices(word) {
+                let start = self.byte_to_grapheme(byte_index);
+                let end = self.byte_to_grapheme(byte_index + word.len());
+                for highlight in highlighting.iter_mut().take(end).skip(start) {
+                    *highlight = HighlightType::Match;
+                }
+            }
+        }
+
         self.highlighting = highlighting;
     }
 
+    /// Marks the graphemes between `start` and `end` (excluded) as selected, which is drawn
+    /// on top of the syntax highlighting.
+    pub fn select(&mut self, start: usize, end: usize) {
+        self.selection = Some((start, end));
+    }
+
+    /// Returns the number of grapheme clusters in the row.
     pub fn len(&self) -> usize {
-        self.string.len()
+        self.len
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.string.is_empty()
+    pub fn as_str(&self) -> &str {
+        &self.string
     }
 
-    pub fn as_bytes(&self) -> &[u8] {
-        self.string.as_bytes()
+    /// Returns the display column the grapheme at `index` starts at.
+    pub fn width_to(&self, index: usize) -> usize {
+        self.string
+            .graphemes(true)
+            .take(index)
+            .fold(0, |column, grapheme| {
+                column + grapheme_width(grapheme, column, self.tab_stop)
+            })
+    }
+
+    /// Returns the index of the grapheme covering the display column `column`.
+    pub fn grapheme_at_column(&self, column: usize) -> usize {
+        let mut current_column = 0;
+        for (index, grapheme) in self.string.graphemes(true).enumerate() {
+            current_column += grapheme_width(grapheme, current_column, self.tab_stop);
+            if current_column > column {
+                return index;
+            }
+        }
+        self.len
+    }
+
+    pub fn grapheme_to_byte(&self, index: usize) -> usize {
+        self.string
+            .grapheme_indices(true)
+            .nth(index)
+            .map(|(byte_index, _)| byte_index)
+            .unwrap_or(self.string.len())
+    }
+
+    pub fn byte_to_grapheme(&self, byte_index: usize) -> usize {
+        self.string
+            .grapheme_indices(true)
+            .take_while(|(index, _)| *index < byte_index)
+            .count()
+    }
+
+    pub fn grapheme_to_char(&self, index: usize) -> usize {
+        self.string[..self.grapheme_to_byte(index)].chars().count()
+    }
+
+    pub fn char_to_grapheme(&self, char_index: usize) -> usize {
+        let byte_index = self
+            .string
+            .char_indices()
+            .nth(char_index)
+            .map(|(byte_index, _)| byte_index)
+            .unwrap_or(self.string.len());
+        self.byte_to_grapheme(byte_index)
+    }
+}
+
+impl From<RopeSlice<'_>> for Row {
+    fn from(value: RopeSlice) -> Self {
+        Row::from(value.to_string().as_str())
     }
 }
 
@@ -295,6 +1126,177 @@ impl From<&str> for Row {
         Self {
             string: String::from(value),
             highlighting: Vec::new(),
+            selection: None,
+            len: value.graphemes(true).count(),
+            tab_stop: TAB_STOP,
         }
     }
 }
+
+/// Returns the display width of `grapheme` when it starts at the display column `column`.
+fn grapheme_width(grapheme: &str, column: usize, tab_stop: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_stop = tab_stop.max(1);
+        tab_stop - column % tab_stop
+    } else if let Some(notation) = control_notation(grapheme) {
+        notation.len()
+    } else {
+        grapheme.width()
+    }
+}
+
+/// Returns how a control character other than a tab is shown, such as `^M` for a carriage
+/// return.
+fn control_notation(grapheme: &str) -> Option<String> {
+    let mut chars = grapheme.chars();
+    let c = chars.next()?;
+    (chars.next().is_none() && c.is_ascii_control() && c != '\t')
+        .then(|| format!("^{}", (c as u8 ^ 0x40) as char))
+}
+
+/// Strips the line break from a rope line. The `\r\n` of DOS files are turned into `\n`
+/// when they are read, so a carriage return left before it is a stray one, kept in the line.
+fn line_content(line: RopeSlice) -> RopeSlice {
+    let mut len = line.len_chars();
+    if len > 0 && line.char(len - 1) == '\n' {
+        len -= 1;
+    }
+    line.slice(..len)
+}
+
+/// Returns whether the start of `file` is UTF-8 text without a byte order mark, a character
+/// cut at the end of the sample being left out.
+fn starts_as_utf8(file: &fs::File) -> Result<bool, io::Error> {
+    let mut sample = vec![0; SAMPLE_SIZE];
+    let length = file.read_at(&mut sample, 0)?;
+    let sample = match std::str::from_utf8(&sample[..length]) {
+        Err(e) if e.error_len().is_none() => &sample[..e.valid_up_to()],
+        _ => &sample[..length],
+    };
+
+    Ok(Encoding::detect(sample) == Some((Encoding::Utf8, false)))
+}
+
+/// Returns a path for a temporary file in the same directory as `target`, so that it can be
+/// renamed over it.
+pub fn temporary_path(target: &Path) -> PathBuf {
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    target.with_file_name(format!(".{}.{}.tmp", name, process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads `bytes` like the contents of a file being opened.
+    fn decode(bytes: &[u8]) -> Buffer {
+        let mut buffer = Buffer::default();
+        let contents = buffer.decode_file(bytes);
+        buffer.text = Rope::from_str(&contents);
+        buffer
+    }
+
+    fn rows(buffer: &Buffer) -> Vec<String> {
+        (0..buffer.len())
+            .map(|y| buffer.row(y).unwrap().as_str().to_string())
+            .collect()
+    }
+
+    /// Checks that `bytes` are written back as they were read.
+    fn round_trip(bytes: &[u8]) -> Buffer {
+        let buffer = decode(bytes);
+        assert_eq!(buffer.encode().unwrap(), bytes);
+        buffer
+    }
+
+    #[test]
+    fn unix_files() {
+        let buffer = round_trip(b"a\nb\n");
+        assert!(buffer.file_format == FileFormat::Unix);
+        assert!(!buffer.missing_final_newline);
+        assert_eq!(rows(&buffer)[..2], ["a", "b"]);
+    }
+
+    #[test]
+    fn dos_files() {
+        let buffer = round_trip(b"a\r\nb\r\n");
+        assert!(buffer.file_format == FileFormat::Dos);
+        assert_eq!(buffer.text(), "a\nb\n");
+        assert_eq!(rows(&buffer)[..2], ["a", "b"]);
+    }
+
+    #[test]
+    fn mixed_line_endings() {
+        // A file isn't read as a DOS one unless every line ends with `\r\n`, and the
+        // carriage returns left are part of their rows.
+        let buffer = round_trip(b"a\r\nb\nc\r\n");
+        assert!(buffer.file_format == FileFormat::Unix);
+        assert_eq!(rows(&buffer)[..3], ["a\r", "b", "c\r"]);
+
+        let buffer = round_trip(b"a\rb\n");
+        assert_eq!(rows(&buffer)[0], "a\rb");
+    }
+
+    #[test]
+    fn missing_final_newline() {
+        let buffer = round_trip(b"a\nb");
+        assert!(buffer.missing_final_newline);
+        assert_eq!(rows(&buffer), ["a", "b"]);
+
+        let buffer = round_trip(b"a\r\nb");
+        assert!(buffer.file_format == FileFormat::Dos);
+        assert!(buffer.missing_final_newline);
+
+        let buffer = round_trip(b"a");
+        assert!(buffer.missing_final_newline);
+        round_trip(b"");
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let buffer = round_trip(b"\xef\xbb\xbfa\r\nb\r\n");
+        assert!(buffer.bom && buffer.encoding == Encoding::Utf8);
+        assert!(buffer.file_format == FileFormat::Dos);
+        assert_eq!(buffer.text(), "a\nb\n");
+
+        let bytes = [0xff, 0xfe, b'a', 0, b'\r', 0, b'\n', 0, 0xe9, 0];
+        let buffer = round_trip(&bytes);
+        assert!(buffer.bom && buffer.encoding == Encoding::Utf16Le);
+        assert!(buffer.file_format == FileFormat::Dos);
+        assert!(buffer.missing_final_newline);
+        assert_eq!(buffer.text(), "a\né");
+    }
+
+    #[test]
+    fn legacy_encodings() {
+        let buffer = round_trip(b"caf\xe9\r\n");
+        assert!(buffer.encoding == Encoding::Latin1);
+        assert_eq!(buffer.text(), "café\n");
+
+        let buffer = round_trip(b"\x93quoted\x94");
+        assert!(buffer.encoding == Encoding::Windows1252);
+        assert_eq!(buffer.text(), "“quoted”");
+    }
+
+    #[test]
+    fn edits_keep_the_file_format() {
+        let mut buffer = decode(b"a\r\nb");
+        let end = buffer.end_of_buffer();
+        buffer.insert_str(&end, "\nc");
+        assert_eq!(buffer.encode().unwrap(), b"a\r\nb\r\nc");
+
+        buffer.set_file_format(FileFormat::Unix);
+        assert_eq!(buffer.encode().unwrap(), b"a\nb\nc");
+    }
+
+    #[test]
+    fn binary_files() {
+        let buffer = decode(b"\x7fELF\0\x01\n\tok");
+        assert!(buffer.binary);
+        assert_eq!(buffer.text(), ".ELF..\n\tok");
+    }
+}
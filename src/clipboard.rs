@@ -0,0 +1,203 @@
+use crate::terminal_utils as termutils;
+use std::{
+    env, io,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Which of the system selections a register maps to: `"+` is the clipboard and `"*` the
+/// primary selection.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardSelection {
+    Clipboard,
+    Primary,
+}
+
+/// The external program used to reach the system clipboard. When none is available the
+/// text is sent to the terminal with an OSC 52 escape sequence, which also works over SSH.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardProvider {
+    WlClipboard,
+    Xclip,
+    Xsel,
+    Osc52,
+}
+
+impl ClipboardSelection {
+    pub fn from_register(name: char) -> Option<Self> {
+        match name {
+            '+' => Some(Self::Clipboard),
+            '*' => Some(Self::Primary),
+            _ => None,
+        }
+    }
+}
+
+impl ClipboardProvider {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wl-copy" | "wl-clipboard" => Some(Self::WlClipboard),
+            "xclip" => Some(Self::Xclip),
+            "xsel" => Some(Self::Xsel),
+            "osc52" => Some(Self::Osc52),
+            _ => None,
+        }
+    }
+
+    /// Uses the provider named by `EDICODE_CLIPBOARD`, or the first one available for the
+    /// current display server.
+    pub fn detect() -> Self {
+        if let Some(provider) = env::var("EDICODE_CLIPBOARD")
+            .ok()
+            .and_then(|name| Self::from_name(&name))
+        {
+            return provider;
+        }
+
+        if env::var_os("WAYLAND_DISPLAY").is_some() && is_executable("wl-copy") {
+            Self::WlClipboard
+        } else if env::var_os("DISPLAY").is_some() && is_executable("xclip") {
+            Self::Xclip
+        } else if env::var_os("DISPLAY").is_some() && is_executable("xsel") {
+            Self::Xsel
+        } else {
+            Self::Osc52
+        }
+    }
+
+    /// Copies `text` to `selection`, falling back to OSC 52 if the provider fails.
+    pub fn copy(&self, text: &str, selection: ClipboardSelection) {
+        let copied = match self.copy_command(selection) {
+            Some(mut command) => run_with_input(&mut command, text).is_ok(),
+            None => false,
+        };
+
+        if !copied {
+            termutils::copy_to_clipboard(text, selection == ClipboardSelection::Primary);
+        }
+    }
+
+    /// Reads the contents of `selection`. Returns `None` when the provider can't read it,
+    /// which is always the case for OSC 52.
+    pub fn paste(&self, selection: ClipboardSelection) -> Option<String> {
+        let output = self
+            .paste_command(selection)?
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+
+        String::from_utf8(output.stdout).ok()
+    }
+
+    fn copy_command(&self, selection: ClipboardSelection) -> Option<Command> {
+        let primary = selection == ClipboardSelection::Primary;
+
+        let command = match self {
+            Self::WlClipboard => {
+                let mut command = Command::new("wl-copy");
+                if primary {
+                    command.arg("--primary");
+                }
+                command
+            }
+            Self::Xclip => {
+                let mut command = Command::new("xclip");
+                command.args(["-i", "-selection", x_selection_name(primary)]);
+                command
+            }
+            Self::Xsel => {
+                let mut command = Command::new("xsel");
+                command.args(["--input", if primary { "--primary" } else { "--clipboard" }]);
+                command
+            }
+            Self::Osc52 => return None,
+        };
+
+        Some(command)
+    }
+
+    fn paste_command(&self, selection: ClipboardSelection) -> Option<Command> {
+        let primary = selection == ClipboardSelection::Primary;
+
+        let command = match self {
+            Self::WlClipboard => {
+                let mut command = Command::new("wl-paste");
+                command.arg("--no-newline");
+                if primary {
+                    command.arg("--primary");
+                }
+                command
+            }
+            Self::Xclip => {
+                let mut command = Command::new("xclip");
+                command.args(["-o", "-selection", x_selection_name(primary)]);
+                command
+            }
+            Self::Xsel => {
+                let mut command = Command::new("xsel");
+                command.args([
+                    "--output",
+                    if primary { "--primary" } else { "--clipboard" },
+                ]);
+                command
+            }
+            Self::Osc52 => return None,
+        };
+
+        Some(command)
+    }
+}
+
+impl std::fmt::Display for ClipboardProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::WlClipboard => "wl-copy",
+            Self::Xclip => "xclip",
+            Self::Xsel => "xsel",
+            Self::Osc52 => "osc52",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+fn x_selection_name(primary: bool) -> &'static str {
+    if primary {
+        "primary"
+    } else {
+        "clipboard"
+    }
+}
+
+fn run_with_input(command: &mut Command, input: &str) -> Result<(), io::Error> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{:?} exited with {}",
+            command, status
+        )))
+    }
+}
+
+fn is_executable(program: &str) -> bool {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| Path::new(&dir).join(program).is_file()))
+        .unwrap_or(false)
+}
@@ -1,5 +1,5 @@
-#[derive(Default)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Position {
-    pub x: usize,
     pub y: usize,
+    pub x: usize,
 }
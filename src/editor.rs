@@ -1,78 +1,218 @@
 use crate::{
-    buffer::{Buffer, Row},
+    buffer::{Buffer, Row, SearchDirection},
+    clipboard::{ClipboardProvider, ClipboardSelection},
     cursor::Position,
-    message::Message,
+    hex::{self, BYTES_PER_ROW},
+    highlighting::HighlightingOptions,
+    keymap::{self, Keymaps, MapMode},
+    keys,
+    message::{Message, MessageType},
+    motion::{self, FindChar, Motion, MotionKind},
+    operator::{Operator, TextRange},
+    options::{self, BufferOptions, GlobalOptions, Kind, OptionInfo, Scope, Value, WindowOptions},
+    register::{Register, RegisterKind, Registers},
+    selection::{Selection, SelectionKind},
+    substitute::Substitution,
+    swap,
+    tab::Tab,
     terminal_utils as termutils,
+    text_object::TextObject,
+    theme::{self, ColorDepth, Group, Theme},
+    window::{Direction, Layout, Rect, SplitDirection, Window},
 };
-use std::io::{self, Write};
-use termion::{event::Key, input::TermRead};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    process,
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-const STATUS_BAR_BG_COLOR: termion::color::Rgb = termion::color::Rgb(52, 120, 198);
-const STATUS_BAR_FG_COLOR: termion::color::Rgb = termion::color::Rgb(255, 255, 255);
 
-#[derive(PartialEq, Eq)]
+/// Modified buffers are written to their swap file after this long without a key being
+/// pressed, or after this many keys.
+const SWAP_IDLE_TIME: Duration = Duration::from_secs(4);
+const SWAP_KEY_COUNT: usize = 200;
+
+/// How many times mappings are replaced by their keys before a key is used, like vim's
+/// `maxmapdepth`, so that recursive mappings which loop end.
+const MAX_MAP_DEPTH: usize = 1000;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
 enum Mode {
     Normal,
     Insert,
+    Visual,
+    VisualLine,
+    VisualBlock,
+}
+
+impl Mode {
+    fn selection_kind(&self) -> Option<SelectionKind> {
+        match self {
+            Mode::Visual => Some(SelectionKind::Character),
+            Mode::VisualLine => Some(SelectionKind::Line),
+            Mode::VisualBlock => Some(SelectionKind::Block),
+            Mode::Normal | Mode::Insert => None,
+        }
+    }
+
+    fn map_mode(&self) -> MapMode {
+        match self {
+            Mode::Normal => MapMode::Normal,
+            Mode::Insert => MapMode::Insert,
+            Mode::Visual | Mode::VisualLine | Mode::VisualBlock => MapMode::Visual,
+        }
+    }
+}
+
+/// A change made from normal mode, kept so `.` can replay it. `keys` holds everything
+/// typed for it but the count and the register name.
+#[derive(Clone, Default)]
+struct RecordedChange {
+    keys: Vec<Key>,
+    count: Option<usize>,
+    register: Option<char>,
 }
 
 pub struct Editor {
     buffers: Vec<Buffer>,
-    cursor_positions: Vec<Position>,
-    scroll_offsets: Vec<Position>,
-    current_buffer: usize,
+    next_buffer_id: usize,
+    windows: Vec<Window>,
+    layout: Layout,
+    current_window: usize,
+    tabs: Vec<Tab>,
+    current_tab: usize,
     terminal_size: (u16, u16),
     mode: Mode,
     prompt_bar_message: Message,
+    registers: Registers,
+    pending_register: Option<char>,
+    clipboard: ClipboardProvider,
+    last_search: Option<(String, SearchDirection)>,
+    last_find: Option<FindChar>,
+    /// Keys to process before reading the terminal, with whether mappings apply to them.
+    pending_keys: VecDeque<(Key, bool)>,
+    keymaps: Keymaps,
+    current_change: RecordedChange,
+    last_change: Option<RecordedChange>,
+    recording: Option<(char, Vec<Key>)>,
+    last_macro: Option<char>,
     should_quit: bool,
+    options: GlobalOptions,
+    /// The values of the buffer and window options new buffers and windows start with.
+    buffer_options: BufferOptions,
+    window_options: WindowOptions,
+    theme: Theme,
+    input: Receiver<Result<Key, io::Error>>,
+    /// The swap file written for each buffer, by id.
+    swap_files: HashMap<usize, PathBuf>,
+    keys_since_swap: usize,
+    /// Whether the first hex digit of the byte under the cursor was just typed, when
+    /// editing bytes in hex.
+    hex_nibble: bool,
 }
 
 impl Default for Editor {
     fn default() -> Self {
-        let buffers = vec![{
-            let args: Vec<String> = std::env::args().collect();
-
-            if args.len() > 1 {
-                Buffer::open(&args[1]).unwrap_or_default()
-            } else {
-                Buffer::default()
-            }
-        }];
+        let args: Vec<String> = std::env::args().collect();
+
+        let (mut buffer, prompt_bar_message) = match args.get(1) {
+            Some(file_name) => match Buffer::open(file_name) {
+                Ok(buffer) => (buffer, Message::default()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (
+                    Buffer::new_file(file_name),
+                    Message::new_normal(format!("\"{}\" [New]", file_name)),
+                ),
+                // The buffer isn't named after the file so that it can't overwrite it.
+                Err(e) => (
+                    Buffer::default(),
+                    Message::new_error(format!("Can't open \"{}\": {}", file_name, e)),
+                ),
+            },
+            None => (Buffer::default(), Message::default()),
+        };
+        buffer.id = 1;
 
         let mut terminal_size = termion::terminal_size().unwrap();
         terminal_size.1 -= 3;
 
-        Self {
-            buffers,
-            current_buffer: 0,
+        let mut editor = Self {
+            buffers: vec![buffer],
+            next_buffer_id: 2,
+            windows: vec![Window::new(0, WindowOptions::default())],
+            layout: Layout::Window(0),
+            current_window: 0,
+            tabs: vec![Tab::default()],
+            current_tab: 0,
             terminal_size,
             mode: Mode::Normal,
-            prompt_bar_message: Message::default(),
-            cursor_positions: vec![Position::default()],
-            scroll_offsets: vec![Position::default()],
+            prompt_bar_message,
+            registers: Registers::default(),
+            pending_register: None,
+            clipboard: ClipboardProvider::detect(),
+            last_search: None,
+            last_find: None,
+            pending_keys: VecDeque::new(),
+            keymaps: Keymaps::default(),
+            current_change: RecordedChange::default(),
+            last_change: None,
+            recording: None,
+            last_macro: None,
             should_quit: false,
-        }
+            options: GlobalOptions::default(),
+            buffer_options: BufferOptions::default(),
+            window_options: WindowOptions::default(),
+            theme: Theme::new(ColorDepth::detect()),
+            input: termutils::read_keys(),
+            swap_files: HashMap::new(),
+            keys_since_swap: 0,
+            hex_nibble: false,
+        };
+        editor.load_config();
+        editor
     }
 }
 
 impl Editor {
     pub fn run(&mut self) {
+        if let Err(e) = self.recover_swap_file(0) {
+            self.die(e);
+        }
+
         loop {
-            if let Err(e) = self.refresh_screen() {
-                die(e);
+            // Keys replayed from a macro are processed without redrawing in between.
+            if self.pending_keys.is_empty() || self.should_quit {
+                if let Err(e) = self.refresh_screen() {
+                    self.die(e);
+                }
             }
 
             if self.should_quit {
+                self.remove_swap_files();
                 break;
             }
 
             if let Err(e) = self.process_keypress() {
-                die(e);
+                self.die(e);
             }
         }
     }
 
+    /// Stops on an error the editor can't go on after, writing the swap files first so that
+    /// the changes made since they were last written can be recovered.
+    fn die(&mut self, e: io::Error) {
+        self.write_swap_files();
+        termutils::clear();
+        panic!("{}", e);
+    }
+
     fn refresh_screen(&self) -> Result<(), io::Error> {
         termutils::hide_cursor();
         termutils::set_cursor_position(&Position::default());
@@ -81,17 +221,14 @@ impl Editor {
             termutils::clear();
             println!("exited");
         } else {
-            self.draw_rows();
-            self.draw_status_bar();
+            self.draw_tabline();
+            for (window, rect) in self.layout.arrange(self.window_area()) {
+                self.draw_rows(window, rect);
+                self.draw_status_bar(window, rect);
+            }
+            self.draw_separators();
             self.draw_command_bar();
-            termutils::set_cursor_position(&Position {
-                x: self.cursor_positions[self.current_buffer]
-                    .x
-                    .saturating_sub(self.scroll_offsets[self.current_buffer].x),
-                y: self.cursor_positions[self.current_buffer]
-                    .y
-                    .saturating_sub(self.scroll_offsets[self.current_buffer].y),
-            });
+            termutils::set_cursor_position(&self.cursor_screen_position());
         }
 
         termutils::show_cursor();
@@ -99,63 +236,71 @@ impl Editor {
     }
 
     fn process_keypress(&mut self) -> Result<(), io::Error> {
-        let pressed_key = Editor::read_key()?;
+        let Some(pressed_key) = self.read_mapped_key()? else {
+            self.prompt_bar_message = Message::new_error("Recursive mapping".to_string());
+            return Ok(());
+        };
 
         self.prompt_bar_message = Message::default();
 
-        match pressed_key {
-            Key::Esc => self.mode = Mode::Normal,
-            Key::Up | Key::Down | Key::Left | Key::Right => self.move_cursor(pressed_key),
-            _ => (),
-        };
+        if pressed_key == Key::Esc {
+            if self.mode == Mode::Insert && self.buffer().hex.is_none() {
+                self.buffer_mut().end_transaction();
+                self.finish_change();
+            }
+            self.mode = Mode::Normal;
+            self.pending_register = None;
+            self.hex_nibble = false;
+        }
 
+        let hex = self.buffer().hex.is_some();
         match self.mode {
-            Mode::Normal => match pressed_key {
-                Key::Char(':') if self.mode == Mode::Normal => {
-                    let prompt = self.prompt(":")?;
-                    if let Some(command) = prompt {
-                        self.process_command(command)?;
-                    }
-                }
-                Key::Char('i') if self.mode == Mode::Normal => self.mode = Mode::Insert,
-                Key::Char('k') | Key::Char('j') | Key::Char('h') | Key::Char('l')
-                    if self.mode == Mode::Normal =>
-                {
-                    self.move_cursor(pressed_key)
-                }
-                Key::Char('d') if self.mode == Mode::Normal => {
-                    self.buffers[self.current_buffer]
-                        .delete(&self.cursor_positions[self.current_buffer], false);
-                }
-                _ => (),
-            },
+            Mode::Normal if hex => self.process_hex_keypress(pressed_key)?,
+            Mode::Normal => self.process_normal_keypress(pressed_key)?,
+            Mode::Insert if hex => self.process_hex_insert_keypress(pressed_key),
             Mode::Insert => match pressed_key {
-                Key::Backspace if self.mode == Mode::Insert => {
-                    let x = self.cursor_positions[self.current_buffer].x;
-                    let y = self.cursor_positions[self.current_buffer].y;
+                Key::Up | Key::Down | Key::Left | Key::Right => self.move_cursor(pressed_key),
+                Key::Backspace => {
+                    let x = self.windows[self.current_window].cursor.x;
+                    let y = self.windows[self.current_window].cursor.y;
 
                     if !(x == 0 && y == 0) {
                         if x == 0 {
-                            self.cursor_positions[self.current_buffer].y = y.saturating_sub(1);
-                            self.cursor_positions[self.current_buffer].x = self.buffers
-                                [self.current_buffer]
-                                .row(y.saturating_sub(1))
-                                .unwrap()
-                                .len();
+                            self.windows[self.current_window].cursor.y = y.saturating_sub(1);
+                            self.windows[self.current_window].cursor.x = self.buffers
+                                [self.current_buffer()]
+                            .row(y.saturating_sub(1))
+                            .unwrap()
+                            .len();
                         } else {
                             self.move_cursor(Key::Left);
                         }
 
-                        self.buffers[self.current_buffer].delete(&Position { x, y }, true);
+                        self.buffer_mut().delete(&Position { x, y }, true);
                     }
                 }
-                Key::Char(c) if self.mode == Mode::Insert => {
-                    self.buffers[self.current_buffer]
-                        .insert(c, &self.cursor_positions[self.current_buffer]);
+                Key::Char('\t') if self.buffer().options.expandtab => {
+                    let tab_stop = self.buffer().options.tabstop;
+                    let spaces = " ".repeat(tab_stop - self.cursor_column() % tab_stop);
+                    let cursor = self.windows[self.current_window].cursor;
+                    let end = self.buffer_mut().insert_str(&cursor, &spaces);
+                    self.windows[self.current_window].cursor = end;
+                }
+                Key::Char(c) => {
+                    let cursor = self.windows[self.current_window].cursor;
+                    self.buffer_mut().insert(c, &cursor);
                     self.move_cursor(Key::Right);
                 }
                 _ => (),
             },
+            Mode::Visual | Mode::VisualLine | Mode::VisualBlock => {
+                self.process_visual_keypress(pressed_key)?
+            }
+        }
+
+        // Like in vim, a macro stops at the first error.
+        if matches!(self.prompt_bar_message.kind, MessageType::Error) {
+            self.pending_keys.clear();
         }
 
         self.scroll();
@@ -163,20 +308,288 @@ impl Editor {
         Ok(())
     }
 
+    fn process_normal_keypress(&mut self, pressed_key: Key) -> Result<(), io::Error> {
+        let register = self.pending_register;
+        let (count, pressed_key) = self.read_count(pressed_key)?;
+        // Counts and registers are kept apart from the keys so `.` can override them.
+        self.current_change = RecordedChange {
+            keys: vec![pressed_key],
+            count,
+            register,
+        };
+
+        let modifies = match pressed_key {
+            Key::Char('i' | 'x' | 'X' | 'D' | 'C' | 'p' | 'P' | '.' | 'u') | Key::Ctrl('r') => true,
+            Key::Char(c) => {
+                Operator::from_char(c).is_some_and(|operator| operator != Operator::Yank)
+            }
+            _ => false,
+        };
+        if modifies && !self.check_modifiable() {
+            return Ok(());
+        }
+
+        match pressed_key {
+            Key::Char(':') => self.command_line()?,
+            Key::Char('i') => {
+                self.buffer_mut().begin_transaction();
+                self.mode = Mode::Insert
+            }
+            Key::Char('/') => self.search(SearchDirection::Forward)?,
+            Key::Char('?') => self.search(SearchDirection::Backward)?,
+            Key::Char('n') => self.search_next(false)?,
+            Key::Char('N') => self.search_next(true)?,
+            Key::Char('v') => self.start_visual(Mode::Visual),
+            Key::Char('V') => self.start_visual(Mode::VisualLine),
+            Key::Ctrl('v') => self.start_visual(Mode::VisualBlock),
+            Key::Char('u') => (0..count.unwrap_or(1)).for_each(|_| self.undo()),
+            Key::Ctrl('r') => (0..count.unwrap_or(1)).for_each(|_| self.redo()),
+            Key::Char('"') => self.select_register()?,
+            Key::Char('q') => self.toggle_recording()?,
+            Key::Char('@') => self.replay_macro(count)?,
+            Key::Ctrl('w') => self.window_command(count)?,
+            Key::Char('g') => match self.read_key()? {
+                Key::Char('t') => match count {
+                    Some(number) => self.go_to_tab(number),
+                    None => self.next_tab(1, true),
+                },
+                Key::Char('T') => self.next_tab(count.unwrap_or(1), false),
+                Key::Char('g') => self.move_by(Motion::FirstLine, count),
+                _ => (),
+            },
+            Key::Char('x') => self.apply_motion_operator(Operator::Delete, Motion::Right, count),
+            Key::Char('X') => self.apply_motion_operator(Operator::Delete, Motion::Left, count),
+            Key::Char('D') => self.apply_motion_operator(Operator::Delete, Motion::LineEnd, count),
+            Key::Char('C') => self.apply_motion_operator(Operator::Change, Motion::LineEnd, count),
+            Key::Char('Y') => self.apply_line_operator(Operator::Yank, count),
+            Key::Char('p') => self.put(false, count),
+            Key::Char('P') => self.put(true, count),
+            Key::Char('.') => self.repeat_change(count),
+            Key::Char(c) if Operator::from_char(c).is_some() => self.operator_pending(c, count)?,
+            _ => {
+                if let Some(motion) = self.read_motion(pressed_key)? {
+                    self.move_by(motion, count);
+                }
+            }
+        }
+
+        // Changes that enter insert mode are finished when it is left.
+        let is_change = match pressed_key {
+            Key::Char('x' | 'X' | 'D' | 'p' | 'P') => true,
+            Key::Char(c) => {
+                Operator::from_char(c).is_some_and(|operator| operator != Operator::Yank)
+            }
+            _ => false,
+        };
+        if is_change && self.mode == Mode::Normal {
+            self.finish_change();
+        }
+
+        Ok(())
+    }
+
+    /// Handles keys in normal mode when the buffer is shown in hex, where the cursor moves
+    /// by bytes.
+    fn process_hex_keypress(&mut self, pressed_key: Key) -> Result<(), io::Error> {
+        let (count, pressed_key) = self.read_count(pressed_key)?;
+        let amount = count.unwrap_or(1);
+        let offset = self.hex_offset();
+        let len = self.buffer().hex.as_ref().map_or(0, |hex| hex.len());
+
+        match pressed_key {
+            Key::Char(':') => self.command_line()?,
+            Key::Char('h') | Key::Left => self.set_hex_offset(offset.saturating_sub(amount)),
+            Key::Char('l') | Key::Right => self.set_hex_offset(offset.saturating_add(amount)),
+            Key::Char('k') | Key::Up => {
+                self.set_hex_offset(offset.saturating_sub(amount * BYTES_PER_ROW))
+            }
+            Key::Char('j') | Key::Down => {
+                // Going down from the last row but one stops on the last byte.
+                let target = offset.saturating_add(amount * BYTES_PER_ROW);
+                if target / BYTES_PER_ROW < len.div_ceil(BYTES_PER_ROW) {
+                    self.set_hex_offset(target);
+                }
+            }
+            Key::Char('0') | Key::Home => self.set_hex_offset(offset - offset % BYTES_PER_ROW),
+            Key::Char('$') | Key::End => {
+                self.set_hex_offset(offset - offset % BYTES_PER_ROW + BYTES_PER_ROW - 1)
+            }
+            Key::Char('G') => match count {
+                Some(row) => self.set_hex_offset((row - 1) * BYTES_PER_ROW),
+                None => self.set_hex_offset(len),
+            },
+            Key::Char('g') => match self.read_key()? {
+                Key::Char('g') => {
+                    self.set_hex_offset(count.map_or(0, |row| (row - 1) * BYTES_PER_ROW))
+                }
+                Key::Char('t') => match count {
+                    Some(number) => self.go_to_tab(number),
+                    None => self.next_tab(1, true),
+                },
+                Key::Char('T') => self.next_tab(amount, false),
+                _ => (),
+            },
+            Key::Char('/') => self.search_hex(SearchDirection::Forward)?,
+            Key::Char('?') => self.search_hex(SearchDirection::Backward)?,
+            Key::Char('n') => self.search_hex_next(false),
+            Key::Char('N') => self.search_hex_next(true),
+            Key::Char('r') => {
+                let high = self.read_key()?;
+                let low = self.read_key()?;
+                match (high, low) {
+                    (Key::Char(high), Key::Char(low))
+                        if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() =>
+                    {
+                        if self.check_hex_modifiable() {
+                            let byte =
+                                (high.to_digit(16).unwrap() * 16 + low.to_digit(16).unwrap()) as u8;
+                            if let Some(hex) = &mut self.buffer_mut().hex {
+                                hex.set(offset, byte);
+                            }
+                        }
+                    }
+                    _ => self.pending_keys.clear(),
+                }
+            }
+            Key::Char('i') if self.check_hex_modifiable() => self.mode = Mode::Insert,
+            Key::Char('u') => (0..amount).for_each(|_| self.undo_hex(false)),
+            Key::Ctrl('r') => (0..amount).for_each(|_| self.undo_hex(true)),
+            Key::Ctrl('w') => self.window_command(count)?,
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Overwrites the bytes under the cursor with the hex digits typed, one half of a byte
+    /// at a time.
+    fn process_hex_insert_keypress(&mut self, pressed_key: Key) {
+        let offset = self.hex_offset();
+
+        match pressed_key {
+            Key::Left => {
+                self.hex_nibble = false;
+                self.set_hex_offset(offset.saturating_sub(1));
+            }
+            Key::Right => {
+                self.hex_nibble = false;
+                self.set_hex_offset(offset + 1);
+            }
+            Key::Up => {
+                self.hex_nibble = false;
+                self.set_hex_offset(offset.saturating_sub(BYTES_PER_ROW));
+            }
+            Key::Down => {
+                self.hex_nibble = false;
+                self.set_hex_offset(offset + BYTES_PER_ROW);
+            }
+            Key::Char(c) if c.is_ascii_hexdigit() => {
+                let digit = c.to_digit(16).unwrap() as u8;
+                let high = self.hex_nibble;
+                let Some(hex) = &mut self.buffer_mut().hex else {
+                    return;
+                };
+                let Some(byte) = hex.get(offset) else {
+                    return;
+                };
+
+                if high {
+                    hex.set(offset, byte & 0xf0 | digit);
+                    self.hex_nibble = false;
+                    self.set_hex_offset(offset + 1);
+                } else {
+                    hex.set(offset, digit << 4 | byte & 0x0f);
+                    self.hex_nibble = true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn process_visual_keypress(&mut self, pressed_key: Key) -> Result<(), io::Error> {
+        let (count, pressed_key) = self.read_count(pressed_key)?;
+
+        match pressed_key {
+            Key::Char('"') => self.select_register()?,
+            Key::Char('v') => self.start_visual(Mode::Visual),
+            Key::Char('V') => self.start_visual(Mode::VisualLine),
+            Key::Ctrl('v') => self.start_visual(Mode::VisualBlock),
+            Key::Char('o') => {
+                let window = &mut self.windows[self.current_window];
+                std::mem::swap(&mut window.visual_anchor, &mut window.cursor);
+            }
+            Key::Char(prefix @ ('i' | 'a')) => {
+                if let Some(range) = self.read_text_object(prefix, count)? {
+                    self.select_range(range);
+                }
+            }
+            Key::Char(c) if Operator::from_visual_char(c).is_some() => {
+                if let (Some(operator), Some(range)) =
+                    (Operator::from_visual_char(c), self.selection_range())
+                {
+                    if operator != Operator::Yank && !self.check_modifiable() {
+                        return Ok(());
+                    }
+                    self.apply_operator(operator, range);
+                    if operator != Operator::Yank && self.mode == Mode::Normal {
+                        self.finish_change();
+                    }
+                }
+            }
+            _ => {
+                if let Some(motion) = self.read_motion(pressed_key)? {
+                    self.move_by(motion, count);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn process_command(&mut self, command: String) -> Result<(), io::Error> {
+        let substitution = Substitution::parse(
+            &command,
+            self.windows[self.current_window].cursor.y,
+            self.buffer().len(),
+            self.last_search.as_ref().map(|(query, _)| query.as_str()),
+        );
+        if let Some(substitution) = substitution {
+            match substitution {
+                Ok(substitution) => self.command_substitute(substitution)?,
+                Err(e) => self.prompt_bar_message = Message::new_error(e),
+            }
+            return Ok(());
+        }
+
         let command = command.split(' ').collect::<Vec<&str>>();
         match command[0] {
             "w" | "write" => {
                 self.command_save_file(&command)?;
                 Ok(())
             }
-            "q" | "quit" => {
+            "q" | "quit" | "q!" | "quit!" => {
                 self.command_quit(&command)?;
                 Ok(())
             }
+            "qa" | "qall" | "quitall" | "qa!" | "qall!" | "quitall!" => {
+                self.command_quit_all(&command)?;
+                Ok(())
+            }
             "wq" | "write-quit" | "x" => {
                 self.command_save_file(&command)?;
-                self.command_quit(&command)?;
+                if !self.buffer().is_dirty() {
+                    self.command_quit(&command)?;
+                }
+                Ok(())
+            }
+            "wa" | "wall" => {
+                self.command_save_all(&command)?;
+                Ok(())
+            }
+            "wqa" | "wqall" | "xa" | "xall" => {
+                if self.command_save_all(&command)? {
+                    self.command_quit_all(&command)?;
+                }
                 Ok(())
             }
             "n" | "new" => {
@@ -195,10 +608,123 @@ impl Editor {
                 self.command_buffer_previous(&command)?;
                 Ok(())
             }
-            "bc" | "buffer-close" => {
+            "b" | "buffer" => {
+                self.command_buffer(&command)?;
+                Ok(())
+            }
+            "ls" | "buffers" | "files" => {
+                self.command_list_buffers(&command)?;
+                Ok(())
+            }
+            "bc" | "buffer-close" | "bd" | "bdelete" | "bc!" | "buffer-close!" | "bd!"
+            | "bdelete!" => {
                 self.command_buffer_close(&command)?;
                 Ok(())
             }
+            "sp" | "split" => {
+                self.command_split(&command, SplitDirection::Horizontal)?;
+                Ok(())
+            }
+            "vs" | "vsplit" => {
+                self.command_split(&command, SplitDirection::Vertical)?;
+                Ok(())
+            }
+            "clo" | "close" | "clo!" | "close!" => {
+                self.command_close(&command)?;
+                Ok(())
+            }
+            "on" | "only" | "on!" | "only!" => {
+                self.command_only(&command)?;
+                Ok(())
+            }
+            "res" | "resize" => {
+                self.command_resize(&command, SplitDirection::Horizontal)?;
+                Ok(())
+            }
+            "vert" | "vertical" if matches!(command.get(1), Some(&"res" | &"resize")) => {
+                self.command_resize(&command[1..], SplitDirection::Vertical)?;
+                Ok(())
+            }
+            "tabnew" | "tabe" | "tabedit" => {
+                self.command_tab_new(&command)?;
+                Ok(())
+            }
+            "tabn" | "tabnext" => {
+                self.command_tab_next(&command)?;
+                Ok(())
+            }
+            "tabp" | "tabprevious" | "tabN" | "tabNext" => {
+                self.command_tab_previous(&command)?;
+                Ok(())
+            }
+            "tabc" | "tabclose" | "tabc!" | "tabclose!" => {
+                self.command_tab_close(&command)?;
+                Ok(())
+            }
+            "se" | "set" | "setl" | "setlocal" => {
+                self.command_set(&command)?;
+                Ok(())
+            }
+            "colo" | "colorscheme" => {
+                self.command_colorscheme(&command)?;
+                Ok(())
+            }
+            "map" => {
+                self.command_map(&command, &[MapMode::Normal, MapMode::Visual], true)?;
+                Ok(())
+            }
+            "no" | "noremap" => {
+                self.command_map(&command, &[MapMode::Normal, MapMode::Visual], false)?;
+                Ok(())
+            }
+            "nm" | "nmap" => {
+                self.command_map(&command, &[MapMode::Normal], true)?;
+                Ok(())
+            }
+            "nn" | "nnoremap" => {
+                self.command_map(&command, &[MapMode::Normal], false)?;
+                Ok(())
+            }
+            "vm" | "vmap" => {
+                self.command_map(&command, &[MapMode::Visual], true)?;
+                Ok(())
+            }
+            "vn" | "vnoremap" => {
+                self.command_map(&command, &[MapMode::Visual], false)?;
+                Ok(())
+            }
+            "im" | "imap" => {
+                self.command_map(&command, &[MapMode::Insert], true)?;
+                Ok(())
+            }
+            "ino" | "inoremap" => {
+                self.command_map(&command, &[MapMode::Insert], false)?;
+                Ok(())
+            }
+            "unm" | "unmap" => {
+                self.command_unmap(&command, &[MapMode::Normal, MapMode::Visual]);
+                Ok(())
+            }
+            "nun" | "nunmap" => {
+                self.command_unmap(&command, &[MapMode::Normal]);
+                Ok(())
+            }
+            "vu" | "vunmap" => {
+                self.command_unmap(&command, &[MapMode::Visual]);
+                Ok(())
+            }
+            "iu" | "iunmap" => {
+                self.command_unmap(&command, &[MapMode::Insert]);
+                Ok(())
+            }
+            "hex" => {
+                self.command_hex(&command)?;
+                Ok(())
+            }
+            "noh" | "nohlsearch" => {
+                self.buffer_mut().set_highlighted_word(None);
+                Ok(())
+            }
             "🍷🗿" => {
                 self.prompt_bar_message = Message::new_normal(
                     "Thank you! What a nice gentleman you are 🍷🗿".to_string(),
@@ -214,114 +740,459 @@ impl Editor {
         }
     }
 
-    fn read_key() -> Result<Key, io::Error> {
-        loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
+    /// Reads a command on the command bar and runs it.
+    fn command_line(&mut self) -> Result<(), io::Error> {
+        // Repeated tabs cycle through the completions of what was typed.
+        let mut completions = Vec::new();
+        let mut completion = 0;
+        let prompt = self.prompt(":", |editor, key, command| {
+            if key != Key::Char('\t') {
+                completions.clear();
+                return;
+            }
+
+            if completions.is_empty() {
+                completions = editor.complete_command(command);
+                completion = 0;
+            } else {
+                completion = (completion + 1) % completions.len();
+            }
+            if let Some(completed) = completions.get(completion) {
+                *command = completed.clone();
             }
+        })?;
+        if let Some(command) = prompt {
+            self.process_command(command)?;
         }
+
+        Ok(())
     }
 
-    fn prompt(&mut self, prompt: &str) -> Result<Option<String>, io::Error> {
-        let mut result = String::new();
+    /// Returns the next key to process: the next one replayed from a macro if any, or else
+    /// one read from the terminal.
+    fn read_key(&mut self) -> Result<Key, io::Error> {
+        let key = match self.pending_keys.pop_front() {
+            Some((key, _)) => key,
+            None => loop {
+                if let Some(key) = self.receive_key(None)? {
+                    break key;
+                }
+            },
+        };
+
+        self.current_change.keys.push(key);
+        Ok(key)
+    }
+
+    /// Returns the next key to process like [`Self::read_key`], applying the mappings of the
+    /// current mode. When the keys read so far start a longer mapping, the next key is waited
+    /// for until `timeoutlen` passes. Returns `None`, dropping the pending keys, when
+    /// recursive mappings are replaced more than [`MAX_MAP_DEPTH`] times.
+    fn read_mapped_key(&mut self) -> Result<Option<Key>, io::Error> {
+        let mode = self.mode.map_mode();
+        let timeout = Duration::from_millis(self.options.timeoutlen as u64);
+
+        for _ in 0..=MAX_MAP_DEPTH {
+            let mut keys = match self.pending_keys.pop_front() {
+                Some((key, true)) => vec![key],
+                Some((key, false)) => {
+                    self.pending_keys.push_front((key, false));
+                    return self.read_key().map(Some);
+                }
+                None => Vec::new(),
+            };
+            while keys.is_empty() || self.keymaps.is_prefix(mode, &keys) {
+                let key = match self.pending_keys.pop_front() {
+                    Some((key, true)) => Some(key),
+                    Some(pending) => {
+                        self.pending_keys.push_front(pending);
+                        None
+                    }
+                    None => self.receive_key((!keys.is_empty()).then_some(timeout))?,
+                };
+                match key {
+                    Some(key) => keys.push(key),
+                    None => break,
+                }
+            }
+
+            // The longest mapping the keys start with is used, and the keys after it are
+            // looked at again. Without one, the first key is used as it is. The keys of
+            // recursive mappings are mapped again, past those `unmapped_len` leaves alone.
+            let (length, replacement, unmapped) = (1..=keys.len())
+                .rev()
+                .find_map(|length| Some((length, self.keymaps.get(mode, &keys[..length])?)))
+                .map_or((1, vec![keys[0]], 1), |(length, mapping)| {
+                    (length, mapping.rhs.clone(), mapping.unmapped_len())
+                });
+            for key in keys[length..].iter().rev() {
+                self.pending_keys.push_front((*key, true));
+            }
+            for (index, key) in replacement.into_iter().enumerate().rev() {
+                self.pending_keys.push_front((key, index >= unmapped));
+            }
+        }
+
+        self.pending_keys.clear();
+        Ok(None)
+    }
+
+    /// Waits for a key to be typed, for at most `wait` when given, doing the work left for
+    /// when no key is typed meanwhile. The key is recorded when a macro is being recorded.
+    fn receive_key(&mut self, wait: Option<Duration>) -> Result<Option<Key>, io::Error> {
+        let deadline = wait.map(|wait| Instant::now() + wait);
 
         loop {
-            self.prompt_bar_message = Message::new_normal(format!("{}{}", prompt, result));
+            // Huge files are scanned for lines between keys until they are scanned whole.
+            let indexing = self
+                .buffers
+                .iter()
+                .find(|buffer| buffer.indexing_progress().is_some());
+            let mut timeout = if indexing.is_some() {
+                Duration::ZERO
+            } else {
+                SWAP_IDLE_TIME
+            };
+            if let Some(deadline) = deadline {
+                timeout = timeout.min(deadline.saturating_duration_since(Instant::now()));
+            }
 
-            self.refresh_screen()?;
+            match self.input.recv_timeout(timeout) {
+                Ok(key) => {
+                    let key = key?;
+                    if let Some((_, keys)) = &mut self.recording {
+                        keys.push(key);
+                    }
 
-            match Self::read_key()? {
-                Key::Char('\n') => break,
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
+                    self.keys_since_swap += 1;
+                    if self.keys_since_swap >= SWAP_KEY_COUNT {
+                        self.write_swap_files();
                     }
+                    return Ok(Some(key));
                 }
-                Key::Backspace => {
-                    result.pop();
+                Err(RecvTimeoutError::Timeout)
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) =>
+                {
+                    return Ok(None)
                 }
-                Key::Esc => {
-                    result.truncate(0);
-                    break;
+                Err(RecvTimeoutError::Timeout) if indexing.is_some() => {
+                    if let Some(buffer) = indexing {
+                        buffer.index_chunk();
+                    }
+                    self.refresh_screen()?;
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.keys_since_swap > 0 {
+                        self.write_swap_files();
+                        self.refresh_screen()?;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "The terminal was closed",
+                    ))
                 }
-                _ => (),
             }
         }
-
-        self.prompt_bar_message = Message::default();
-
-        if result.is_empty() {
-            return Ok(None);
-        }
-
-        Ok(Some(result))
     }
 
-    fn scroll(&mut self) {
-        if self.cursor_positions[self.current_buffer].y < self.scroll_offsets[self.current_buffer].y
-        {
-            self.scroll_offsets[self.current_buffer].y =
-                self.cursor_positions[self.current_buffer].y;
-        } else if self.cursor_positions[self.current_buffer].y
-            >= self.scroll_offsets[self.current_buffer]
-                .y
-                .saturating_add(self.terminal_size.1 as usize)
-        {
-            self.scroll_offsets[self.current_buffer].y = self.cursor_positions[self.current_buffer]
-                .y
-                .saturating_sub(self.terminal_size.1 as usize)
-                .saturating_add(1);
-        }
+    /// Reads the count typed before a command, starting with `pressed_key`. Returns the
+    /// count, if any, along with the first key that is not part of it.
+    fn read_count(&mut self, mut pressed_key: Key) -> Result<(Option<usize>, Key), io::Error> {
+        let mut count: Option<usize> = None;
 
-        if self.cursor_positions[self.current_buffer].x < self.scroll_offsets[self.current_buffer].x
-        {
-            self.scroll_offsets[self.current_buffer].x =
-                self.cursor_positions[self.current_buffer].x;
-        } else if self.cursor_positions[self.current_buffer].x
-            >= self.scroll_offsets[self.current_buffer]
-                .x
-                .saturating_add(self.terminal_size.0 as usize)
-        {
-            self.scroll_offsets[self.current_buffer].x = self.cursor_positions[self.current_buffer]
-                .x
-                .saturating_sub(self.terminal_size.0 as usize)
-                .saturating_add(1);
+        loop {
+            match pressed_key {
+                Key::Char(digit @ '0'..='9') if digit != '0' || count.is_some() => {
+                    let value = digit.to_digit(10).unwrap_or_default() as usize;
+                    count = Some(
+                        count
+                            .unwrap_or_default()
+                            .saturating_mul(10)
+                            .saturating_add(value),
+                    );
+                }
+                _ => return Ok((count, pressed_key)),
+            }
+
+            pressed_key = self.read_key()?;
         }
     }
 
-    fn move_cursor(&mut self, pressed_key: Key) {
-        let current_pos = &mut self.cursor_positions[self.current_buffer];
-        let x = &mut current_pos.x;
-        let y = &mut current_pos.y;
+    /// Reads the motion started by `pressed_key`, reading the rest of it for `gg`, `f`,
+    /// `F`, `t` and `T`. Returns `None` when the keys don't form a motion.
+    fn read_motion(&mut self, pressed_key: Key) -> Result<Option<Motion>, io::Error> {
+        let motion = match pressed_key {
+            Key::Char('h') | Key::Left => Motion::Left,
+            Key::Char('l') | Key::Right => Motion::Right,
+            Key::Char('k') | Key::Up => Motion::Up,
+            Key::Char('j') | Key::Down => Motion::Down,
+            Key::Char('w') => Motion::WordForward { big: false },
+            Key::Char('W') => Motion::WordForward { big: true },
+            Key::Char('b') => Motion::WordBackward { big: false },
+            Key::Char('B') => Motion::WordBackward { big: true },
+            Key::Char('e') => Motion::WordEnd { big: false },
+            Key::Char('E') => Motion::WordEnd { big: true },
+            Key::Char('0') | Key::Home => Motion::LineStart,
+            Key::Char('^') => Motion::FirstNonBlank,
+            Key::Char('$') | Key::End => Motion::LineEnd,
+            // The last line of a huge file is only known once it is scanned whole.
+            Key::Char('G') if !self.finish_indexing()? => return Ok(None),
+            Key::Char('G') => Motion::LastLine,
+            Key::Char('g') => match self.read_key()? {
+                Key::Char('g') => Motion::FirstLine,
+                _ => return Ok(None),
+            },
+            Key::Char(c @ ('f' | 'F' | 't' | 'T')) => {
+                let Key::Char(target) = self.read_key()? else {
+                    return Ok(None);
+                };
+
+                let find = FindChar {
+                    target,
+                    forward: c.is_lowercase(),
+                    till: matches!(c, 't' | 'T'),
+                };
+                self.last_find = Some(find);
+                Motion::Find(find)
+            }
+            Key::Char(';') | Key::Char(',') => {
+                let Some(find) = self.last_find else {
+                    return Ok(None);
+                };
+
+                if pressed_key == Key::Char(',') {
+                    Motion::RepeatFind(find.reversed())
+                } else {
+                    Motion::RepeatFind(find)
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(motion))
+    }
+
+    /// Reads the text object following `prefix`, which is `i` or `a`, and returns the text
+    /// `count` of them cover around the cursor.
+    fn read_text_object(
+        &mut self,
+        prefix: char,
+        count: Option<usize>,
+    ) -> Result<Option<TextRange>, io::Error> {
+        let Key::Char(c) = self.read_key()? else {
+            return Ok(None);
+        };
+
+        let buffer = self.buffer();
+        let options = HighlightingOptions::from(buffer.file_type.clone());
+        let cursor = self.windows[self.current_window].cursor;
+
+        Ok(TextObject::from_char(c, &options)
+            .and_then(|object| object.range(buffer, cursor, count.unwrap_or(1), prefix == 'i')))
+    }
+
+    /// Reads what follows operator `c`: a count, then either a motion or `c` again to
+    /// operate on whole rows, like `dd` or `>>`.
+    fn operator_pending(&mut self, c: char, count: Option<usize>) -> Result<(), io::Error> {
+        let Some(operator) = Operator::from_char(c) else {
+            return Ok(());
+        };
+
+        let pressed_key = self.read_key()?;
+        let (motion_count, pressed_key) = self.read_count(pressed_key)?;
+        // Both counts multiply, so `2d3w` deletes six words.
+        let count = match (count, motion_count) {
+            (None, None) => None,
+            (count, motion_count) => Some(count.unwrap_or(1) * motion_count.unwrap_or(1)),
+        };
+        self.current_change.keys.truncate(1);
+        self.current_change.keys.push(pressed_key);
+        self.current_change.count = count;
+
+        if pressed_key == Key::Char(c) {
+            self.apply_line_operator(operator, count);
+        } else if let Key::Char(prefix @ ('i' | 'a')) = pressed_key {
+            match self.read_text_object(prefix, count)? {
+                Some(range) => self.apply_operator(operator, range),
+                None => self.pending_register = None,
+            }
+        } else if let Some(motion) = self.read_motion(pressed_key)? {
+            self.apply_motion_operator(operator, motion, count);
+        } else {
+            self.pending_register = None;
+        }
+
+        Ok(())
+    }
+
+    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, io::Error>
+    where
+        C: FnMut(&mut Self, Key, &mut String),
+    {
+        let mut result = String::new();
+
+        loop {
+            self.prompt_bar_message = Message::new_normal(format!("{}{}", prompt, result));
+
+            self.refresh_screen()?;
+
+            let key = self.read_key()?;
+            match key {
+                Key::Char('\n') => break,
+                Key::Char(c) if !c.is_control() => result.push(c),
+                Key::Backspace => {
+                    result.pop();
+                }
+                Key::Esc => {
+                    result.truncate(0);
+                    break;
+                }
+                _ => (),
+            }
+
+            callback(self, key, &mut result);
+        }
+
+        self.prompt_bar_message = Message::default();
+
+        if result.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(result))
+    }
+
+    fn scroll(&mut self) {
+        let rect = self.window_rect(self.current_window);
+        // The last row of a window is its status line.
+        let height = rect.height.saturating_sub(1);
+        let width = rect.width;
+        let column = self.cursor_column();
+        let window = &mut self.windows[self.current_window];
+        // Small windows can't keep as many rows around the cursor.
+        let scrolloff = window.options.scrolloff.min(height.saturating_sub(1) / 2);
+
+        if window.cursor.y < window.scroll.y + scrolloff {
+            window.scroll.y = window.cursor.y.saturating_sub(scrolloff);
+        } else if window.cursor.y + scrolloff >= window.scroll.y.saturating_add(height) {
+            window.scroll.y = (window.cursor.y + scrolloff + 1).saturating_sub(height);
+        }
+
+        if column < window.scroll.x {
+            window.scroll.x = column;
+        } else if column >= window.scroll.x.saturating_add(width) {
+            window.scroll.x = column.saturating_sub(width).saturating_add(1);
+        }
+    }
+
+    /// Returns the area shared by the windows, which is the whole screen but the command bar
+    /// and the tabline.
+    fn window_area(&self) -> Rect {
+        let tabline = self.tabline_height();
+
+        Rect {
+            x: 0,
+            y: tabline,
+            width: self.terminal_size.0 as usize,
+            height: self.terminal_size.1 as usize + 1 - tabline,
+        }
+    }
 
-        let height = self.buffers[self.current_buffer].len();
-        let mut width = if let Some(row) = self.buffers[self.current_buffer].row(*y) {
+    /// The tabline is only shown when there are several tabs.
+    fn tabline_height(&self) -> usize {
+        usize::from(self.tabs.len() > 1)
+    }
+
+    fn window_rect(&self, window: usize) -> Rect {
+        self.layout
+            .arrange(self.window_area())
+            .into_iter()
+            .find(|(index, _)| *index == window)
+            .map(|(_, rect)| rect)
+            .unwrap_or_default()
+    }
+
+    /// Returns where the cursor of the current window is on the screen.
+    fn cursor_screen_position(&self) -> Position {
+        let rect = self.window_rect(self.current_window);
+        let window = &self.windows[self.current_window];
+
+        Position {
+            x: rect.x + self.cursor_column().saturating_sub(window.scroll.x),
+            y: rect.y + window.cursor.y.saturating_sub(window.scroll.y),
+        }
+    }
+
+    fn current_buffer(&self) -> usize {
+        self.windows[self.current_window].buffer
+    }
+
+    fn buffer(&self) -> &Buffer {
+        &self.buffers[self.current_buffer()]
+    }
+
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        let index = self.current_buffer();
+        &mut self.buffers[index]
+    }
+
+    /// Returns the display column of the cursor, which differs from its grapheme index
+    /// when the row contains tabs or wide characters.
+    fn cursor_column(&self) -> usize {
+        let cursor = &self.windows[self.current_window].cursor;
+        if self.buffer().hex.is_some() {
+            return hex::column(cursor.x) + usize::from(self.hex_nibble);
+        }
+
+        match self.buffer().row(cursor.y) {
+            Some(row) => row.width_to(cursor.x),
+            None => 0,
+        }
+    }
+
+    fn move_cursor(&mut self, pressed_key: Key) {
+        let column = self.cursor_column();
+        let window = &mut self.windows[self.current_window];
+        let buffer = &self.buffers[window.buffer];
+        let x = &mut window.cursor.x;
+        let y = &mut window.cursor.y;
+
+        let height = buffer.len();
+        let mut width = if let Some(row) = buffer.row(*y) {
             row.len()
         } else {
             0
         };
 
         match pressed_key {
-            Key::Up | Key::Char('k') => *y = y.saturating_sub(1),
-            Key::Down | Key::Char('j') => {
-                if *y < height {
-                    *y = y.saturating_add(1)
+            Key::Up | Key::Down => {
+                if pressed_key == Key::Up {
+                    *y = y.saturating_sub(1);
+                } else if *y < height {
+                    *y = y.saturating_add(1);
+                }
+
+                // Keep the cursor on the same display column across wide characters.
+                if let Some(row) = buffer.row(*y) {
+                    *x = row.grapheme_at_column(column);
                 }
             }
-            Key::Left | Key::Char('h') => {
+            Key::Left => {
                 if *x > 0 {
                     *x -= 1;
                 } else if *y > 0 {
                     *y -= 1;
 
-                    if let Some(row) = self.buffers[self.current_buffer].row(*y) {
+                    if let Some(row) = buffer.row(*y) {
                         *x = row.len();
                     } else {
                         *x = 0;
                     }
                 }
             }
-            Key::Right | Key::Char('l') => {
+            Key::Right => {
                 if *x < width {
                     *x += 1;
                 } else if *y < height {
@@ -332,7 +1203,7 @@ impl Editor {
             _ => unreachable!(),
         };
 
-        width = if let Some(row) = self.buffers[self.current_buffer].row(*y) {
+        width = if let Some(row) = buffer.row(*y) {
             row.len()
         } else {
             0
@@ -343,201 +1214,2408 @@ impl Editor {
         }
     }
 
-    fn draw_row(&self, row: &Row) {
-        println!(
-            "{}\r",
-            row.render(
-                self.scroll_offsets[self.current_buffer].x,
-                self.terminal_size.0 as usize + self.scroll_offsets[self.current_buffer].x
-            )
-        );
+    fn move_by(&mut self, motion: Motion, count: Option<usize>) {
+        let cursor = self.windows[self.current_window].cursor;
+        match motion.apply(self.buffer(), cursor, count) {
+            Some(position) => self.set_cursor_position(position),
+            // A motion that can't move stops the macro being replayed, if any.
+            None => self.pending_keys.clear(),
+        }
     }
 
-    fn draw_rows(&self) {
-        for terminal_row in 0..self.terminal_size.1 {
-            termutils::clear_line();
-            let buffer = &self.buffers[self.current_buffer];
+    /// Applies `operator` to `count` rows starting at the cursor.
+    fn apply_line_operator(&mut self, operator: Operator, count: Option<usize>) {
+        let y = self.windows[self.current_window].cursor.y;
+        let len = self.buffer().len();
 
-            if let Some(row) =
-                buffer.row(terminal_row as usize + self.scroll_offsets[self.current_buffer].y)
-            {
-                self.draw_row(row);
-            } else if buffer.is_empty() && terminal_row == self.terminal_size.1 / 3 {
-                self.draw_welcome_message();
-            } else {
-                println!("~\r");
+        if y >= len {
+            if operator == Operator::Change {
+                self.buffer_mut().begin_transaction();
+                self.mode = Mode::Insert;
             }
+            self.pending_register = None;
+            return;
         }
+
+        let last = (y + count.unwrap_or(1) - 1).min(len - 1);
+        self.apply_operator(operator, TextRange::Lines { first: y, last });
     }
 
-    fn draw_status_bar(&self) {
-        let width = self.terminal_size.0 as usize;
-        let mode = match self.mode {
-            Mode::Normal => "NORMAL",
-            Mode::Insert => "INSERT",
-        };
-        let file_name = if let Some(name) = self.buffers[self.current_buffer].save_location.clone()
-        {
-            name
-        } else {
-            "[scratch]".to_string()
+    /// Applies `operator` to the text between the cursor and where `motion` lands.
+    fn apply_motion_operator(&mut self, operator: Operator, motion: Motion, count: Option<usize>) {
+        let cursor = self.windows[self.current_window].cursor;
+        let buffer = self.buffer();
+
+        // `cw` behaves like `ce` when the cursor is on a word.
+        let change_word = match motion {
+            Motion::WordForward { big } if operator == Operator::Change => {
+                motion::change_word_end(buffer, cursor, count, big)
+            }
+            _ => None,
         };
-        let is_dirty = if self.buffers[self.current_buffer].is_dirty() {
-            "[+]"
-        } else {
-            ""
+        let (mut target, kind) = match change_word {
+            Some(target) => (target, MotionKind::Inclusive),
+            None => match motion.apply(buffer, cursor, count) {
+                Some(target) => (target, motion.kind()),
+                None => {
+                    self.pending_register = None;
+                    return;
+                }
+            },
         };
-        let file_type = format!("{}", self.buffers[self.current_buffer].file_type);
-        let current_pos = format!(
-            "{}:{}",
-            self.cursor_positions[self.current_buffer].y + 1,
-            self.cursor_positions[self.current_buffer].x + 1
-        );
 
-        let mut status = String::new();
+        // When the last word moved over by `w` ends its row, the operator stops there
+        // instead of taking the line break and the indent of the next row with it.
+        if matches!(motion, Motion::WordForward { .. })
+            && change_word.is_none()
+            && target.y > cursor.y
+            && target.x <= motion::first_non_blank(buffer, target.y)
+        {
+            let y = target.y - 1;
+            let end = Position {
+                x: buffer.row(y).map(|row| row.len()).unwrap_or_default(),
+                y,
+            };
+            if end > cursor {
+                target = end;
+            }
+        }
 
-        let left_side = format!("{}   {} {}", mode, file_name, is_dirty);
-        let right_side = format!("{}   {}", file_type, current_pos);
+        let range = match kind {
+            MotionKind::Linewise => TextRange::Lines {
+                first: cursor.y.min(target.y),
+                last: cursor.y.max(target.y),
+            },
+            MotionKind::Exclusive | MotionKind::Inclusive => {
+                let start = cursor.min(target);
+                let mut end = cursor.max(target);
+                if kind == MotionKind::Inclusive {
+                    let row_len = buffer.row(end.y).map(|row| row.len()).unwrap_or_default();
+                    end.x = (end.x + 1).min(row_len);
+                }
+                TextRange::Characters { start, end }
+            }
+        };
 
-        status.push_str(&left_side);
+        self.apply_operator(operator, range);
+    }
 
-        // separator
-        let len = left_side.len() + right_side.len() + 2; // +1 is the space at the start of the status bar
-        status.push_str(&" ".repeat(width - len));
+    fn apply_operator(&mut self, operator: Operator, range: TextRange) {
+        match operator {
+            Operator::Delete => self.delete_text(range, false),
+            Operator::Change => self.delete_text(range, true),
+            Operator::Yank => self.yank_text(range),
+            Operator::Indent => self.indent_text(range, true),
+            Operator::Dedent => self.indent_text(range, false),
+            Operator::ToggleCase => self.change_case(range, toggle_case),
+            Operator::Lowercase => self.change_case(range, str::to_lowercase),
+            Operator::Uppercase => self.change_case(range, str::to_uppercase),
+        }
 
-        status.push_str(&right_side);
+        self.pending_register = None;
+        if self.mode != Mode::Insert {
+            self.mode = Mode::Normal;
+        }
+    }
 
-        // spaces on the sides
-        status.insert(0, ' ');
-        status.insert(status.len(), ' ');
+    fn search(&mut self, direction: SearchDirection) -> Result<(), io::Error> {
+        if !self.finish_indexing()? {
+            return Ok(());
+        }
 
-        termutils::set_bg_color(STATUS_BAR_BG_COLOR);
-        termutils::set_fg_color(STATUS_BAR_FG_COLOR);
+        let prompt = match direction {
+            SearchDirection::Forward => "/",
+            SearchDirection::Backward => "?",
+        };
+        let start_position = self.windows[self.current_window].cursor;
+        let start_offset = self.windows[self.current_window].scroll;
+        let previous_word = self.buffer().highlighted_word().map(str::to_string);
+
+        let query = self.prompt(prompt, |editor, _, query| {
+            // Huge files are only searched once the query is entered.
+            if !editor.buffer().is_huge() {
+                editor.windows[editor.current_window].cursor = editor
+                    .buffer()
+                    .find(query, &start_position, direction)
+                    .unwrap_or(start_position);
+            }
 
-        println!("{}\r", status);
+            let word = (!query.is_empty()).then(|| query.to_string());
+            editor.buffer_mut().set_highlighted_word(word);
+            editor.scroll();
+        })?;
 
-        termutils::reset_bg_color();
-        termutils::reset_fg_color();
-    }
+        match query {
+            Some(query) => {
+                self.windows[self.current_window].cursor = start_position;
+                self.last_search = Some((query, direction));
+                self.search_next(false)?;
+            }
+            None => {
+                self.windows[self.current_window].cursor = start_position;
+                self.windows[self.current_window].scroll = start_offset;
+                self.buffer_mut().set_highlighted_word(previous_word);
+            }
+        }
 
-    fn draw_command_bar(&self) {
-        termutils::clear_line();
-        let mut text = self.prompt_bar_message.clone();
-        text.message.truncate(self.terminal_size.0 as usize);
-        println!("{}", text);
+        Ok(())
     }
 
-    fn draw_welcome_message(&self) {
-        let mut welcome_message = format!("Edicode -- version {}", VERSION);
-        let width = self.terminal_size.0 as usize;
-        let len = welcome_message.len();
-        let padding = width.saturating_sub(len) / 2;
-        let spaces = " ".repeat(padding.saturating_sub(1));
-        welcome_message = format!("~{}{}", spaces, welcome_message);
-        welcome_message.truncate(width);
-        println!("{}\r", welcome_message);
-    }
+    fn search_next(&mut self, reverse: bool) -> Result<(), io::Error> {
+        let Some((query, direction)) = self.last_search.clone() else {
+            self.prompt_bar_message = Message::new_error("No previous search pattern".to_string());
+            return Ok(());
+        };
+        if !self.finish_indexing()? {
+            return Ok(());
+        }
 
-    fn command_save_file(&mut self, command: &[&str]) -> Result<(), io::Error> {
-        let buffer = &mut self.buffers[self.current_buffer];
-        let mut save_location = buffer.save_location.clone().unwrap_or_default();
-        if let Some(new_save_location) = command.get(1).copied() {
-            save_location = new_save_location.to_string();
-            buffer.save_location = Some(save_location.clone());
+        let direction = if reverse {
+            direction.reversed()
+        } else {
+            direction
+        };
+
+        let current_position = self.windows[self.current_window].cursor;
+        let buffer = self.buffer_mut();
+        buffer.set_highlighted_word(Some(query.clone()));
+
+        match buffer.find(&query, &current_position, direction) {
+            Some(position) => {
+                let wrapped = match direction {
+                    SearchDirection::Forward => position <= current_position,
+                    SearchDirection::Backward => position >= current_position,
+                };
+                if wrapped {
+                    self.prompt_bar_message = Message::new_warning(match direction {
+                        SearchDirection::Forward => {
+                            "search hit BOTTOM, continuing at TOP".to_string()
+                        }
+                        SearchDirection::Backward => {
+                            "search hit TOP, continuing at BOTTOM".to_string()
+                        }
+                    });
+                }
+                self.windows[self.current_window].cursor = position;
+            }
+            None => {
+                self.prompt_bar_message =
+                    Message::new_error(format!("Pattern not found: {}", query))
+            }
         }
 
-        if save_location.is_empty() {
+        Ok(())
+    }
+
+    /// Scans the current buffer for lines until it is scanned whole when it is a huge file
+    /// still being scanned, showing how far it got. Returns `false` when stopped with
+    /// `<C-c>`, the other keys typed meanwhile being processed afterwards.
+    fn finish_indexing(&mut self) -> Result<bool, io::Error> {
+        while let Some(progress) = self.buffer().indexing_progress() {
             self.prompt_bar_message =
-                Message::new_error("Can't save with no path set!".to_string());
-            return Ok(());
-        }
+                Message::new_normal(format!("Scanning for lines: {}%, <C-c> to stop", progress));
+            self.refresh_screen()?;
+            self.buffer().index_chunk();
 
-        buffer.save(&save_location)?;
-        self.prompt_bar_message = Message::new_normal(format!("\"{}\" written", save_location));
+            while let Ok(key) = self.input.try_recv() {
+                let key = key?;
+                if key == Key::Ctrl('c') {
+                    self.prompt_bar_message = Message::new_error("Interrupted".to_string());
+                    return Ok(false);
+                }
+                if let Some((_, keys)) = &mut self.recording {
+                    keys.push(key);
+                }
+                self.pending_keys.push_back((key, true));
+            }
+        }
 
-        Ok(())
+        self.prompt_bar_message = Message::default();
+        Ok(true)
     }
 
-    fn command_quit(&mut self, _command: &[&str]) -> Result<(), io::Error> {
-        self.should_quit = true;
-        Ok(())
+    /// Keeps the keys of the change that just ended so `.` can repeat it.
+    fn finish_change(&mut self) {
+        self.last_change = Some(std::mem::take(&mut self.current_change));
     }
 
-    fn command_new_buffer(&mut self, _command: &[&str]) -> Result<(), io::Error> {
-        self.add_buffer(Buffer::default());
-        Ok(())
+    /// Replays the last change, with `count` replacing its count when given.
+    fn repeat_change(&mut self, count: Option<usize>) {
+        let Some(change) = self.last_change.clone() else {
+            return;
+        };
+
+        let count = count.or(change.count);
+        self.pending_register = change.register;
+        let digits = count.map(|count| count.to_string()).unwrap_or_default();
+        // The keys were mapped already when the change was made.
+        for key in digits.chars().map(Key::Char).chain(change.keys).rev() {
+            self.pending_keys.push_front((key, false));
+        }
     }
 
-    fn command_open_file(&mut self, command: &[&str]) -> Result<(), io::Error> {
-        if let Some(file_location) = command.get(1) {
-            let buffer = match Buffer::open(file_location) {
-                Ok(buffer) => buffer,
-                Err(e) => match e.kind() {
-                    io::ErrorKind::NotFound => {
-                        self.prompt_bar_message = Message::new_error(e.to_string());
-                        return Ok(());
-                    }
-                    _ => return Result::Err(e),
-                },
-            };
+    /// Starts recording the keys typed into the register named by the next key, or stops
+    /// the recording in progress.
+    fn toggle_recording(&mut self) -> Result<(), io::Error> {
+        if let Some((name, mut recorded)) = self.recording.take() {
+            // The `q` that stopped the recording is not part of the macro.
+            recorded.pop();
+            self.registers.record(name, keys::to_notation(&recorded));
+            return Ok(());
+        }
 
-            self.add_buffer(buffer);
-        } else {
-            self.prompt_bar_message = Message::new_error("File path not given!".to_string());
+        match self.read_key()? {
+            Key::Char(name) if name.is_ascii_alphabetic() => {
+                self.recording = Some((name, Vec::new()));
+            }
+            _ => (),
         }
 
         Ok(())
     }
 
-    fn command_buffer_next(&mut self, _command: &[&str]) -> Result<(), io::Error> {
-        if self.current_buffer + 1 == self.buffers.len() {
-            self.current_buffer = 0;
+    /// Replays the macro in the register named by the next key `count` times, or the last
+    /// replayed one for `@@`.
+    fn replay_macro(&mut self, count: Option<usize>) -> Result<(), io::Error> {
+        let name = match self.read_key()? {
+            Key::Char('@') => match self.last_macro {
+                Some(name) => name,
+                None => {
+                    self.prompt_bar_message =
+                        Message::new_error("No previously used register".to_string());
+                    return Ok(());
+                }
+            },
+            Key::Char(name) if Registers::is_valid_name(name) => name,
+            _ => return Ok(()),
+        };
+
+        let Some(register) = self.read_register(Some(name)) else {
+            self.prompt_bar_message = Message::new_error(format!("Nothing in register {}", name));
             return Ok(());
+        };
+
+        self.last_macro = Some(name);
+        let replayed = keys::parse(&register.text).repeat(count.unwrap_or(1));
+        // Replayed keys go before the rest of an outer macro.
+        for key in replayed.into_iter().rev() {
+            self.pending_keys.push_front((key, true));
         }
 
-        self.current_buffer += 1;
+        Ok(())
+    }
+
+    /// Reads the name of the register the next yank, delete or put uses.
+    fn select_register(&mut self) -> Result<(), io::Error> {
+        match self.read_key()? {
+            Key::Char(name) if Registers::is_valid_name(name) => self.pending_register = Some(name),
+            _ => self.pending_register = None,
+        }
 
         Ok(())
     }
 
-    fn command_buffer_previous(&mut self, _command: &[&str]) -> Result<(), io::Error> {
-        if self.current_buffer == 0 {
-            self.current_buffer = self.buffers.len() - 1;
-            return Ok(());
+    /// Stores yanked text in the pending register, copying it to the system clipboard for
+    /// `"+` and `"*`.
+    fn yank_to_register(&mut self, register: Register) {
+        let name = self.pending_register.take();
+        self.copy_to_clipboard(name, &register);
+        self.registers.yank(name, register);
+    }
+
+    fn delete_to_register(&mut self, register: Register) {
+        let name = self.pending_register.take();
+        self.copy_to_clipboard(name, &register);
+        self.registers.delete(name, register);
+    }
+
+    fn copy_to_clipboard(&self, name: Option<char>, register: &Register) {
+        if let Some(selection) = name.and_then(ClipboardSelection::from_register) {
+            self.clipboard.copy(&register.text, selection);
         }
+    }
 
-        self.current_buffer -= 1;
+    /// Returns the contents of register `name`, reading `"+` and `"*` from the system
+    /// clipboard when the provider allows it.
+    fn read_register(&mut self, name: Option<char>) -> Option<Register> {
+        if let Some(selection) = name.and_then(ClipboardSelection::from_register) {
+            if let Some(text) = self.clipboard.paste(selection) {
+                self.registers.set_clipboard(name.unwrap(), text);
+            }
+        }
 
-        Ok(())
+        self.registers.get(name).cloned()
     }
 
-    fn command_buffer_close(&mut self, _command: &[&str]) -> Result<(), io::Error> {
-        self.buffers.remove(self.current_buffer);
-        self.cursor_positions.remove(self.current_buffer);
-        self.scroll_offsets.remove(self.current_buffer);
+    /// Puts the contents of the selected register `count` times after the cursor, or before
+    /// it when `before` is set. Line-wise contents go below or above the current row.
+    fn put(&mut self, before: bool, count: Option<usize>) {
+        let name = self.pending_register.take();
+        let Some(register) = self.read_register(name) else {
+            self.prompt_bar_message =
+                Message::new_error(format!("Nothing in register {}", name.unwrap_or('"')));
+            return;
+        };
+
+        let count = count.unwrap_or(1);
+        let text = match register.kind {
+            RegisterKind::Block => register
+                .text
+                .split('\n')
+                .map(|line| line.repeat(count))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            RegisterKind::Character | RegisterKind::Line => register.text.repeat(count),
+        };
 
-        if self.buffers.is_empty() {
-            self.add_buffer(Buffer::default());
-            self.current_buffer = 0;
-            return Ok(());
+        let cursor = self.windows[self.current_window].cursor;
+        let buffer = self.buffer_mut();
+        let row_len = buffer
+            .row(cursor.y)
+            .map(|row| row.len())
+            .unwrap_or_default();
+        buffer.begin_transaction();
+
+        let new_cursor = match register.kind {
+            RegisterKind::Character => {
+                let at = if before || row_len == 0 {
+                    cursor
+                } else {
+                    Position {
+                        x: (cursor.x + 1).min(row_len),
+                        y: cursor.y,
+                    }
+                };
+                let end = buffer.insert_str(&at, &text);
+                Position {
+                    x: end.x.saturating_sub(1),
+                    y: end.y,
+                }
+            }
+            RegisterKind::Line => {
+                let y = if before || buffer.is_empty() {
+                    cursor.y
+                } else {
+                    cursor.y + 1
+                };
+                buffer.insert_str(&Position { x: 0, y }, &text);
+                Position { x: 0, y }
+            }
+            RegisterKind::Block => {
+                let x = if before || row_len == 0 {
+                    cursor.x
+                } else {
+                    cursor.x + 1
+                };
+                for (index, line) in text.split('\n').enumerate() {
+                    let y = cursor.y + index;
+                    let row_len = buffer.row(y).map(|row| row.len()).unwrap_or_default();
+                    if row_len < x {
+                        let padding = " ".repeat(x - row_len);
+                        buffer.insert_str(&Position { x: row_len, y }, &(padding + line));
+                    } else {
+                        buffer.insert_str(&Position { x, y }, line);
+                    }
+                }
+                Position { x, y: cursor.y }
+            }
+        };
+
+        buffer.end_transaction();
+        self.set_cursor_position(new_cursor);
+    }
+
+    /// Enters the given visual mode, or leaves it when it is already the current one.
+    fn start_visual(&mut self, mode: Mode) {
+        if self.mode == mode {
+            self.mode = Mode::Normal;
+            return;
         }
 
-        if self.current_buffer == self.buffers.len() {
-            self.current_buffer = self.buffers.len() - 1;
+        if self.mode == Mode::Normal {
+            self.windows[self.current_window].visual_anchor =
+                self.windows[self.current_window].cursor;
         }
+        self.mode = mode;
+    }
 
-        Ok(())
+    fn selection(&self) -> Option<Selection> {
+        let kind = self.mode.selection_kind()?;
+        Some(Selection::new(
+            kind,
+            self.windows[self.current_window].visual_anchor,
+            self.windows[self.current_window].cursor,
+        ))
     }
 
-    fn add_buffer(&mut self, buffer: Buffer) {
-        self.buffers.push(buffer);
-        self.cursor_positions.push(Position::default());
-        self.scroll_offsets.push(Position::default());
-        self.current_buffer += 1;
+    /// Returns the text covered by the visual selection.
+    fn selection_range(&self) -> Option<TextRange> {
+        let selection = self.selection()?;
+
+        Some(match selection.kind {
+            SelectionKind::Character => TextRange::Characters {
+                start: selection.start,
+                end: self.selection_end(&selection),
+            },
+            SelectionKind::Line => TextRange::Lines {
+                first: selection.start.y,
+                last: selection.end.y,
+            },
+            SelectionKind::Block => TextRange::Block(selection),
+        })
+    }
+
+    /// Selects the text in `range`, switching to line-wise visual mode for whole rows.
+    fn select_range(&mut self, range: TextRange) {
+        let (anchor, cursor) = match range {
+            TextRange::Characters { start, end } => {
+                self.mode = Mode::Visual;
+
+                // The selection is inclusive, so it stops on the grapheme before `end`.
+                let last = if end.x > 0 {
+                    Position {
+                        x: end.x - 1,
+                        y: end.y,
+                    }
+                } else if end.y > start.y {
+                    let y = end.y - 1;
+                    Position {
+                        x: self
+                            .buffer()
+                            .row(y)
+                            .map(|row| row.len())
+                            .unwrap_or_default(),
+                        y,
+                    }
+                } else {
+                    start
+                };
+                (start, last)
+            }
+            TextRange::Lines { first, last } => {
+                self.mode = Mode::VisualLine;
+                (Position { x: 0, y: first }, Position { x: 0, y: last })
+            }
+            TextRange::Block(selection) => (selection.start, selection.end),
+        };
+
+        self.windows[self.current_window].visual_anchor = anchor;
+        self.windows[self.current_window].cursor = cursor;
     }
-}
 
-fn die(e: io::Error) {
-    termutils::clear();
-    panic!("{}", e);
+    /// Returns the text in `range`, with rows of a block separated by line breaks.
+    fn range_text(&self, range: &TextRange) -> String {
+        let buffer = self.buffer();
+
+        match range {
+            TextRange::Characters { start, end } => buffer.text_range(start, end),
+            TextRange::Lines { first, last } => {
+                let mut text = buffer.text_range(
+                    &Position { x: 0, y: *first },
+                    &Position { x: 0, y: last + 1 },
+                );
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text
+            }
+            TextRange::Block(selection) => (selection.start.y..=selection.end.y)
+                .filter_map(|y| {
+                    let row = buffer.row(y)?;
+                    let (start, end) = selection.row_range(y, row.len())?;
+                    Some(
+                        row.as_str()[row.grapheme_to_byte(start)..row.grapheme_to_byte(end)]
+                            .to_string(),
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+        }
+    }
+
+    /// Returns the position right after a character selection, which includes the line
+    /// break when the selection ends past the last grapheme of its row.
+    fn selection_end(&self, selection: &Selection) -> Position {
+        let row_len = self
+            .buffer()
+            .row(selection.end.y)
+            .map(|row| row.len())
+            .unwrap_or_default();
+
+        if selection.end.x < row_len {
+            Position {
+                x: selection.end.x + 1,
+                y: selection.end.y,
+            }
+        } else {
+            Position {
+                x: 0,
+                y: selection.end.y + 1,
+            }
+        }
+    }
+
+    /// Deletes the text in `range` into the pending register. With `change`, rows are
+    /// emptied instead of removed and insert mode is entered.
+    fn delete_text(&mut self, range: TextRange, change: bool) {
+        let text = self.range_text(&range);
+        self.delete_to_register(Register::new(text, range.register_kind()));
+
+        let buffer = self.buffer_mut();
+        buffer.begin_transaction();
+
+        match range {
+            TextRange::Characters { start, end } => {
+                buffer.delete_range(&start, &end);
+            }
+            TextRange::Lines { first, last } if change => {
+                let last_row_len = buffer.row(last).map(|row| row.len()).unwrap_or_default();
+                buffer.delete_range(
+                    &Position { x: 0, y: first },
+                    &Position {
+                        x: last_row_len,
+                        y: last,
+                    },
+                );
+            }
+            TextRange::Lines { first, last } => {
+                buffer.delete_range(
+                    &Position { x: 0, y: first },
+                    &Position { x: 0, y: last + 1 },
+                );
+            }
+            TextRange::Block(selection) => {
+                for y in selection.start.y..=selection.end.y {
+                    let Some(row) = buffer.row(y) else {
+                        continue;
+                    };
+                    if let Some((start, end)) = selection.row_range(y, row.len()) {
+                        buffer.delete_range(&Position { x: start, y }, &Position { x: end, y });
+                    }
+                }
+            }
+        }
+
+        if change {
+            self.windows[self.current_window].cursor = range.start();
+            self.mode = Mode::Insert;
+            return;
+        }
+
+        buffer.end_transaction();
+        let cursor = match range {
+            TextRange::Lines { first, .. } => {
+                let y = first.min(buffer.len().saturating_sub(1));
+                Position {
+                    x: motion::first_non_blank(buffer, y),
+                    y,
+                }
+            }
+            TextRange::Characters { .. } | TextRange::Block(_) => range.start(),
+        };
+        self.set_cursor_position(cursor);
+    }
+
+    fn yank_text(&mut self, range: TextRange) {
+        let text = self.range_text(&range);
+        self.yank_to_register(Register::new(text, range.register_kind()));
+
+        if let TextRange::Lines { first, last } = range {
+            if last > first {
+                self.prompt_bar_message =
+                    Message::new_normal(format!("{} lines yanked", last - first + 1));
+            }
+        }
+
+        // Line-wise yanks keep the cursor column, like `yy` and `yj`.
+        let cursor = self.windows[self.current_window].cursor;
+        let cursor = match range {
+            TextRange::Lines { first, .. } => Position {
+                x: cursor.x,
+                y: first,
+            },
+            TextRange::Characters { .. } | TextRange::Block(_) => range.start(),
+        };
+        self.set_cursor_position(cursor);
+    }
+
+    fn indent_text(&mut self, range: TextRange, indent: bool) {
+        let buffer = self.buffer_mut();
+        let shift_width = buffer.options.shiftwidth;
+        buffer.begin_transaction();
+
+        for y in range.first_row()..=range.last_row() {
+            let Some(row) = buffer.row(y) else {
+                continue;
+            };
+
+            if indent {
+                if !row.as_str().is_empty() {
+                    buffer.insert_str(&Position { x: 0, y }, &" ".repeat(shift_width));
+                }
+            } else {
+                let width = if row.as_str().starts_with('\t') {
+                    1
+                } else {
+                    row.as_str()
+                        .chars()
+                        .take(shift_width)
+                        .take_while(|c| *c == ' ')
+                        .count()
+                };
+                buffer.delete_range(&Position { x: 0, y }, &Position { x: width, y });
+            }
+        }
+
+        buffer.end_transaction();
+        let y = range.first_row();
+        let x = motion::first_non_blank(buffer, y);
+        self.set_cursor_position(Position { x, y });
+    }
+
+    fn change_case(&mut self, range: TextRange, convert: fn(&str) -> String) {
+        let buffer = self.buffer_mut();
+        buffer.begin_transaction();
+
+        for y in range.first_row()..=range.last_row() {
+            let Some(row) = buffer.row(y) else {
+                continue;
+            };
+            let Some((start, end)) = range.row_range(y, row.len()) else {
+                continue;
+            };
+
+            let text = &row.as_str()[row.grapheme_to_byte(start)..row.grapheme_to_byte(end)];
+            let converted = convert(text);
+            if converted != text {
+                buffer.replace_range(
+                    &Position { x: start, y },
+                    &Position { x: end, y },
+                    &converted,
+                );
+            }
+        }
+
+        buffer.end_transaction();
+        self.set_cursor_position(range.start());
+    }
+
+    fn undo(&mut self) {
+        match self.buffer_mut().undo() {
+            Some(position) => self.set_cursor_position(position),
+            None => {
+                self.prompt_bar_message = Message::new_error("Already at oldest change".to_string())
+            }
+        }
+    }
+
+    fn redo(&mut self) {
+        match self.buffer_mut().redo() {
+            Some(position) => self.set_cursor_position(position),
+            None => {
+                self.prompt_bar_message = Message::new_error("Already at newest change".to_string())
+            }
+        }
+    }
+
+    fn set_cursor_position(&mut self, position: Position) {
+        if self.buffer().hex.is_some() {
+            self.set_hex_offset(position.y * BYTES_PER_ROW + position.x);
+            return;
+        }
+
+        let buffer = self.buffer();
+        let y = position.y.min(buffer.len());
+        let x = match buffer.row(y) {
+            Some(row) => position.x.min(row.len()),
+            None => 0,
+        };
+
+        self.windows[self.current_window].cursor = Position { x, y };
+    }
+
+    /// Returns the offset of the byte under the cursor, when the buffer is shown in hex.
+    fn hex_offset(&self) -> usize {
+        let cursor = self.windows[self.current_window].cursor;
+        cursor.y * BYTES_PER_ROW + cursor.x
+    }
+
+    /// Moves the cursor to the byte at `offset`, or to the last byte.
+    fn set_hex_offset(&mut self, offset: usize) {
+        let len = self.buffer().hex.as_ref().map_or(0, |hex| hex.len());
+        let offset = offset.min(len.saturating_sub(1));
+
+        self.windows[self.current_window].cursor = Position {
+            x: offset % BYTES_PER_ROW,
+            y: offset / BYTES_PER_ROW,
+        };
+    }
+
+    fn undo_hex(&mut self, redo: bool) {
+        let Some(hex) = &mut self.buffer_mut().hex else {
+            return;
+        };
+
+        match if redo { hex.redo() } else { hex.undo() } {
+            Some(offset) => self.set_hex_offset(offset),
+            None if redo => {
+                self.prompt_bar_message = Message::new_error("Already at newest change".to_string())
+            }
+            None => {
+                self.prompt_bar_message = Message::new_error("Already at oldest change".to_string())
+            }
+        }
+    }
+
+    fn search_hex(&mut self, direction: SearchDirection) -> Result<(), io::Error> {
+        let prompt = match direction {
+            SearchDirection::Forward => "/",
+            SearchDirection::Backward => "?",
+        };
+
+        if let Some(query) = self.prompt(prompt, |_, _, _| {})? {
+            self.last_search = Some((query, direction));
+            self.search_hex_next(false);
+        }
+
+        Ok(())
+    }
+
+    /// Finds the next match of the last search, read as a pattern of hex bytes.
+    fn search_hex_next(&mut self, reverse: bool) {
+        let Some((query, direction)) = self.last_search.clone() else {
+            self.prompt_bar_message = Message::new_error("No previous search pattern".to_string());
+            return;
+        };
+        let Some(pattern) = hex::parse_pattern(&query) else {
+            self.prompt_bar_message = Message::new_error(format!("Invalid hex pattern: {}", query));
+            return;
+        };
+
+        let direction = if reverse {
+            direction.reversed()
+        } else {
+            direction
+        };
+        let offset = self.hex_offset();
+        let found = self
+            .buffer()
+            .hex
+            .as_ref()
+            .and_then(|hex| hex.find(&pattern, offset, direction));
+
+        match found {
+            Some(found) => {
+                let wrapped = match direction {
+                    SearchDirection::Forward => found <= offset,
+                    SearchDirection::Backward => found >= offset,
+                };
+                if wrapped {
+                    self.prompt_bar_message = Message::new_warning(match direction {
+                        SearchDirection::Forward => {
+                            "search hit BOTTOM, continuing at TOP".to_string()
+                        }
+                        SearchDirection::Backward => {
+                            "search hit TOP, continuing at BOTTOM".to_string()
+                        }
+                    });
+                }
+                self.set_hex_offset(found);
+            }
+            None => {
+                self.prompt_bar_message =
+                    Message::new_error(format!("Pattern not found: {}", query))
+            }
+        }
+    }
+
+    fn draw_row(&self, row: &Row, window: &Window, rect: Rect) {
+        print!(
+            "{}",
+            row.render(window.scroll.x, rect.width + window.scroll.x, &self.theme)
+        );
+    }
+
+    fn draw_rows(&self, index: usize, rect: Rect) {
+        let window = &self.windows[index];
+        let buffer = &self.buffers[window.buffer];
+        // Only the current window shows the selection, as it follows its cursor.
+        let selection = if index == self.current_window {
+            self.selection()
+        } else {
+            None
+        };
+        let height = rect.height.saturating_sub(1);
+
+        for terminal_row in 0..height {
+            termutils::set_cursor_position(&Position {
+                x: rect.x,
+                y: rect.y + terminal_row,
+            });
+            let y = terminal_row + window.scroll.y;
+
+            if let Some(hex) = &buffer.hex {
+                if y < hex.rows() {
+                    let cursor = window.cursor.y * BYTES_PER_ROW + window.cursor.x;
+                    let end = rect.width + window.scroll.x;
+                    print!(
+                        "{}",
+                        hex.render(y, window.scroll.x, end, Some(cursor), &self.theme)
+                    );
+                } else {
+                    print!("{:<width$}", "~", width = rect.width);
+                }
+            } else if let Some(mut row) = buffer.highlighted_row(y, self.options.hlsearch) {
+                if let Some((start, end)) =
+                    selection.and_then(|selection| selection.row_range(y, row.len()))
+                {
+                    row.select(start, end);
+                }
+                self.draw_row(&row, window, rect);
+            } else if buffer.is_empty() && terminal_row == height / 3 {
+                self.draw_welcome_message(rect.width);
+            } else {
+                print!("{:<width$}", "~", width = rect.width);
+            }
+        }
+    }
+
+    fn draw_tabline(&self) {
+        if self.tabline_height() == 0 {
+            return;
+        }
+
+        let width = self.terminal_size.0 as usize;
+        termutils::set_cursor_position(&Position::default());
+        termutils::set_fg_color(self.theme.color(Group::StatusFg));
+
+        let mut column = 0;
+        for index in 0..self.tabs.len() {
+            let (windows, current_window) = if index == self.current_tab {
+                (&self.windows, self.current_window)
+            } else {
+                (&self.tabs[index].windows, self.tabs[index].current_window)
+            };
+            let name = self.buffers[windows[current_window].buffer].name();
+            let is_dirty = if windows
+                .iter()
+                .any(|window| self.buffers[window.buffer].is_dirty())
+            {
+                " [+]"
+            } else {
+                ""
+            };
+
+            let label = format!(" {} {}{} ", index + 1, name, is_dirty);
+            let label = truncate_to_width(&label, width.saturating_sub(column));
+            column += label.width();
+
+            if index == self.current_tab {
+                termutils::set_bg_color(self.theme.color(Group::StatusBg));
+            } else {
+                termutils::set_bg_color(self.theme.color(Group::StatusInactiveBg));
+            }
+            print!("{}", label);
+        }
+
+        termutils::reset_bg_color();
+        termutils::reset_fg_color();
+        print!("{}", " ".repeat(width.saturating_sub(column)));
+    }
+
+    /// Draws the columns between windows which are side by side.
+    fn draw_separators(&self) {
+        termutils::set_fg_color(self.theme.color(Group::Separator));
+        for separator in self.layout.separators(self.window_area()) {
+            for y in separator.y..separator.y + separator.height {
+                termutils::set_cursor_position(&Position { x: separator.x, y });
+                print!("│");
+            }
+        }
+        termutils::reset_fg_color();
+    }
+
+    fn draw_status_bar(&self, index: usize, rect: Rect) {
+        let width = rect.width;
+        let active = index == self.current_window;
+        let window = &self.windows[index];
+        let buffer = &self.buffers[window.buffer];
+        // Other windows only tell which buffer they show.
+        let mode = match self.mode {
+            _ if !active => "",
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+            Mode::VisualLine => "V-LINE",
+            Mode::VisualBlock => "V-BLOCK",
+        };
+        let file_name = buffer.name();
+        let is_dirty = match (buffer.is_dirty(), buffer.read_only) {
+            (true, _) => "[+]",
+            (false, true) => "[RO]",
+            (false, false) => "",
+        };
+        let file_type = format!("{}", buffer.file_type);
+        let column = buffer
+            .row(window.cursor.y)
+            .map(|row| row.width_to(window.cursor.x))
+            .unwrap_or_default();
+        let current_pos = if buffer.hex.is_some() {
+            format!("0x{:x}", window.cursor.y * BYTES_PER_ROW + window.cursor.x)
+        } else if column == window.cursor.x {
+            format!("{}:{}", window.cursor.y + 1, column + 1)
+        } else {
+            format!(
+                "{}:{}-{}",
+                window.cursor.y + 1,
+                window.cursor.x + 1,
+                column + 1
+            )
+        };
+
+        let mut status = String::new();
+
+        let recording = match self.recording {
+            Some((name, _)) if active => format!("   recording @{}", name),
+            _ => String::new(),
+        };
+
+        let left_side = if active {
+            format!("{}   {} {}{}", mode, file_name, is_dirty, recording)
+        } else {
+            format!("{} {}", file_name, is_dirty)
+        };
+        let file_format = if buffer.hex.is_some() {
+            "hex".to_string()
+        } else if buffer.binary {
+            "binary".to_string()
+        } else if let Some(progress) = buffer.indexing_progress() {
+            format!("huge, indexing {}%", progress)
+        } else if buffer.is_huge() {
+            "huge".to_string()
+        } else {
+            format!(
+                "{} {}{}{}",
+                buffer.encoding,
+                buffer.file_format,
+                if buffer.bom { " [BOM]" } else { "" },
+                if buffer.missing_final_newline {
+                    " [noeol]"
+                } else {
+                    ""
+                }
+            )
+        };
+        let right_side = format!("{}   {}   {}", file_format, file_type, current_pos);
+
+        status.push_str(&left_side);
+
+        // separator
+        let len = left_side.width() + right_side.width() + 2; // +1 is the space at the start of the status bar
+        status.push_str(&" ".repeat(width.saturating_sub(len)));
+
+        status.push_str(&right_side);
+
+        // spaces on the sides
+        status.insert(0, ' ');
+        status.insert(status.len(), ' ');
+
+        // Narrow windows cut the status bar short.
+        let status = truncate_to_width(&status, width);
+
+        if active {
+            termutils::set_bg_color(self.theme.color(Group::StatusBg));
+        } else {
+            termutils::set_bg_color(self.theme.color(Group::StatusInactiveBg));
+        }
+        termutils::set_fg_color(self.theme.color(Group::StatusFg));
+
+        termutils::set_cursor_position(&Position {
+            x: rect.x,
+            y: rect.y + rect.height.saturating_sub(1),
+        });
+        print!("{}", status);
+
+        termutils::reset_bg_color();
+        termutils::reset_fg_color();
+    }
+
+    fn draw_command_bar(&self) {
+        termutils::set_cursor_position(&Position {
+            x: 0,
+            y: self.terminal_size.1 as usize + 1,
+        });
+        termutils::clear_line();
+        let mut text = self.prompt_bar_message.clone();
+        text.message.truncate(self.terminal_size.0 as usize);
+        println!("{}", text.render(&self.theme));
+    }
+
+    fn draw_welcome_message(&self, width: usize) {
+        let mut welcome_message = format!("Edicode -- version {}", VERSION);
+        let len = welcome_message.len();
+        let padding = width.saturating_sub(len) / 2;
+        let spaces = " ".repeat(padding.saturating_sub(1));
+        welcome_message = format!("~{}{}", spaces, welcome_message);
+        welcome_message.truncate(width);
+        print!("{:<width$}", welcome_message, width = width);
+    }
+
+    fn command_save_file(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        let backup = self.options.backup;
+        let buffer = self.buffer_mut();
+        // A path given to `:w` only becomes the buffer's once it is written to.
+        let save_location = match command.get(1) {
+            Some(save_location) => save_location.to_string(),
+            None => buffer.save_location.clone().unwrap_or_default(),
+        };
+
+        if save_location.is_empty() {
+            self.prompt_bar_message =
+                Message::new_error("Can't save with no path set!".to_string());
+            return Ok(());
+        }
+
+        if let Err(e) = buffer.save(&save_location, backup) {
+            self.prompt_bar_message =
+                Message::new_error(format!("Can't write \"{}\": {}", save_location, e));
+            return Ok(());
+        }
+        buffer.save_location = Some(save_location.clone());
+        self.prompt_bar_message = Message::new_normal(format!("\"{}\" written", save_location));
+        self.remove_swap_file(self.buffer().id);
+
+        Ok(())
+    }
+
+    /// Saves every modified buffer and returns whether all of them could be saved.
+    fn command_save_all(&mut self, _command: &[&str]) -> Result<bool, io::Error> {
+        let mut written = 0;
+        let mut unnamed = Vec::new();
+        let mut failed = Vec::new();
+
+        for buffer in self.buffers.iter_mut().filter(|buffer| buffer.is_dirty()) {
+            match buffer.save_location.clone() {
+                Some(save_location) => match buffer.save(&save_location, self.options.backup) {
+                    Ok(()) => {
+                        written += 1;
+                        if let Some(path) = self.swap_files.remove(&buffer.id) {
+                            let _ = fs::remove_file(path);
+                        }
+                    }
+                    Err(e) => failed.push(format!("\"{}\": {}", save_location, e)),
+                },
+                None => unnamed.push(buffer.id.to_string()),
+            }
+        }
+
+        if !failed.is_empty() {
+            self.prompt_bar_message =
+                Message::new_error(format!("Can't write {}", failed.join(", ")));
+            return Ok(false);
+        }
+
+        if !unnamed.is_empty() {
+            self.prompt_bar_message =
+                Message::new_error(format!("No file name for buffer {}", unnamed.join(", ")));
+            return Ok(false);
+        }
+
+        self.prompt_bar_message = Message::new_normal(format!(
+            "{} buffer{} written",
+            written,
+            if written == 1 { "" } else { "s" }
+        ));
+        Ok(true)
+    }
+
+    /// Shows the current buffer in hex, or goes back to its text.
+    fn command_hex(&mut self, _command: &[&str]) -> Result<(), io::Error> {
+        let result = if self.buffer().hex.is_some() {
+            self.buffer_mut().leave_hex()
+        } else {
+            self.buffer_mut().enter_hex()
+        };
+
+        match result {
+            Ok(()) => {
+                self.hex_nibble = false;
+                self.set_cursor_position(Position::default());
+            }
+            Err(e) => {
+                self.prompt_bar_message = Message::new_error(format!("Can't switch to hex: {}", e))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn command_set(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        if command.len() < 2 {
+            self.prompt_bar_message = Message::new_error("Option not given!".to_string());
+            return Ok(());
+        }
+
+        if command[1] == "all" {
+            let lines = options::OPTIONS
+                .iter()
+                .map(|info| format!("  {}", self.describe_option(info)))
+                .collect::<Vec<String>>();
+            return self.show_lines(&lines);
+        }
+
+        // `:setlocal` leaves the values new buffers and windows start with as they are.
+        let local = command[0].starts_with("setl");
+        for argument in &command[1..] {
+            if let Err(e) = self.set_option(argument, local) {
+                self.prompt_bar_message = Message::new_error(e);
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies an argument of `:set`: `name` turns a flag on or shows another option,
+    /// `noname` turns a flag off, `name!` toggles it, `name?` shows the value of the option
+    /// and `name=value` sets it.
+    fn set_option(&mut self, argument: &str, local: bool) -> Result<(), String> {
+        let name = argument.split(['=', '?']).next().unwrap_or_default();
+        if let Some(group) = theme::deprecated_option(name) {
+            match argument.split_once('=') {
+                Some((_, value)) => self.theme.set_color(group, value)?,
+                None => {
+                    let color = self.theme.describe_color(group);
+                    self.prompt_bar_message = Message::new_normal(format!("  {}={}", name, color));
+                }
+            }
+            return Ok(());
+        }
+
+        let find = |name: &str| options::find(name).ok_or(format!("Unknown option: {}", name));
+        let find_flag = |name: &str| options::find(name).filter(|info| info.kind == Kind::Flag);
+
+        let (info, value) = if let Some(name) = argument.strip_suffix('?') {
+            let info = find(name)?;
+            self.prompt_bar_message =
+                Message::new_normal(format!("  {}", self.describe_option(info)));
+            return Ok(());
+        } else if let Some((name, value)) = argument.split_once('=') {
+            let info = find(name)?;
+            (info, Value::parse(info.kind, value)?)
+        } else if let Some(info) = argument.strip_suffix('!').and_then(find_flag) {
+            let flag = matches!(self.option_value(info), Value::Flag(true));
+            (info, Value::Flag(!flag))
+        } else if let Some(info) = argument.strip_prefix("no").and_then(find_flag) {
+            (info, Value::Flag(false))
+        } else {
+            let info = find(argument)?;
+            if info.kind != Kind::Flag {
+                self.prompt_bar_message =
+                    Message::new_normal(format!("  {}", self.describe_option(info)));
+                return Ok(());
+            }
+            (info, Value::Flag(true))
+        };
+
+        self.apply_option(info, value, local)
+    }
+
+    /// Returns the value of an option for the current buffer and window.
+    fn option_value(&self, info: &OptionInfo) -> Value {
+        let value = match info.scope {
+            Scope::Global => self.options.get(info.name),
+            Scope::Buffer => self.buffer().option(info.name),
+            Scope::Window => self.windows[self.current_window].options.get(info.name),
+        };
+        value.unwrap()
+    }
+
+    /// Sets an option for the current buffer and window, and for new ones unless `local` is
+    /// set.
+    fn apply_option(&mut self, info: &OptionInfo, value: Value, local: bool) -> Result<(), String> {
+        match info.scope {
+            Scope::Global => {
+                self.options.set(info.name, value)?;
+                self.theme.set_depth(self.options.colordepth);
+                Ok(())
+            }
+            Scope::Buffer => {
+                self.buffer_mut().set_option(info.name, value.clone())?;
+                // The options describing the file of a buffer have no value for new ones.
+                if !local && self.buffer_options.get(info.name).is_some() {
+                    self.buffer_options.set(info.name, value)?;
+                }
+                Ok(())
+            }
+            Scope::Window => {
+                self.windows[self.current_window]
+                    .options
+                    .set(info.name, value.clone())?;
+                if !local {
+                    self.window_options.set(info.name, value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Describes an option as `:set` shows it: `name` or `noname` for a flag, and
+    /// `name=value` otherwise.
+    fn describe_option(&self, info: &OptionInfo) -> String {
+        match self.option_value(info) {
+            Value::Flag(true) => info.name.to_string(),
+            Value::Flag(false) => format!("no{}", info.name),
+            value => format!("{}={}", info.name, value),
+        }
+    }
+
+    /// Applies the options set in the config file, which give their values to the current
+    /// buffers and windows too, and tells what is wrong with it if anything.
+    fn load_config(&mut self) {
+        let config = match options::read_config() {
+            Ok(config) => config,
+            Err(e) => {
+                self.prompt_bar_message = Message::new_error(format!("Error in config: {}", e));
+                return;
+            }
+        };
+
+        let mut errors = Vec::new();
+        // Mappings are made last so they use the leader set in the file.
+        let (mappings, values): (Vec<_>, Vec<_>) =
+            config.iter().partition(|(_, value)| value.is_table());
+        for (name, value) in values.into_iter().chain(mappings) {
+            let result = match (value, theme::deprecated_option(name)) {
+                (toml::Value::Table(mappings), _) => self.map_from_config(name, mappings),
+                (toml::Value::String(theme), _) if name == "colorscheme" => {
+                    Theme::load(theme, self.options.colordepth).map(|theme| self.theme = theme)
+                }
+                // Set after `colorscheme`, which comes first in the sorted table.
+                (toml::Value::String(color), Some(group)) => self.theme.set_color(group, color),
+                (value, _) => options::find(name)
+                    .ok_or(format!("Unknown option: {}", name))
+                    .and_then(|info| {
+                        let value = Value::from_toml(info.kind, value)?;
+                        match info.scope {
+                            Scope::Global => self.options.set(info.name, value),
+                            Scope::Buffer => self.buffer_options.set(info.name, value),
+                            Scope::Window => self.window_options.set(info.name, value),
+                        }
+                    }),
+            };
+            if let Err(e) = result {
+                errors.push(e);
+            }
+        }
+
+        for buffer in &mut self.buffers {
+            buffer.options = self.buffer_options.clone();
+        }
+        for window in &mut self.windows {
+            window.options = self.window_options.clone();
+        }
+        self.theme.set_depth(self.options.colordepth);
+
+        if !errors.is_empty() {
+            self.prompt_bar_message =
+                Message::new_error(format!("Error in config: {}", errors.join(", ")));
+        }
+    }
+
+    /// Switches to the theme named by `:colorscheme name`, or shows the name of the current
+    /// one.
+    fn command_colorscheme(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        let Some(name) = command.get(1) else {
+            self.prompt_bar_message = Message::new_normal(self.theme.name.clone());
+            return Ok(());
+        };
+
+        match Theme::load(name, self.options.colordepth) {
+            Ok(theme) => self.theme = theme,
+            Err(e) => self.prompt_bar_message = Message::new_error(e),
+        }
+
+        Ok(())
+    }
+
+    /// Makes the mappings of a table of the config file, such as `[imap]` or
+    /// `[inoremap]`, which maps each key of the table to its value.
+    fn map_from_config(&mut self, name: &str, mappings: &toml::Table) -> Result<(), String> {
+        let (modes, recursive): (&[MapMode], bool) = match name {
+            "map" => (&[MapMode::Normal, MapMode::Visual], true),
+            "noremap" => (&[MapMode::Normal, MapMode::Visual], false),
+            "nmap" => (&[MapMode::Normal], true),
+            "nnoremap" => (&[MapMode::Normal], false),
+            "vmap" => (&[MapMode::Visual], true),
+            "vnoremap" => (&[MapMode::Visual], false),
+            "imap" => (&[MapMode::Insert], true),
+            "inoremap" => (&[MapMode::Insert], false),
+            _ => return Err(format!("Unknown option: {}", name)),
+        };
+
+        for (lhs, rhs) in mappings {
+            let rhs = rhs.as_str().ok_or(format!("Invalid argument: {}", rhs))?;
+            self.map_keys(modes, lhs, rhs, recursive)?;
+        }
+
+        Ok(())
+    }
+
+    /// Maps the keys written `lhs` to those written `rhs` in `modes`, mapping the keys of
+    /// `rhs` again when `recursive`.
+    fn map_keys(
+        &mut self,
+        modes: &[MapMode],
+        lhs: &str,
+        rhs: &str,
+        recursive: bool,
+    ) -> Result<(), String> {
+        let lhs = keymap::parse(lhs, &self.options.mapleader);
+        if lhs.is_empty() {
+            return Err("Argument required".to_string());
+        }
+        let rhs = keymap::parse(rhs, &self.options.mapleader);
+
+        for mode in modes {
+            self.keymaps.map(*mode, lhs.clone(), rhs.clone(), recursive);
+        }
+
+        Ok(())
+    }
+
+    /// Maps keys with `:map lhs rhs`, or lists the mappings starting with `lhs`, or all of
+    /// them, with `:map lhs` and `:map`. Only `:map` and its mode forms make recursive
+    /// mappings, which are listed without the `*` of those made with `:noremap`.
+    fn command_map(
+        &mut self,
+        command: &[&str],
+        modes: &[MapMode],
+        recursive: bool,
+    ) -> Result<(), io::Error> {
+        if command.len() > 2 {
+            let rhs = command[2..].join(" ");
+            if let Err(e) = self.map_keys(modes, command[1], &rhs, recursive) {
+                self.prompt_bar_message = Message::new_error(e);
+            }
+            return Ok(());
+        }
+
+        let keys = command
+            .get(1)
+            .map(|lhs| keymap::parse(lhs, &self.options.mapleader))
+            .unwrap_or_default();
+        let lines = self
+            .keymaps
+            .list(modes, &keys)
+            .map(|mapping| {
+                format!(
+                    "{}  {:<12} {}{}",
+                    mapping.mode.letter(),
+                    keys::to_notation(&mapping.lhs),
+                    if mapping.recursive { " " } else { "*" },
+                    keys::to_notation(&mapping.rhs)
+                )
+            })
+            .collect::<Vec<String>>();
+
+        if lines.is_empty() {
+            self.prompt_bar_message = Message::new_normal("No mapping found".to_string());
+            return Ok(());
+        }
+        self.show_lines(&lines)
+    }
+
+    fn command_unmap(&mut self, command: &[&str], modes: &[MapMode]) {
+        if command.len() < 2 {
+            self.prompt_bar_message = Message::new_error("Argument required".to_string());
+            return;
+        }
+
+        let lhs = keymap::parse(command[1], &self.options.mapleader);
+        let mut found = false;
+        for mode in modes {
+            found |= self.keymaps.unmap(*mode, &lhs);
+        }
+        if !found {
+            self.prompt_bar_message = Message::new_error("No such mapping".to_string());
+        }
+    }
+
+    /// Closes the current window, or quits when it is the last one. A window can't be closed
+    /// without `!` while it is the last one showing a modified buffer, which would be left
+    /// hidden otherwise.
+    fn command_quit(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        if self.windows.len() > 1 || self.tabs.len() > 1 {
+            let force = command.first().is_some_and(|name| name.ends_with('!'));
+            self.close_window(force);
+            return Ok(());
+        }
+
+        self.command_quit_all(command)
+    }
+
+    fn command_quit_all(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        let force = command.first().is_some_and(|name| name.ends_with('!'));
+        if !force && !self.check_unsaved() {
+            return Ok(());
+        }
+
+        self.should_quit = true;
+        Ok(())
+    }
+
+    fn command_new_buffer(&mut self, _command: &[&str]) -> Result<(), io::Error> {
+        self.add_buffer(Buffer::default());
+        Ok(())
+    }
+
+    fn command_open_file(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        if let Some(file_location) = command.get(1) {
+            let buffer = match Buffer::open(file_location) {
+                Ok(buffer) => buffer,
+                Err(e) => {
+                    self.prompt_bar_message =
+                        Message::new_error(format!("Can't open \"{}\": {}", file_location, e));
+                    return Ok(());
+                }
+            };
+
+            self.add_buffer(buffer);
+            self.recover_swap_file(self.current_buffer())?;
+        } else {
+            self.prompt_bar_message = Message::new_error("File path not given!".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn command_buffer_next(&mut self, _command: &[&str]) -> Result<(), io::Error> {
+        let next = (self.current_buffer() + 1) % self.buffers.len();
+        self.show_buffer(next);
+
+        Ok(())
+    }
+
+    fn command_buffer_previous(&mut self, _command: &[&str]) -> Result<(), io::Error> {
+        let previous = self
+            .current_buffer()
+            .checked_sub(1)
+            .unwrap_or(self.buffers.len() - 1);
+        self.show_buffer(previous);
+
+        Ok(())
+    }
+
+    fn command_buffer(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        let Some(argument) = command.get(1) else {
+            self.prompt_bar_message = Message::new_error("Buffer not given!".to_string());
+            return Ok(());
+        };
+
+        if let Some(index) = self.find_buffer(argument) {
+            self.show_buffer(index);
+        }
+
+        Ok(())
+    }
+
+    fn command_list_buffers(&mut self, _command: &[&str]) -> Result<(), io::Error> {
+        let current = self.current_buffer();
+        let lines = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| {
+                // `%` marks the current buffer, and `a` the ones shown in a window.
+                let shown = if index == current {
+                    "%a"
+                } else if self.windows.iter().any(|window| window.buffer == index) {
+                    " a"
+                } else {
+                    "  "
+                };
+                let is_dirty = if buffer.is_dirty() { "+" } else { " " };
+
+                format!(
+                    "{:>3} {} {} \"{}\"   {}",
+                    buffer.id,
+                    shown,
+                    is_dirty,
+                    buffer.name(),
+                    buffer.file_type
+                )
+            })
+            .collect::<Vec<String>>();
+
+        self.show_lines(&lines)
+    }
+
+    /// Closes the current buffer, or the one given by id or name. Modified buffers are only
+    /// closed with a `!` or once confirmed.
+    fn command_buffer_close(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        let closed = match command.get(1) {
+            Some(argument) => match self.find_buffer(argument) {
+                Some(index) => index,
+                None => return Ok(()),
+            },
+            None => self.current_buffer(),
+        };
+
+        let id = self.buffers[closed].id;
+        if self.buffers[closed].is_dirty() && !command[0].ends_with('!') {
+            // `:bc` asks before dropping the changes, where `:bd` refuses to.
+            if matches!(command[0], "bc" | "buffer-close") {
+                let question = format!("Buffer {} is modified, close it anyway?", id);
+                if !self.confirm(&question)? {
+                    return Ok(());
+                }
+            } else {
+                self.prompt_bar_message = Message::new_error(format!(
+                    "No write since last change for buffer {} (add ! to override)",
+                    id
+                ));
+                return Ok(());
+            }
+        }
+
+        self.close_buffer(closed);
+        Ok(())
+    }
+
+    fn command_tab_new(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        let buffer = self.current_buffer();
+        self.store_tab();
+        self.tabs.insert(
+            self.current_tab + 1,
+            Tab::new(buffer, self.window_options.clone()),
+        );
+        self.load_tab(self.current_tab + 1);
+
+        if command.get(1).is_some() {
+            self.command_open_file(command)
+        } else {
+            self.command_new_buffer(command)
+        }
+    }
+
+    fn command_tab_next(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        match command.get(1).map(|number| number.parse::<usize>()) {
+            Some(Ok(number)) => self.go_to_tab(number),
+            Some(Err(_)) => {
+                self.prompt_bar_message =
+                    Message::new_error(format!("Invalid tab number: {}", command[1]))
+            }
+            None => self.next_tab(1, true),
+        }
+
+        Ok(())
+    }
+
+    fn command_tab_previous(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        match command.get(1).map(|count| count.parse::<usize>()) {
+            Some(Ok(count)) => self.next_tab(count, false),
+            Some(Err(_)) => {
+                self.prompt_bar_message =
+                    Message::new_error(format!("Invalid count: {}", command[1]))
+            }
+            None => self.next_tab(1, false),
+        }
+
+        Ok(())
+    }
+
+    fn command_tab_close(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        if self.tabs.len() == 1 {
+            self.prompt_bar_message = Message::new_error("Cannot close last tab page".to_string());
+            return Ok(());
+        }
+
+        let force = command.first().is_some_and(|name| name.ends_with('!'));
+        self.close_tab(force);
+        Ok(())
+    }
+
+    fn command_split(
+        &mut self,
+        command: &[&str],
+        direction: SplitDirection,
+    ) -> Result<(), io::Error> {
+        if !self.split_window(direction) {
+            return Ok(());
+        }
+
+        if command.get(1).is_some() {
+            self.command_open_file(command)?;
+        }
+
+        Ok(())
+    }
+
+    fn command_close(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        let force = command.first().is_some_and(|name| name.ends_with('!'));
+        self.close_window(force);
+        Ok(())
+    }
+
+    fn command_only(&mut self, command: &[&str]) -> Result<(), io::Error> {
+        let force = command.first().is_some_and(|name| name.ends_with('!'));
+        self.only_window(force);
+        Ok(())
+    }
+
+    fn command_resize(
+        &mut self,
+        command: &[&str],
+        direction: SplitDirection,
+    ) -> Result<(), io::Error> {
+        let Some(argument) = command.get(1) else {
+            self.prompt_bar_message = Message::new_error("Size not given!".to_string());
+            return Ok(());
+        };
+
+        let (sign, number) = match argument.chars().next() {
+            Some(sign @ ('+' | '-')) => (Some(sign), &argument[1..]),
+            _ => (None, *argument),
+        };
+        let Ok(number) = number.parse::<usize>() else {
+            self.prompt_bar_message = Message::new_error(format!("Invalid size: {}", argument));
+            return Ok(());
+        };
+
+        match sign {
+            Some('+') => self.resize_window(direction, number as isize),
+            Some(_) => self.resize_window(direction, -(number as isize)),
+            None => {
+                // Heights are given without the status line.
+                let size = match direction {
+                    SplitDirection::Horizontal => number + 1,
+                    SplitDirection::Vertical => number,
+                };
+                self.layout.set_size(self.current_window, direction, size);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn command_substitute(&mut self, substitution: Substitution) -> Result<(), io::Error> {
+        if !self.check_modifiable() {
+            return Ok(());
+        }
+
+        let previous_word = self.buffer().highlighted_word().map(str::to_string);
+        self.buffer_mut().begin_transaction();
+
+        let mut substitutions = 0;
+        let mut changed_lines = 0;
+        let mut last_changed_line = None;
+        let mut found_any = false;
+        let mut replace_all = !substitution.confirm;
+        let mut last_line = substitution.last_line;
+        let mut y = substitution.first_line;
+
+        while y <= last_line && y < self.buffer().len() {
+            let original = self.buffer().row(y).unwrap();
+            let string = original.as_str();
+
+            // Maps offsets of the original row to their position once earlier matches of
+            // the same row have been replaced.
+            let mut anchor_offset = 0;
+            let mut anchor = Position { x: 0, y };
+            let mut line_changed = false;
+            let mut stop = false;
+
+            for captures in substitution.pattern.captures_iter(string) {
+                let found = captures.get(0).unwrap();
+                found_any = true;
+                let found_start = original.byte_to_grapheme(found.start());
+                let found_end = original.byte_to_grapheme(found.end());
+                let start = Position {
+                    x: anchor.x + found_start - anchor_offset,
+                    y: anchor.y,
+                };
+                let end = Position {
+                    x: anchor.x + found_end - anchor_offset,
+                    y: anchor.y,
+                };
+
+                let mut replacement = String::new();
+                captures.expand(&substitution.replacement, &mut replacement);
+
+                if !replace_all {
+                    self.windows[self.current_window].cursor = start;
+                    self.buffer_mut()
+                        .set_highlighted_word(Some(found.as_str().to_string()));
+                    self.scroll();
+                    self.prompt_bar_message =
+                        Message::new_normal(format!("replace with {} (y/n/a/q/l)?", replacement));
+                    self.refresh_screen()?;
+
+                    match self.read_key()? {
+                        Key::Char('y') => (),
+                        // Without `g`, only the first match of each line is offered.
+                        Key::Char('n') if substitution.global => continue,
+                        Key::Char('n') => break,
+                        Key::Char('a') => replace_all = true,
+                        Key::Char('l') => stop = true,
+                        _ => {
+                            stop = true;
+                            break;
+                        }
+                    }
+                }
+
+                anchor = self.buffer_mut().replace_range(&start, &end, &replacement);
+                anchor_offset = found_end;
+                substitutions += 1;
+                line_changed = true;
+
+                if stop || !substitution.global {
+                    break;
+                }
+            }
+
+            if line_changed {
+                changed_lines += 1;
+                last_changed_line = Some(anchor.y);
+            }
+
+            if stop {
+                break;
+            }
+
+            last_line += anchor.y - y;
+            y = anchor.y + 1;
+        }
+
+        let buffer = self.buffer_mut();
+        buffer.end_transaction();
+        buffer.set_highlighted_word(previous_word);
+
+        match last_changed_line {
+            // Every match was skipped, or the confirmation quit before replacing any.
+            None if found_any => self.prompt_bar_message = Message::default(),
+            Some(line) => {
+                self.set_cursor_position(Position { x: 0, y: line });
+                self.prompt_bar_message = Message::new_normal(format!(
+                    "{} substitution{} on {} line{}",
+                    substitutions,
+                    if substitutions == 1 { "" } else { "s" },
+                    changed_lines,
+                    if changed_lines == 1 { "" } else { "s" },
+                ));
+            }
+            None => {
+                self.prompt_bar_message = Message::new_error(format!(
+                    "Pattern not found: {}",
+                    substitution.pattern.as_str()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_buffer(&mut self, buffer: Buffer) {
+        let index = self.push_buffer(buffer);
+        self.show_buffer(index);
+    }
+
+    /// Adds `buffer` to the list, giving it an id, and returns its index.
+    fn push_buffer(&mut self, mut buffer: Buffer) -> usize {
+        buffer.id = self.next_buffer_id;
+        buffer.options = self.buffer_options.clone();
+        self.next_buffer_id += 1;
+        self.buffers.push(buffer);
+        self.buffers.len() - 1
+    }
+
+    fn close_buffer(&mut self, closed: usize) {
+        let buffer = self.buffers.remove(closed);
+        self.remove_swap_file(buffer.id);
+
+        if self.buffers.is_empty() {
+            self.push_buffer(Buffer::default());
+        }
+
+        // Windows showing the closed buffer show the one which took its place instead.
+        let replacement = closed.min(self.buffers.len() - 1);
+        let windows = self
+            .windows
+            .iter_mut()
+            .chain(self.tabs.iter_mut().flat_map(|tab| tab.windows.iter_mut()));
+        for window in windows {
+            window.forget_buffer(closed, replacement);
+        }
+        self.set_cursor_position(self.windows[self.current_window].cursor);
+    }
+
+    /// Returns the index of the buffer `argument` refers to, by id or by a part of its name,
+    /// or `None` after telling why there is none.
+    fn find_buffer(&mut self, argument: &str) -> Option<usize> {
+        if let Ok(id) = argument.parse::<usize>() {
+            let index = self.buffers.iter().position(|buffer| buffer.id == id);
+            if index.is_none() {
+                self.prompt_bar_message =
+                    Message::new_error(format!("Buffer {} does not exist", id));
+            }
+            return index;
+        }
+
+        // A buffer named exactly like the argument wins over the ones only containing it.
+        if let Some(index) = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.name() == argument)
+        {
+            return Some(index);
+        }
+
+        let matches = self
+            .buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.name().contains(argument))
+            .map(|(index, _)| index)
+            .collect::<Vec<usize>>();
+        match matches[..] {
+            [index] => Some(index),
+            [] => {
+                self.prompt_bar_message =
+                    Message::new_error(format!("No matching buffer for {}", argument));
+                None
+            }
+            _ => {
+                self.prompt_bar_message =
+                    Message::new_error(format!("More than one match for {}", argument));
+                None
+            }
+        }
+    }
+
+    /// Returns the command lines `command` can be completed to.
+    fn complete_command(&self, command: &str) -> Vec<String> {
+        let Some((name, argument)) = command.split_once(' ') else {
+            return Vec::new();
+        };
+
+        match name.trim_end_matches('!') {
+            "b" | "buffer" | "bc" | "buffer-close" | "bd" | "bdelete" => self
+                .buffers
+                .iter()
+                .map(Buffer::name)
+                .filter(|buffer| buffer.contains(argument))
+                .map(|buffer| format!("{} {}", name, buffer))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Writes the modified buffers to their swap file, in the state directory for those
+    /// without a file, so their changes can be recovered if the editor stops without saving
+    /// them.
+    fn write_swap_files(&mut self) {
+        self.keys_since_swap = 0;
+
+        for buffer in &self.buffers {
+            // Bytes edited in hex aren't journaled.
+            if buffer.hex.is_some() {
+                continue;
+            }
+
+            if !buffer.is_dirty() {
+                if let Some(path) = self.swap_files.remove(&buffer.id) {
+                    let _ = fs::remove_file(path);
+                }
+                continue;
+            }
+
+            let previous = self.swap_files.get(&buffer.id).map(PathBuf::as_path);
+            let save_location = buffer.save_location.as_deref();
+            match swap::write(save_location, buffer.id, &buffer.text(), previous) {
+                Ok(path) => {
+                    // A buffer which was given a file since leaves its previous swap file.
+                    if let Some(previous) = self.swap_files.insert(buffer.id, path.clone()) {
+                        if previous != path {
+                            let _ = fs::remove_file(previous);
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.prompt_bar_message = Message::new_error(format!(
+                        "Can't write swap file for \"{}\": {}",
+                        buffer.name(),
+                        e
+                    ));
+                }
+            }
+        }
+    }
+
+    fn remove_swap_file(&mut self, id: usize) {
+        if let Some(path) = self.swap_files.remove(&id) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    fn remove_swap_files(&mut self) {
+        for (_, path) in self.swap_files.drain() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Looks for swap files left by another editor for the buffer at `index`, and asks
+    /// whether to restore their text, compare it with the file or delete them. For a buffer
+    /// without a file, those of the buffers which had none are looked for.
+    fn recover_swap_file(&mut self, index: usize) -> Result<(), io::Error> {
+        // Read-only buffers couldn't take the recovered text anyway.
+        if self.buffers[index].read_only {
+            return Ok(());
+        }
+        let save_location = self.buffers[index].save_location.clone();
+        let name = self.buffers[index].name().to_string();
+        let paths = match &save_location {
+            Some(save_location) => swap::find(save_location),
+            None => swap::find_unnamed(),
+        };
+
+        for path in paths {
+            if self.swap_files.values().any(|ours| *ours == path) {
+                continue;
+            }
+            let Ok(swap) = swap::read(&path) else {
+                continue;
+            };
+            if swap.pid == process::id() {
+                continue;
+            }
+            // Its text is still being changed, and the other editor keeps writing to it.
+            if swap.is_in_use() {
+                if let Some(save_location) = &save_location {
+                    self.prompt_bar_message = Message::new_error(format!(
+                        "\"{}\" is being edited by process {} (swap file \"{}\")",
+                        save_location,
+                        swap.pid,
+                        path.display()
+                    ));
+                }
+                continue;
+            }
+
+            loop {
+                self.prompt_bar_message = Message::new_normal(format!(
+                    "Found swap file \"{}\": (r)estore, (d)iff, (D)elete or (i)gnore?",
+                    path.display()
+                ));
+                self.refresh_screen()?;
+
+                match self.read_key()? {
+                    Key::Char('r') => {
+                        let buffer = &mut self.buffers[index];
+                        buffer.set_text(&swap.text);
+                        // The swap file is kept up to date from now on.
+                        self.swap_files.insert(buffer.id, path.clone());
+                        self.prompt_bar_message = Message::new_normal(format!(
+                            "Restored \"{}\" from its swap file",
+                            name
+                        ));
+                        return Ok(());
+                    }
+                    Key::Char('d') => {
+                        let lines = swap::diff(&self.buffers[index].text(), &swap.text);
+                        self.show_lines(&lines)?;
+                    }
+                    Key::Char('D') => {
+                        self.prompt_bar_message = match fs::remove_file(&path) {
+                            Ok(()) => Message::default(),
+                            Err(e) => Message::new_error(format!(
+                                "Can't delete \"{}\": {}",
+                                path.display(),
+                                e
+                            )),
+                        };
+                        break;
+                    }
+                    _ => {
+                        self.prompt_bar_message = Message::default();
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether every buffer is saved, telling which ones are not otherwise.
+    fn check_unsaved(&mut self) -> bool {
+        let unsaved = self
+            .buffers
+            .iter()
+            .filter(|buffer| buffer.is_dirty())
+            .map(|buffer| format!("{} \"{}\"", buffer.id, buffer.name()))
+            .collect::<Vec<String>>();
+
+        if !unsaved.is_empty() {
+            self.prompt_bar_message = Message::new_error(format!(
+                "No write since last change for {} (add ! to override)",
+                unsaved.join(", ")
+            ));
+        }
+
+        unsaved.is_empty()
+    }
+
+    /// Returns whether the windows at `closing` can be closed without hiding the changes of
+    /// their buffers, telling it can't otherwise: modified buffers have to stay shown in
+    /// another window, in any tab.
+    fn check_unsaved_views(&mut self, closing: &[usize]) -> bool {
+        let other_tabs = self
+            .tabs
+            .iter()
+            .enumerate()
+            .filter(|(tab, _)| *tab != self.current_tab)
+            .flat_map(|(_, tab)| tab.windows.iter());
+        let shown = self
+            .windows
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !closing.contains(index))
+            .map(|(_, window)| window)
+            .chain(other_tabs)
+            .map(|window| window.buffer)
+            .collect::<Vec<usize>>();
+
+        let mut hidden = closing
+            .iter()
+            .map(|window| self.windows[*window].buffer)
+            .filter(|buffer| self.buffers[*buffer].is_dirty() && !shown.contains(buffer))
+            .collect::<Vec<usize>>();
+        hidden.sort_unstable();
+        hidden.dedup();
+        if hidden.is_empty() {
+            return true;
+        }
+
+        let unsaved = hidden
+            .iter()
+            .map(|buffer| {
+                let buffer = &self.buffers[*buffer];
+                format!("{} \"{}\"", buffer.id, buffer.name())
+            })
+            .collect::<Vec<String>>();
+        self.prompt_bar_message = Message::new_error(format!(
+            "No write since last change for {} (add ! to override)",
+            unsaved.join(", ")
+        ));
+        false
+    }
+
+    /// Returns whether the current buffer can be changed, telling it can't otherwise.
+    fn check_modifiable(&mut self) -> bool {
+        if self.buffer().hex.is_some() {
+            self.prompt_bar_message = Message::new_error("Not allowed in hex mode".to_string());
+            return false;
+        }
+        if self.buffer().read_only {
+            self.prompt_bar_message = Message::new_error("Buffer is read-only".to_string());
+        }
+
+        !self.buffer().read_only
+    }
+
+    /// Returns whether the bytes of the current buffer can be changed, telling they can't
+    /// otherwise.
+    fn check_hex_modifiable(&mut self) -> bool {
+        if self.buffer().read_only {
+            self.prompt_bar_message = Message::new_error("Buffer is read-only".to_string());
+        }
+
+        !self.buffer().read_only
+    }
+
+    /// Asks `question` and returns whether it was answered with `y`.
+    fn confirm(&mut self, question: &str) -> Result<bool, io::Error> {
+        self.prompt_bar_message = Message::new_normal(format!("{} (y/n)", question));
+        self.refresh_screen()?;
+
+        let answer = self.read_key()?;
+        self.prompt_bar_message = Message::default();
+
+        Ok(answer == Key::Char('y'))
+    }
+
+    /// Shows `lines` over the bottom of the screen, a page at a time, each until a key is
+    /// pressed.
+    fn show_lines(&mut self, lines: &[String]) -> Result<(), io::Error> {
+        let width = self.terminal_size.0 as usize;
+        let bottom = self.terminal_size.1 as usize + 1;
+
+        for page in lines.chunks(bottom.max(1)) {
+            self.prompt_bar_message = Message::default();
+            self.refresh_screen()?;
+
+            termutils::hide_cursor();
+            for (index, line) in page.iter().enumerate() {
+                termutils::set_cursor_position(&Position {
+                    x: 0,
+                    y: bottom - page.len() + index,
+                });
+                termutils::clear_line();
+                print!("{}", truncate_to_width(line, width));
+            }
+
+            termutils::set_cursor_position(&Position { x: 0, y: bottom });
+            termutils::clear_line();
+            print!(
+                "{}",
+                Message::new_normal("Press any key to continue".to_string()).render(&self.theme)
+            );
+            termutils::show_cursor();
+            io::stdout().flush()?;
+
+            self.read_key()?;
+        }
+
+        Ok(())
+    }
+
+    /// Shows the buffer at `index` in the current window.
+    fn show_buffer(&mut self, index: usize) {
+        self.windows[self.current_window].show_buffer(index);
+        self.set_cursor_position(self.windows[self.current_window].cursor);
+    }
+
+    /// Runs the `Ctrl-w` command typed next, which acts on the windows.
+    fn window_command(&mut self, count: Option<usize>) -> Result<(), io::Error> {
+        let key = self.read_key()?;
+        let amount = count.unwrap_or(1) as isize;
+
+        match key {
+            Key::Char('h') | Key::Ctrl('h') | Key::Left | Key::Backspace => {
+                self.focus_neighbour(Direction::Left)
+            }
+            Key::Char('j') | Key::Ctrl('j') | Key::Down => self.focus_neighbour(Direction::Down),
+            Key::Char('k') | Key::Ctrl('k') | Key::Up => self.focus_neighbour(Direction::Up),
+            Key::Char('l') | Key::Ctrl('l') | Key::Right => self.focus_neighbour(Direction::Right),
+            Key::Char('w') | Key::Ctrl('w') => self.focus_next_window(true),
+            Key::Char('W') => self.focus_next_window(false),
+            Key::Char('s') | Key::Char('S') | Key::Ctrl('s') => {
+                self.split_window(SplitDirection::Horizontal);
+            }
+            Key::Char('v') | Key::Ctrl('v') => {
+                self.split_window(SplitDirection::Vertical);
+            }
+            Key::Char('c') => self.close_window(false),
+            Key::Char('q') | Key::Ctrl('q') => self.command_quit(&["quit"])?,
+            Key::Char('o') | Key::Ctrl('o') => self.only_window(false),
+            Key::Char('+') => self.resize_window(SplitDirection::Horizontal, amount),
+            Key::Char('-') => self.resize_window(SplitDirection::Horizontal, -amount),
+            Key::Char('>') => self.resize_window(SplitDirection::Vertical, amount),
+            Key::Char('<') => self.resize_window(SplitDirection::Vertical, -amount),
+            Key::Char('=') => self.layout.equalize(self.window_area()),
+            Key::Esc => (),
+            _ => self.pending_keys.clear(),
+        }
+
+        Ok(())
+    }
+
+    /// Splits the current window in two, both showing its buffer, and moves to the new one.
+    /// Returns `false` when there is not enough room.
+    fn split_window(&mut self, direction: SplitDirection) -> bool {
+        let window = self.windows.len();
+        let rect = self.window_rect(self.current_window);
+        if !self
+            .layout
+            .split(self.current_window, window, direction, rect)
+        {
+            self.prompt_bar_message = Message::new_error("Not enough room".to_string());
+            return false;
+        }
+
+        self.windows.push(self.windows[self.current_window].clone());
+        self.current_window = window;
+        self.scroll();
+        true
+    }
+
+    /// Closes the current window, along with its tab if it is the last one in it. Without
+    /// `force`, the last window showing a modified buffer is kept.
+    fn close_window(&mut self, force: bool) {
+        if self.windows.len() == 1 {
+            if self.tabs.len() > 1 {
+                self.close_tab(force);
+            } else {
+                self.prompt_bar_message =
+                    Message::new_error("Cannot close last window".to_string());
+            }
+            return;
+        }
+        if !force && !self.check_unsaved_views(&[self.current_window]) {
+            return;
+        }
+
+        // Move to the window before the closed one, or the one after it if it was first.
+        let order = self.layout.windows();
+        let position = order
+            .iter()
+            .position(|window| *window == self.current_window)
+            .unwrap_or_default();
+        let next = if position > 0 {
+            order[position - 1]
+        } else {
+            order[1]
+        };
+
+        self.layout.remove(self.current_window);
+        self.windows.remove(self.current_window);
+        self.focus_window(if next > self.current_window {
+            next - 1
+        } else {
+            next
+        });
+    }
+
+    /// Closes every window but the current one. Without `force`, none is closed when one of
+    /// them is the last showing a modified buffer.
+    fn only_window(&mut self, force: bool) {
+        let others = (0..self.windows.len())
+            .filter(|window| *window != self.current_window)
+            .collect::<Vec<usize>>();
+        if !force && !self.check_unsaved_views(&others) {
+            return;
+        }
+
+        let window = self.windows.swap_remove(self.current_window);
+        self.windows = vec![window];
+        self.layout.only(0);
+        self.current_window = 0;
+    }
+
+    fn resize_window(&mut self, direction: SplitDirection, delta: isize) {
+        self.layout.resize(self.current_window, direction, delta);
+    }
+
+    fn focus_neighbour(&mut self, direction: Direction) {
+        let cursor = self.cursor_screen_position();
+        match self
+            .layout
+            .neighbour(self.current_window, direction, cursor, self.window_area())
+        {
+            Some(window) => self.focus_window(window),
+            None => self.pending_keys.clear(),
+        }
+    }
+
+    /// Moves to the window after the current one in the layout, or before it when `forward`
+    /// is `false`, wrapping around.
+    fn focus_next_window(&mut self, forward: bool) {
+        let order = self.layout.windows();
+        let position = order
+            .iter()
+            .position(|window| *window == self.current_window)
+            .unwrap_or_default();
+        let next = if forward {
+            (position + 1) % order.len()
+        } else {
+            position.checked_sub(1).unwrap_or(order.len() - 1)
+        };
+
+        self.focus_window(order[next]);
+    }
+
+    /// Moves the windows of the current tab back into it, before another tab is shown.
+    fn store_tab(&mut self) {
+        self.tabs[self.current_tab] = Tab {
+            windows: std::mem::take(&mut self.windows),
+            layout: std::mem::take(&mut self.layout),
+            current_window: self.current_window,
+        };
+    }
+
+    /// Shows the tab at `index`, whose windows are moved out of it.
+    fn load_tab(&mut self, index: usize) {
+        let tab = std::mem::take(&mut self.tabs[index]);
+        self.windows = tab.windows;
+        self.layout = tab.layout;
+        self.current_tab = index;
+        self.focus_window(tab.current_window);
+    }
+
+    fn focus_tab(&mut self, index: usize) {
+        if index != self.current_tab {
+            self.store_tab();
+            self.load_tab(index);
+        }
+    }
+
+    /// Moves `count` tabs after the current one, or before it when `forward` is `false`,
+    /// wrapping around.
+    fn next_tab(&mut self, count: usize, forward: bool) {
+        let len = self.tabs.len();
+        let count = count % len;
+        let index = if forward {
+            (self.current_tab + count) % len
+        } else {
+            (self.current_tab + len - count) % len
+        };
+
+        self.focus_tab(index);
+    }
+
+    /// Moves to the tab numbered `number`, counting from 1.
+    fn go_to_tab(&mut self, number: usize) {
+        if number == 0 || number > self.tabs.len() {
+            self.prompt_bar_message = Message::new_error(format!("No tab page {}", number));
+            self.pending_keys.clear();
+            return;
+        }
+
+        self.focus_tab(number - 1);
+    }
+
+    /// Closes the current tab, which must not be the last one, and moves to the tab which
+    /// takes its place.
+    /// Closes the current tab. Without `force`, it is kept when one of its windows is the
+    /// last showing a modified buffer.
+    fn close_tab(&mut self, force: bool) {
+        let windows = (0..self.windows.len()).collect::<Vec<usize>>();
+        if !force && !self.check_unsaved_views(&windows) {
+            return;
+        }
+
+        self.tabs.remove(self.current_tab);
+        self.load_tab(self.current_tab.min(self.tabs.len() - 1));
+    }
+
+    fn focus_window(&mut self, window: usize) {
+        self.current_window = window;
+        // The buffer may have been changed from another window in the meantime.
+        self.set_cursor_position(self.windows[window].cursor);
+        self.scroll();
+    }
+}
+
+/// Cuts `text` short so it takes at most `width` columns.
+fn truncate_to_width(text: &str, width: usize) -> String {
+    text.graphemes(true)
+        .scan(0, |column, grapheme| {
+            *column += grapheme.width();
+            (*column <= width).then_some(grapheme)
+        })
+        .collect()
+}
+
+fn toggle_case(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_uppercase() {
+                c.to_lowercase().to_string()
+            } else {
+                c.to_uppercase().to_string()
+            }
+        })
+        .collect()
 }
@@ -0,0 +1,343 @@
+/// The character encodings files can be read and written in.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+/// The characters Windows-1252 puts at bytes 0x80 to 0x9F instead of the C1 control codes
+/// of Latin-1. The bytes it leaves undefined are read as those control codes.
+const WINDOWS_1252: [Option<char>; 32] = [
+    Some('€'),
+    None,
+    Some('‚'),
+    Some('ƒ'),
+    Some('„'),
+    Some('…'),
+    Some('†'),
+    Some('‡'),
+    Some('ˆ'),
+    Some('‰'),
+    Some('Š'),
+    Some('‹'),
+    Some('Œ'),
+    None,
+    Some('Ž'),
+    None,
+    None,
+    Some('‘'),
+    Some('’'),
+    Some('“'),
+    Some('”'),
+    Some('•'),
+    Some('–'),
+    Some('—'),
+    Some('˜'),
+    Some('™'),
+    Some('š'),
+    Some('›'),
+    Some('œ'),
+    None,
+    Some('ž'),
+    Some('Ÿ'),
+];
+
+impl Encoding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Self::Utf8),
+            "utf-16le" | "utf16le" => Some(Self::Utf16Le),
+            "utf-16be" | "utf16be" | "utf-16" | "utf16" => Some(Self::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Self::Latin1),
+            "cp1252" | "windows-1252" => Some(Self::Windows1252),
+            _ => None,
+        }
+    }
+
+    /// Returns the byte order mark of the encoding, which Latin-1 and Windows-1252 have
+    /// none of.
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Self::Utf8 => &[0xef, 0xbb, 0xbf],
+            Self::Utf16Le => &[0xff, 0xfe],
+            Self::Utf16Be => &[0xfe, 0xff],
+            Self::Latin1 | Self::Windows1252 => &[],
+        }
+    }
+
+    /// Guesses the encoding of `bytes` and whether they start with its byte order mark.
+    /// Returns `None` when they don't look like text.
+    pub fn detect(bytes: &[u8]) -> Option<(Self, bool)> {
+        for encoding in [Self::Utf8, Self::Utf16Le, Self::Utf16Be] {
+            if bytes.starts_with(encoding.bom()) {
+                return Some((encoding, true));
+            }
+        }
+
+        if let Some(encoding) = detect_utf16(bytes) {
+            return Some((encoding, false));
+        }
+
+        // Text hardly ever holds null bytes or many control codes.
+        let controls = bytes
+            .iter()
+            .filter(|byte| **byte < 0x20 && !b"\t\n\r\x0c\x1b".contains(byte))
+            .count();
+        if bytes.contains(&0) || controls * 10 > bytes.len() {
+            return None;
+        }
+
+        if std::str::from_utf8(bytes).is_ok() {
+            Some((Self::Utf8, false))
+        } else if bytes.iter().any(|byte| (0x80..0xa0).contains(byte)) {
+            Some((Self::Windows1252, false))
+        } else {
+            Some((Self::Latin1, false))
+        }
+    }
+
+    /// Decodes `bytes`, which don't include the byte order mark. Returns `None` when they
+    /// are not valid in the encoding.
+    pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+        match self {
+            Self::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            Self::Utf16Le | Self::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    return None;
+                }
+
+                let units = bytes.chunks(2).map(|pair| match self {
+                    Self::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                });
+                char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .ok()
+            }
+            Self::Latin1 => Some(bytes.iter().map(|byte| *byte as char).collect()),
+            Self::Windows1252 => Some(
+                bytes
+                    .iter()
+                    .map(|byte| match byte {
+                        0x80..=0x9f => {
+                            WINDOWS_1252[(byte - 0x80) as usize].unwrap_or(*byte as char)
+                        }
+                        _ => *byte as char,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Encodes `text`, without the byte order mark. Returns the first character the
+    /// encoding can't represent if there is one.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, char> {
+        match self {
+            Self::Utf8 => Ok(text.as_bytes().to_vec()),
+            Self::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Self::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Self::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c).map_err(|_| c))
+                .collect(),
+            Self::Windows1252 => text
+                .chars()
+                .map(|c| {
+                    if let Some(index) = WINDOWS_1252.iter().position(|mapped| *mapped == Some(c)) {
+                        return Ok(0x80 + index as u8);
+                    }
+
+                    match u8::try_from(c) {
+                        // Only the control codes Windows-1252 leaves undefined round-trip.
+                        Ok(byte @ 0x80..=0x9f)
+                            if WINDOWS_1252[(byte - 0x80) as usize].is_none() =>
+                        {
+                            Ok(byte)
+                        }
+                        Ok(0x80..=0x9f) | Err(_) => Err(c),
+                        Ok(byte) => Ok(byte),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Utf8 => write!(f, "utf-8"),
+            Self::Utf16Le => write!(f, "utf-16le"),
+            Self::Utf16Be => write!(f, "utf-16be"),
+            Self::Latin1 => write!(f, "latin1"),
+            Self::Windows1252 => write!(f, "cp1252"),
+        }
+    }
+}
+
+/// Recognizes UTF-16 without a byte order mark by the null bytes mostly ASCII text has on
+/// one side of each pair of bytes.
+fn detect_utf16(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.len() < 4 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let pairs = bytes.len() / 2;
+    let even_nulls = bytes.iter().step_by(2).filter(|byte| **byte == 0).count();
+    let odd_nulls = bytes
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|byte| **byte == 0)
+        .count();
+
+    let encoding = if odd_nulls * 10 >= pairs * 3 && even_nulls * 10 < pairs {
+        Encoding::Utf16Le
+    } else if even_nulls * 10 >= pairs * 3 && odd_nulls * 10 < pairs {
+        Encoding::Utf16Be
+    } else {
+        return None;
+    };
+
+    // Only take it for UTF-16 if it decodes as such.
+    encoding.decode(bytes).map(|_| encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODINGS: [Encoding; 5] = [
+        Encoding::Utf8,
+        Encoding::Utf16Le,
+        Encoding::Utf16Be,
+        Encoding::Latin1,
+        Encoding::Windows1252,
+    ];
+
+    fn detect(bytes: &[u8]) -> Option<(String, bool)> {
+        Encoding::detect(bytes).map(|(encoding, bom)| (encoding.to_string(), bom))
+    }
+
+    fn with_bom(encoding: Encoding, text: &str) -> Vec<u8> {
+        let mut bytes = encoding.bom().to_vec();
+        bytes.extend(encoding.encode(text).unwrap());
+        bytes
+    }
+
+    #[test]
+    fn parse_names() {
+        for encoding in ENCODINGS {
+            assert!(Encoding::parse(&encoding.to_string()) == Some(encoding));
+        }
+        assert!(Encoding::parse("UTF8") == Some(Encoding::Utf8));
+        assert!(Encoding::parse("utf-16") == Some(Encoding::Utf16Be));
+        assert!(Encoding::parse("ISO-8859-1") == Some(Encoding::Latin1));
+        assert!(Encoding::parse("windows-1252") == Some(Encoding::Windows1252));
+        assert!(Encoding::parse("ebcdic").is_none());
+    }
+
+    #[test]
+    fn detect_byte_order_marks() {
+        let text = "héllo\n";
+        assert_eq!(
+            detect(&with_bom(Encoding::Utf8, text)).unwrap(),
+            ("utf-8".into(), true)
+        );
+        assert_eq!(
+            detect(&with_bom(Encoding::Utf16Le, text)).unwrap(),
+            ("utf-16le".into(), true)
+        );
+        assert_eq!(
+            detect(&with_bom(Encoding::Utf16Be, text)).unwrap(),
+            ("utf-16be".into(), true)
+        );
+    }
+
+    #[test]
+    fn detect_without_byte_order_marks() {
+        let text = "plain text, with a few words\n";
+        assert_eq!(detect(text.as_bytes()).unwrap(), ("utf-8".into(), false));
+        assert_eq!(
+            detect("naïve €\n".as_bytes()).unwrap(),
+            ("utf-8".into(), false)
+        );
+        assert_eq!(detect(b"").unwrap(), ("utf-8".into(), false));
+
+        let utf16le = Encoding::Utf16Le.encode(text).unwrap();
+        assert_eq!(detect(&utf16le).unwrap(), ("utf-16le".into(), false));
+        let utf16be = Encoding::Utf16Be.encode(text).unwrap();
+        assert_eq!(detect(&utf16be).unwrap(), ("utf-16be".into(), false));
+
+        assert_eq!(detect(b"caf\xe9\n").unwrap(), ("latin1".into(), false));
+        assert_eq!(
+            detect(b"\x93quoted\x94\n").unwrap(),
+            ("cp1252".into(), false)
+        );
+    }
+
+    #[test]
+    fn detect_binary() {
+        assert!(detect(b"\x7fELF\x02\x01\x01\x00\x00\x00").is_none());
+        assert!(detect(b"text\0with a null").is_none());
+        assert!(detect(b"\x01\x02\x03\x04\x05abc").is_none());
+        // Escapes and form feeds are found in text.
+        assert!(detect(b"\x1b[1mbold\x1b[0m\x0c\n").is_some());
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = "Tab\there, caf\u{e9} \u{a9} \u{ff}\r\n";
+        for encoding in ENCODINGS {
+            let bytes = encoding.encode(text).unwrap();
+            assert_eq!(encoding.decode(&bytes).unwrap(), text, "{}", encoding);
+        }
+
+        let text = "\u{20ac}\u{2018}quoted\u{2019} \u{153}uvre\u{2026}";
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+            let bytes = encoding.encode(text).unwrap();
+            assert_eq!(encoding.decode(&bytes).unwrap(), text, "{}", encoding);
+        }
+        let bytes = Encoding::Windows1252.encode(text).unwrap();
+        assert_eq!(bytes[..3], [0x80, 0x91, b'q']);
+        assert_eq!(Encoding::Windows1252.decode(&bytes).unwrap(), text);
+
+        // Characters outside of the basic plane take two units in UTF-16.
+        let bytes = Encoding::Utf16Le.encode("\u{1f600}").unwrap();
+        assert_eq!(bytes, [0x3d, 0xd8, 0x00, 0xde]);
+        assert_eq!(Encoding::Utf16Le.decode(&bytes).unwrap(), "\u{1f600}");
+    }
+
+    #[test]
+    fn every_byte_round_trips() {
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        for encoding in [Encoding::Latin1, Encoding::Windows1252] {
+            let text = encoding.decode(&bytes).unwrap();
+            assert_eq!(encoding.encode(&text).unwrap(), bytes, "{}", encoding);
+        }
+    }
+
+    #[test]
+    fn unencodable_characters() {
+        assert_eq!(Encoding::Latin1.encode("a\u{20ac}b"), Err('\u{20ac}'));
+        assert_eq!(Encoding::Windows1252.encode("a\u{100}b"), Err('\u{100}'));
+        // The C1 control codes Windows-1252 replaces can't be written in it.
+        assert_eq!(Encoding::Windows1252.encode("\u{80}"), Err('\u{80}'));
+        assert_eq!(Encoding::Windows1252.encode("\u{81}"), Ok(vec![0x81]));
+        assert!(Encoding::Utf16Le.encode("\u{10ffff}").is_ok());
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        assert!(Encoding::Utf8.decode(b"\xff\xfe").is_none());
+        assert!(Encoding::Utf8.decode(b"\xc3").is_none());
+        assert!(Encoding::Utf16Le.decode(b"a\0b").is_none());
+        // An unpaired surrogate.
+        assert!(Encoding::Utf16Le.decode(&[0x3d, 0xd8, b'a', 0]).is_none());
+        assert!(Encoding::Utf16Be.decode(&[0xdc, 0x00]).is_none());
+    }
+}
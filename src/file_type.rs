@@ -1,7 +1,8 @@
 use crate::highlighting::HighlightingOptions;
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub enum FileType {
+    #[default]
     PlainText,
     Rust,
     Golang,
@@ -17,7 +18,7 @@ impl From<&str> for FileType {
 
 impl From<String> for FileType {
     fn from(value: String) -> Self {
-        let extension = value.split('.').last().unwrap_or_default();
+        let extension = value.split('.').next_back().unwrap_or_default();
         if extension.is_empty() {
             return Self::PlainText;
         }
@@ -32,11 +33,11 @@ impl From<String> for FileType {
     }
 }
 
-impl Into<HighlightingOptions> for FileType {
-    fn into(self) -> HighlightingOptions {
+impl From<FileType> for HighlightingOptions {
+    fn from(value: FileType) -> Self {
         use FileType::*;
 
-        match self {
+        match value {
             PlainText => HighlightingOptions {
                 highlight_numbers: false,
                 highlight_strings: None,
@@ -49,12 +50,6 @@ impl Into<HighlightingOptions> for FileType {
     }
 }
 
-impl Default for FileType {
-    fn default() -> Self {
-        Self::PlainText
-    }
-}
-
 impl std::fmt::Display for FileType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use FileType::*;
@@ -0,0 +1,300 @@
+use crate::{
+    buffer::SearchDirection,
+    theme::{Group, Theme},
+};
+use termion::color::{Bg, Reset};
+
+/// The number of bytes shown on each row.
+pub const BYTES_PER_ROW: usize = 16;
+
+/// The width of the offset column and the spaces after it.
+const OFFSET_WIDTH: usize = 10;
+
+/// The raw bytes of a buffer, edited in place and shown like `hexdump -C` does: an offset
+/// column, the bytes in hex, and their printable ASCII characters.
+pub struct HexView {
+    bytes: Vec<u8>,
+    /// Each edit as the offset of the byte and the value it replaced.
+    undo: Vec<(usize, u8)>,
+    redo: Vec<(usize, u8)>,
+    /// How many edits could be undone when the bytes were last saved, or `None` when that
+    /// state can't be reached anymore.
+    saved: Option<usize>,
+}
+
+impl HexView {
+    /// Shows `bytes`, which are those of the file when `saved` is set.
+    pub fn new(bytes: Vec<u8>, saved: bool) -> Self {
+        Self {
+            bytes,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            saved: saved.then_some(0),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.bytes.len().div_ceil(BYTES_PER_ROW)
+    }
+
+    pub fn get(&self, offset: usize) -> Option<u8> {
+        self.bytes.get(offset).copied()
+    }
+
+    pub fn set(&mut self, offset: usize, byte: u8) {
+        let Some(previous) = self.bytes.get(offset).copied() else {
+            return;
+        };
+        if previous == byte {
+            return;
+        }
+
+        if self.saved.is_some_and(|saved| saved > self.undo.len()) {
+            self.saved = None;
+        }
+        self.bytes[offset] = byte;
+        self.undo.push((offset, previous));
+        self.redo.clear();
+    }
+
+    /// Reverts the last edit and returns the offset of its byte.
+    pub fn undo(&mut self) -> Option<usize> {
+        let (offset, byte) = self.undo.pop()?;
+        self.redo.push((offset, self.bytes[offset]));
+        self.bytes[offset] = byte;
+        Some(offset)
+    }
+
+    /// Reapplies the last undone edit and returns the offset of its byte.
+    pub fn redo(&mut self) -> Option<usize> {
+        let (offset, byte) = self.redo.pop()?;
+        self.undo.push((offset, self.bytes[offset]));
+        self.bytes[offset] = byte;
+        Some(offset)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.undo.len());
+    }
+
+    /// Returns whether the bytes differ from the file.
+    pub fn is_modified(&self) -> bool {
+        self.saved != Some(self.undo.len())
+    }
+
+    /// Returns whether the bytes differ from those the view was opened with.
+    pub fn is_changed(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Finds `pattern`, in which `None` matches any byte, starting next to `offset` and
+    /// wrapping around the end.
+    pub fn find(
+        &self,
+        pattern: &[Option<u8>],
+        offset: usize,
+        direction: SearchDirection,
+    ) -> Option<usize> {
+        if pattern.is_empty() || pattern.len() > self.bytes.len() {
+            return None;
+        }
+
+        let matches = |start: &usize| {
+            pattern
+                .iter()
+                .zip(&self.bytes[*start..])
+                .all(|(expected, byte)| expected.is_none_or(|expected| expected == *byte))
+        };
+
+        let starts = self.bytes.len() - pattern.len() + 1;
+        match direction {
+            SearchDirection::Forward => (offset + 1..starts)
+                .chain(0..(offset + 1).min(starts))
+                .find(matches),
+            SearchDirection::Backward => (0..offset.min(starts))
+                .rev()
+                .chain((offset.min(starts)..starts).rev())
+                .find(matches),
+        }
+    }
+
+    /// Renders the row of bytes starting at `row * BYTES_PER_ROW`, from the screen column
+    /// `start` to `end`. The character of the byte at `cursor` is highlighted in the ASCII
+    /// column.
+    pub fn render(
+        &self,
+        row: usize,
+        start: usize,
+        end: usize,
+        cursor: Option<usize>,
+        theme: &Theme,
+    ) -> String {
+        let first = row * BYTES_PER_ROW;
+        let bytes =
+            &self.bytes[first.min(self.bytes.len())..(first + BYTES_PER_ROW).min(self.bytes.len())];
+
+        let mut cells: Vec<(char, bool)> = format!("{:08x}  ", first)
+            .chars()
+            .map(|c| (c, false))
+            .collect();
+        for index in 0..BYTES_PER_ROW {
+            let hex = match bytes.get(index) {
+                Some(byte) => format!("{:02x} ", byte),
+                None => "   ".to_string(),
+            };
+            cells.extend(hex.chars().map(|c| (c, false)));
+            if index == BYTES_PER_ROW / 2 - 1 {
+                cells.push((' ', false));
+            }
+        }
+        cells.extend([(' ', false), ('|', false)]);
+        for (index, byte) in bytes.iter().enumerate() {
+            let c = match byte {
+                b' '..=b'~' => *byte as char,
+                _ => '.',
+            };
+            cells.push((c, cursor == Some(first + index)));
+        }
+        cells.push(('|', false));
+
+        let mut result = String::new();
+        for (c, highlighted) in cells.iter().skip(start).take(end.saturating_sub(start)) {
+            if *highlighted {
+                result.push_str(&format!(
+                    "{}{}{}",
+                    Bg(theme.color(Group::Selection)),
+                    c,
+                    Bg(Reset)
+                ));
+            } else {
+                result.push(*c);
+            }
+        }
+        let width = cells
+            .len()
+            .saturating_sub(start)
+            .min(end.saturating_sub(start));
+        result.push_str(&" ".repeat(end.saturating_sub(start) - width));
+
+        result
+    }
+}
+
+/// Returns the screen column of the first hex digit of the byte at `index` in its row.
+pub fn column(index: usize) -> usize {
+    OFFSET_WIDTH + index * 3 + usize::from(index >= BYTES_PER_ROW / 2)
+}
+
+/// Parses a pattern of hex bytes such as `de ad be ef` or `cafe??00`, where `??` matches
+/// any byte.
+pub fn parse_pattern(text: &str) -> Option<Vec<Option<u8>>> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| match pair {
+            ['?', '?'] => Some(None),
+            [high, low] => Some(Some((high.to_digit(16)? * 16 + low.to_digit(16)?) as u8)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BYTES: [u8; 9] = [0xde, 0xad, 0xbe, 0xef, 0x00, 0xde, 0xad, 0x00, 0xde];
+
+    fn find(pattern: &str, offset: usize, direction: SearchDirection) -> Option<usize> {
+        let view = HexView::new(BYTES.to_vec(), true);
+        view.find(&parse_pattern(pattern).unwrap(), offset, direction)
+    }
+
+    #[test]
+    fn parse_patterns() {
+        assert_eq!(
+            parse_pattern("de ad be ef"),
+            Some(vec![Some(0xde), Some(0xad), Some(0xbe), Some(0xef)])
+        );
+        assert_eq!(
+            parse_pattern("CAfe??00"),
+            Some(vec![Some(0xca), Some(0xfe), None, Some(0x00)])
+        );
+        assert_eq!(parse_pattern(" ? ? "), Some(vec![None]));
+    }
+
+    #[test]
+    fn parse_invalid_patterns() {
+        assert_eq!(parse_pattern(""), None);
+        assert_eq!(parse_pattern("  "), None);
+        assert_eq!(parse_pattern("abc"), None);
+        assert_eq!(parse_pattern("zz"), None);
+        assert_eq!(parse_pattern("?a"), None);
+        assert_eq!(parse_pattern("a?"), None);
+        assert_eq!(parse_pattern("éé"), None);
+    }
+
+    #[test]
+    fn find_forward() {
+        assert_eq!(find("de ad", 0, SearchDirection::Forward), Some(5));
+        assert_eq!(find("00", 4, SearchDirection::Forward), Some(7));
+        // The last possible start of the pattern is searched too.
+        assert_eq!(find("00 de", 4, SearchDirection::Forward), Some(7));
+        assert_eq!(find("ff", 0, SearchDirection::Forward), None);
+    }
+
+    #[test]
+    fn find_backward() {
+        assert_eq!(find("de ad", 5, SearchDirection::Backward), Some(0));
+        assert_eq!(find("00", 8, SearchDirection::Backward), Some(7));
+        assert_eq!(find("ff", 8, SearchDirection::Backward), None);
+    }
+
+    #[test]
+    fn find_wraps_around() {
+        assert_eq!(find("de ad", 5, SearchDirection::Forward), Some(0));
+        assert_eq!(find("de ad", 0, SearchDirection::Backward), Some(5));
+        // The only match is found again at the offset it starts from.
+        assert_eq!(find("be", 2, SearchDirection::Forward), Some(2));
+        assert_eq!(find("be", 2, SearchDirection::Backward), Some(2));
+    }
+
+    #[test]
+    fn find_wildcards() {
+        assert_eq!(find("de ??", 0, SearchDirection::Forward), Some(5));
+        assert_eq!(find("?? 00", 3, SearchDirection::Forward), Some(6));
+        assert_eq!(find("?? 00", 6, SearchDirection::Forward), Some(3));
+        assert_eq!(find("?? 00", 3, SearchDirection::Backward), Some(6));
+        assert_eq!(find("?? 00", 6, SearchDirection::Backward), Some(3));
+        // A wildcard doesn't match past the end of the bytes.
+        assert_eq!(find("de ?? ??", 6, SearchDirection::Forward), Some(0));
+        assert_eq!(find("?? ??", 7, SearchDirection::Forward), Some(0));
+        assert_eq!(find("?? ??", 0, SearchDirection::Backward), Some(7));
+    }
+
+    #[test]
+    fn find_longer_than_the_bytes() {
+        assert_eq!(
+            find(&"??".repeat(BYTES.len()), 0, SearchDirection::Forward),
+            Some(0)
+        );
+        assert_eq!(
+            find(&"??".repeat(BYTES.len() + 1), 0, SearchDirection::Forward),
+            None
+        );
+        let view = HexView::new(Vec::new(), true);
+        assert_eq!(view.find(&[None], 0, SearchDirection::Backward), None);
+    }
+}
@@ -1,9 +1,11 @@
-use termion::color::{Color, Rgb};
+use crate::theme::{Group, TermColor, Theme};
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Default)]
 pub enum HighlightType {
     Number,
     String,
+    Match,
+    #[default]
     None,
 }
 
@@ -13,14 +15,15 @@ pub struct HighlightingOptions {
 }
 
 impl HighlightType {
-    pub fn to_color(&self) -> impl Color {
+    pub fn to_color(&self, theme: &Theme) -> TermColor {
         use HighlightType::*;
 
-        match self {
-            Number => Rgb(220, 163, 163),
-            String => Rgb(211, 54, 130),
-            None => Rgb(255, 255, 255),
-        }
+        theme.color(match self {
+            Number => Group::Number,
+            String => Group::String,
+            Match => Group::Match,
+            None => Group::Text,
+        })
     }
 }
 
@@ -41,9 +44,3 @@ impl HighlightingOptions {
         }
     }
 }
-
-impl Default for HighlightType {
-    fn default() -> Self {
-        Self::None
-    }
-}
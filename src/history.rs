@@ -0,0 +1,283 @@
+use crate::cursor::Position;
+
+/// A single primitive edit, recorded with enough information to be reverted. `index` is
+/// the char index the edit happened at, `at` is where the cursor goes when it is undone.
+#[derive(Clone)]
+pub enum Change {
+    Insert {
+        at: Position,
+        index: usize,
+        text: String,
+    },
+    Delete {
+        at: Position,
+        index: usize,
+        text: String,
+    },
+}
+
+/// A group of changes that is undone and redone as a single step.
+#[derive(Clone)]
+pub struct Transaction {
+    id: usize,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    pending: Option<Transaction>,
+    next_id: usize,
+    saved_id: usize,
+}
+
+impl Change {
+    pub fn position(&self) -> Position {
+        match self {
+            Change::Insert { at, .. } | Change::Delete { at, .. } => *at,
+        }
+    }
+}
+
+impl History {
+    /// Starts grouping every following change into one transaction until `end` is called.
+    pub fn begin(&mut self) {
+        if self.pending.is_none() {
+            self.pending = Some(self.new_transaction());
+        }
+    }
+
+    pub fn end(&mut self) {
+        if let Some(transaction) = self.pending.take() {
+            if !transaction.changes.is_empty() {
+                self.undo_stack.push(transaction);
+            }
+        }
+    }
+
+    pub fn record(&mut self, change: Change) {
+        self.redo_stack.clear();
+
+        if let Some(transaction) = &mut self.pending {
+            transaction.changes.push(change);
+            return;
+        }
+
+        let mut transaction = self.new_transaction();
+        transaction.changes.push(change);
+        self.undo_stack.push(transaction);
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Transaction> {
+        self.end();
+        self.undo_stack.pop()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Transaction> {
+        self.end();
+        self.redo_stack.pop()
+    }
+
+    pub fn push_undo(&mut self, transaction: Transaction) {
+        self.undo_stack.push(transaction);
+    }
+
+    pub fn push_redo(&mut self, transaction: Transaction) {
+        self.redo_stack.push(transaction);
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.end();
+        self.saved_id = self.current_id();
+    }
+
+    pub fn is_modified(&self) -> bool {
+        if let Some(transaction) = &self.pending {
+            if !transaction.changes.is_empty() {
+                return true;
+            }
+        }
+
+        self.current_id() != self.saved_id
+    }
+
+    fn current_id(&self) -> usize {
+        self.undo_stack.last().map(|t| t.id).unwrap_or_default()
+    }
+
+    fn new_transaction(&mut self) -> Transaction {
+        self.next_id += 1;
+        Transaction {
+            id: self.next_id,
+            changes: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(text: &str) -> Change {
+        Change::Insert {
+            at: Position::default(),
+            index: 0,
+            text: text.to_string(),
+        }
+    }
+
+    fn texts(transaction: &Transaction) -> Vec<&str> {
+        transaction
+            .changes
+            .iter()
+            .map(|change| match change {
+                Change::Insert { text, .. } | Change::Delete { text, .. } => text.as_str(),
+            })
+            .collect()
+    }
+
+    /// Undoes the last transaction the way buffers do, returning its changes.
+    fn undo(history: &mut History) -> Option<Vec<String>> {
+        let transaction = history.pop_undo()?;
+        let changes = texts(&transaction)
+            .iter()
+            .map(|text| text.to_string())
+            .collect();
+        history.push_redo(transaction);
+        Some(changes)
+    }
+
+    fn redo(history: &mut History) -> Option<Vec<String>> {
+        let transaction = history.pop_redo()?;
+        let changes = texts(&transaction)
+            .iter()
+            .map(|text| text.to_string())
+            .collect();
+        history.push_undo(transaction);
+        Some(changes)
+    }
+
+    #[test]
+    fn changes_outside_of_transactions_are_undone_one_by_one() {
+        let mut history = History::default();
+        history.record(insert("a"));
+        history.record(insert("b"));
+
+        assert_eq!(undo(&mut history).unwrap(), ["b"]);
+        assert_eq!(undo(&mut history).unwrap(), ["a"]);
+        assert!(undo(&mut history).is_none());
+    }
+
+    #[test]
+    fn transactions_group_changes() {
+        let mut history = History::default();
+        history.begin();
+        history.record(insert("a"));
+        // Beginning again doesn't start another transaction.
+        history.begin();
+        history.record(insert("b"));
+        history.end();
+        history.record(insert("c"));
+
+        assert_eq!(undo(&mut history).unwrap(), ["c"]);
+        assert_eq!(undo(&mut history).unwrap(), ["a", "b"]);
+        assert!(undo(&mut history).is_none());
+        assert_eq!(redo(&mut history).unwrap(), ["a", "b"]);
+    }
+
+    #[test]
+    fn empty_transactions_are_dropped() {
+        let mut history = History::default();
+        history.record(insert("a"));
+        history.begin();
+        history.end();
+
+        assert_eq!(undo(&mut history).unwrap(), ["a"]);
+        assert!(undo(&mut history).is_none());
+    }
+
+    #[test]
+    fn undo_ends_the_pending_transaction() {
+        let mut history = History::default();
+        history.begin();
+        history.record(insert("a"));
+        history.record(insert("b"));
+
+        assert_eq!(undo(&mut history).unwrap(), ["a", "b"]);
+        history.record(insert("c"));
+        assert_eq!(undo(&mut history).unwrap(), ["c"]);
+    }
+
+    #[test]
+    fn recording_clears_the_redo_stack() {
+        let mut history = History::default();
+        history.record(insert("a"));
+        history.record(insert("b"));
+        undo(&mut history);
+        undo(&mut history);
+        assert_eq!(redo(&mut history).unwrap(), ["a"]);
+
+        history.record(insert("c"));
+        assert!(redo(&mut history).is_none());
+        assert_eq!(undo(&mut history).unwrap(), ["c"]);
+        assert_eq!(undo(&mut history).unwrap(), ["a"]);
+    }
+
+    #[test]
+    fn modified_since_saved() {
+        let mut history = History::default();
+        assert!(!history.is_modified());
+
+        history.record(insert("a"));
+        assert!(history.is_modified());
+        history.mark_saved();
+        assert!(!history.is_modified());
+
+        // Undoing past the save and redoing back to it.
+        undo(&mut history);
+        assert!(history.is_modified());
+        redo(&mut history);
+        assert!(!history.is_modified());
+
+        history.record(insert("b"));
+        undo(&mut history);
+        assert!(!history.is_modified());
+
+        // A change made after undoing past the save clears the way back to it.
+        undo(&mut history);
+        history.record(insert("c"));
+        assert!(history.is_modified());
+        undo(&mut history);
+        assert!(history.is_modified());
+        assert!(redo(&mut history).is_some());
+        assert!(redo(&mut history).is_none());
+        assert!(history.is_modified());
+    }
+
+    #[test]
+    fn modified_while_a_transaction_is_pending() {
+        let mut history = History::default();
+        history.begin();
+        assert!(!history.is_modified());
+        history.record(insert("a"));
+        assert!(history.is_modified());
+
+        history.mark_saved();
+        assert!(!history.is_modified());
+        undo(&mut history);
+        assert!(history.is_modified());
+    }
+
+    #[test]
+    fn undoing_everything_after_saving_an_empty_history() {
+        let mut history = History::default();
+        history.mark_saved();
+        history.record(insert("a"));
+        history.record(insert("b"));
+        undo(&mut history);
+        assert!(history.is_modified());
+        undo(&mut history);
+        assert!(!history.is_modified());
+    }
+}
@@ -0,0 +1,182 @@
+use regex::bytes::Regex;
+use std::{cell::RefCell, fs::File, io, os::unix::fs::FileExt};
+
+/// Files from this size on are paged in rather than read whole.
+pub const HUGE_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How many bytes are scanned for line breaks at a time.
+const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// How many bytes at the start of a file tell whether it can be paged in.
+pub const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Lines longer than this are cut, as only their start can be shown anyway.
+const MAX_LINE_LENGTH: u64 = 1024 * 1024;
+
+/// A file too big to be read into a buffer. Its lines are read from the disk when they are
+/// needed, and where they start is found a chunk at a time.
+pub struct HugeFile {
+    file: File,
+    size: u64,
+    index: RefCell<LineIndex>,
+}
+
+#[derive(Default)]
+struct LineIndex {
+    /// The offset of the first byte of each line found so far.
+    starts: Vec<u64>,
+    /// How many bytes were scanned for line breaks.
+    scanned: u64,
+}
+
+impl HugeFile {
+    pub fn new(file: File) -> Result<Self, io::Error> {
+        let size = file.metadata()?.len();
+        let starts = if size > 0 { vec![0] } else { Vec::new() };
+
+        Ok(Self {
+            file,
+            size,
+            index: RefCell::new(LineIndex { starts, scanned: 0 }),
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Scans the next chunk of the file for line breaks and returns whether the whole file
+    /// is scanned.
+    pub fn index_chunk(&self) -> bool {
+        let mut index = self.index.borrow_mut();
+        if index.scanned >= self.size {
+            return true;
+        }
+
+        let length = CHUNK_SIZE.min((self.size - index.scanned) as usize);
+        let mut chunk = vec![0; length];
+        if self.file.read_exact_at(&mut chunk, index.scanned).is_err() {
+            // The file shrank or can't be read anymore: what was found is all there is.
+            index.scanned = self.size;
+            return true;
+        }
+
+        let start = index.scanned;
+        for (offset, _) in chunk.iter().enumerate().filter(|(_, byte)| **byte == b'\n') {
+            let next = start + offset as u64 + 1;
+            if next < self.size {
+                index.starts.push(next);
+            }
+        }
+        index.scanned += length as u64;
+
+        index.scanned >= self.size
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.index.borrow().scanned >= self.size
+    }
+
+    /// Returns how many bytes from the start of the file were scanned for line breaks.
+    pub fn scanned(&self) -> u64 {
+        self.index.borrow().scanned.min(self.size)
+    }
+
+    /// Returns how much of the file was scanned for line breaks, in percent.
+    pub fn progress(&self) -> u64 {
+        match self.size {
+            0 => 100,
+            size => self.index.borrow().scanned * 100 / size,
+        }
+    }
+
+    /// Returns the number of lines found so far, which are all of them once the file is
+    /// indexed. The last line found is only counted once its end is.
+    pub fn len(&self) -> usize {
+        let index = self.index.borrow();
+        if index.scanned >= self.size {
+            index.starts.len()
+        } else {
+            index.starts.len().saturating_sub(1)
+        }
+    }
+
+    /// Returns the line at `y` without its line break, scanning the file as far as needed.
+    pub fn line(&self, y: usize) -> Option<String> {
+        while y >= self.len() && !self.is_indexed() {
+            self.index_chunk();
+        }
+
+        let (start, end) = {
+            let index = self.index.borrow();
+            let start = *index.starts.get(y)?;
+            let end = index.starts.get(y + 1).copied().unwrap_or(self.size);
+            (start, end)
+        };
+
+        let mut bytes = vec![0; (end - start).min(MAX_LINE_LENGTH) as usize];
+        self.file.read_exact_at(&mut bytes, start).ok()?;
+        if bytes.ends_with(b"\n") {
+            bytes.pop();
+            if bytes.ends_with(b"\r") {
+                bytes.pop();
+            }
+        }
+
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Returns the offset of the first byte of the line at `y`, once the file is indexed
+    /// that far.
+    pub fn line_start(&self, y: usize) -> Option<u64> {
+        self.index.borrow().starts.get(y).copied()
+    }
+
+    /// Returns the index of the line the byte at `offset` is part of, among the lines
+    /// found so far.
+    pub fn line_at(&self, offset: u64) -> usize {
+        let index = self.index.borrow();
+        index
+            .starts
+            .partition_point(|start| *start <= offset)
+            .saturating_sub(1)
+    }
+
+    /// Returns the offset of the first match of `query` starting between `start` and `end`,
+    /// or of the last one when `last`, reading the file a chunk at a time rather than a
+    /// line at a time.
+    pub fn find(&self, query: &str, start: u64, end: u64, last: bool) -> Option<u64> {
+        let pattern = Regex::new(&regex::escape(query)).ok()?;
+        // Chunks go on for the length of the query, so that matches across two are found.
+        let overlap = query.len().saturating_sub(1) as u64;
+        let end = end.min(self.size);
+        let search = |chunk_start: u64| {
+            let chunk_end = (chunk_start + CHUNK_SIZE as u64 + overlap).min(self.size);
+            let mut chunk = vec![0; (chunk_end - chunk_start) as usize];
+            self.file.read_exact_at(&mut chunk, chunk_start).ok()?;
+
+            let mut matches = pattern
+                .find_iter(&chunk)
+                .map(|found| chunk_start + found.start() as u64)
+                .filter(|offset| *offset < end);
+            if last {
+                matches.last()
+            } else {
+                matches.next()
+            }
+        };
+
+        let mut chunks = (start..end).step_by(CHUNK_SIZE).collect::<Vec<_>>();
+        if last {
+            chunks.reverse();
+        }
+        chunks.into_iter().find_map(search)
+    }
+
+    /// Reads the whole file.
+    pub fn read(&self) -> Result<Vec<u8>, io::Error> {
+        let mut bytes = vec![0; self.size as usize];
+        self.file.read_exact_at(&mut bytes, 0)?;
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,204 @@
+use crate::keys;
+use termion::event::Key;
+
+/// The modes mappings are made for. The three visual modes share their mappings.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MapMode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl MapMode {
+    /// Returns the letter `:map` lists the mappings of the mode with.
+    pub fn letter(self) -> char {
+        match self {
+            Self::Normal => 'n',
+            Self::Insert => 'i',
+            Self::Visual => 'v',
+        }
+    }
+}
+
+pub struct Mapping {
+    pub mode: MapMode,
+    pub lhs: Vec<Key>,
+    pub rhs: Vec<Key>,
+    /// Whether the keys of `rhs` are mapped again, as with `:map` but not `:noremap`.
+    pub recursive: bool,
+}
+
+impl Mapping {
+    /// Returns how many keys at the start of `rhs` are not mapped again. Like in vim, the
+    /// first key of a recursive mapping whose `rhs` starts with its `lhs` is not, so that
+    /// `:map j jzz` doesn't loop.
+    pub fn unmapped_len(&self) -> usize {
+        if !self.recursive {
+            self.rhs.len()
+        } else if self.rhs.starts_with(&self.lhs) {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// The mappings of every mode.
+#[derive(Default)]
+pub struct Keymaps {
+    mappings: Vec<Mapping>,
+}
+
+impl Keymaps {
+    /// Maps `lhs` to `rhs` in `mode`, replacing the mapping of `lhs` there if any.
+    pub fn map(&mut self, mode: MapMode, lhs: Vec<Key>, rhs: Vec<Key>, recursive: bool) {
+        self.unmap(mode, &lhs);
+        self.mappings.push(Mapping {
+            mode,
+            lhs,
+            rhs,
+            recursive,
+        });
+    }
+
+    /// Removes the mapping of `lhs` in `mode` and returns whether there was one.
+    pub fn unmap(&mut self, mode: MapMode, lhs: &[Key]) -> bool {
+        let count = self.mappings.len();
+        self.mappings
+            .retain(|mapping| mapping.mode != mode || mapping.lhs != lhs);
+        self.mappings.len() != count
+    }
+
+    /// Returns the mapping of `keys` in `mode`.
+    pub fn get(&self, mode: MapMode, keys: &[Key]) -> Option<&Mapping> {
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.mode == mode && mapping.lhs == keys)
+    }
+
+    /// Returns whether a mapping of `mode` starts with `keys` and goes on after them.
+    pub fn is_prefix(&self, mode: MapMode, keys: &[Key]) -> bool {
+        self.mappings.iter().any(|mapping| {
+            mapping.mode == mode && mapping.lhs.len() > keys.len() && mapping.lhs.starts_with(keys)
+        })
+    }
+
+    /// Returns the mappings of `modes` that start with `keys`, in the order they were made.
+    pub fn list<'a>(
+        &'a self,
+        modes: &'a [MapMode],
+        keys: &'a [Key],
+    ) -> impl Iterator<Item = &'a Mapping> {
+        self.mappings
+            .iter()
+            .filter(move |mapping| modes.contains(&mapping.mode) && mapping.lhs.starts_with(keys))
+    }
+}
+
+/// Reads the keys of a mapping written in the notation of [`keys::parse`], in which
+/// `<leader>` stands for the keys of `leader`.
+pub fn parse(text: &str, leader: &str) -> Vec<Key> {
+    // Only ASCII letters change case, so the offsets in both strings are the same.
+    let lowercase = text.to_ascii_lowercase();
+    let mut parsed = Vec::new();
+    let mut start = 0;
+
+    for (index, name) in lowercase.match_indices("<leader>") {
+        parsed.extend(keys::parse(&text[start..index]));
+        parsed.extend(keys::parse(leader));
+        start = index + name.len();
+    }
+    parsed.extend(keys::parse(&text[start..]));
+
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(lhs: &str, rhs: &str, recursive: bool) -> Mapping {
+        Mapping {
+            mode: MapMode::Normal,
+            lhs: keys::parse(lhs),
+            rhs: keys::parse(rhs),
+            recursive,
+        }
+    }
+
+    #[test]
+    fn parse_leader() {
+        assert_eq!(parse("<leader>w", "\\"), keys::parse("\\w"));
+        assert_eq!(parse("<Leader>w", ","), keys::parse(",w"));
+        assert_eq!(parse("<LEADER><leader>", ","), keys::parse(",,"));
+        assert_eq!(parse("d<leader>w<Leader>", ","), keys::parse("d,w,"));
+    }
+
+    #[test]
+    fn parse_leader_notation() {
+        // The leader is written in the same notation as the mapping.
+        assert_eq!(
+            parse("<leader>f", "<Space>"),
+            [Key::Char(' '), Key::Char('f')]
+        );
+        assert_eq!(
+            parse("<leader><C-w>", "<Esc>g"),
+            [Key::Esc, Key::Char('g'), Key::Ctrl('w')]
+        );
+        assert_eq!(parse("<leader>", ""), []);
+    }
+
+    #[test]
+    fn parse_without_leader() {
+        assert_eq!(
+            parse("<C-W>x<cr>", ","),
+            [Key::Ctrl('w'), Key::Char('x'), Key::Char('\n')]
+        );
+        assert_eq!(parse("<lt>leader>", ","), keys::parse("<lt>leader>"));
+        assert_eq!(parse("<leader", ","), keys::parse("<leader"));
+        // Offsets stay right after text whose case only ASCII letters change in.
+        assert_eq!(parse("É<Leader>é", ","), keys::parse("É,é"));
+    }
+
+    #[test]
+    fn unmapped_len() {
+        assert_eq!(mapping("j", "gjzz", false).unmapped_len(), 4);
+        assert_eq!(mapping("j", "jzz", false).unmapped_len(), 3);
+        assert_eq!(mapping("j", "jzz", true).unmapped_len(), 1);
+        assert_eq!(mapping("gj", "gjzz", true).unmapped_len(), 1);
+        assert_eq!(mapping("j", "gjzz", true).unmapped_len(), 0);
+        assert_eq!(mapping("gj", "gzz", true).unmapped_len(), 0);
+    }
+
+    #[test]
+    fn map_and_unmap() {
+        let mut keymaps = Keymaps::default();
+        keymaps.map(MapMode::Normal, keys::parse("gj"), keys::parse("j"), true);
+        keymaps.map(
+            MapMode::Insert,
+            keys::parse("jk"),
+            keys::parse("<Esc>"),
+            false,
+        );
+        keymaps.map(MapMode::Normal, keys::parse("gj"), keys::parse("jj"), false);
+
+        let mapping = keymaps.get(MapMode::Normal, &keys::parse("gj")).unwrap();
+        assert_eq!(mapping.rhs, keys::parse("jj"));
+        assert!(!mapping.recursive);
+        assert!(keymaps.get(MapMode::Visual, &keys::parse("gj")).is_none());
+        assert!(keymaps.get(MapMode::Normal, &keys::parse("g")).is_none());
+
+        assert!(keymaps.is_prefix(MapMode::Normal, &keys::parse("g")));
+        assert!(!keymaps.is_prefix(MapMode::Normal, &keys::parse("gj")));
+        assert!(!keymaps.is_prefix(MapMode::Insert, &keys::parse("g")));
+
+        let modes = [MapMode::Normal, MapMode::Insert];
+        assert_eq!(keymaps.list(&modes, &[]).count(), 2);
+        assert_eq!(keymaps.list(&modes, &keys::parse("j")).count(), 1);
+
+        assert!(keymaps.unmap(MapMode::Normal, &keys::parse("gj")));
+        assert!(!keymaps.unmap(MapMode::Normal, &keys::parse("gj")));
+        assert!(!keymaps.is_prefix(MapMode::Normal, &keys::parse("g")));
+        assert!(keymaps.get(MapMode::Insert, &keys::parse("jk")).is_some());
+    }
+}
@@ -0,0 +1,108 @@
+use termion::event::Key;
+
+/// Writes `keys` in the `<Esc>`, `<C-r>` notation used to store macros in registers.
+pub fn to_notation(keys: &[Key]) -> String {
+    keys.iter().map(|key| key_to_notation(*key)).collect()
+}
+
+pub fn key_to_notation(key: Key) -> String {
+    match key {
+        Key::Char('<') => "<lt>".to_string(),
+        Key::Char('\n') => "<CR>".to_string(),
+        Key::Char('\t') => "<Tab>".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("<C-{}>", c),
+        Key::Alt(c) => format!("<A-{}>", c),
+        Key::F(n) => format!("<F{}>", n),
+        Key::Esc => "<Esc>".to_string(),
+        Key::Backspace => "<BS>".to_string(),
+        Key::Delete => "<Del>".to_string(),
+        Key::Insert => "<Insert>".to_string(),
+        Key::Up => "<Up>".to_string(),
+        Key::Down => "<Down>".to_string(),
+        Key::Left => "<Left>".to_string(),
+        Key::Right => "<Right>".to_string(),
+        Key::Home => "<Home>".to_string(),
+        Key::End => "<End>".to_string(),
+        Key::PageUp => "<PageUp>".to_string(),
+        Key::PageDown => "<PageDown>".to_string(),
+        Key::BackTab => "<S-Tab>".to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Reads keys written in the notation of [`to_notation`]. A `<` that doesn't start a known
+/// key name is read as itself.
+pub fn parse(text: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some((key, length)) = rest
+                .find('>')
+                .and_then(|end| Some((parse_name(&rest[1..end])?, end + 1)))
+            {
+                keys.push(key);
+                rest = &rest[length..];
+                continue;
+            }
+        }
+
+        keys.push(match c {
+            '\r' => Key::Char('\n'),
+            '\x1b' => Key::Esc,
+            c => Key::Char(c),
+        });
+        rest = &rest[c.len_utf8()..];
+    }
+
+    keys
+}
+
+/// Parses the name of a key written between `<` and `>`, ignoring case.
+fn parse_name(name: &str) -> Option<Key> {
+    let lowercase = name.to_lowercase();
+
+    let key = match lowercase.as_str() {
+        "lt" => Key::Char('<'),
+        "cr" | "enter" | "return" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "space" => Key::Char(' '),
+        "bar" => Key::Char('|'),
+        "bslash" => Key::Char('\\'),
+        "esc" => Key::Esc,
+        "bs" | "backspace" => Key::Backspace,
+        "del" | "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "s-tab" => Key::BackTab,
+        _ => {
+            if let Some(n) = lowercase.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                return Some(Key::F(n));
+            }
+
+            let (modifier, c) = name.split_once('-')?;
+            let mut chars = c.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+
+            match modifier.to_lowercase().as_str() {
+                "c" => Key::Ctrl(c.to_ascii_lowercase()),
+                "a" | "m" => Key::Alt(c),
+                _ => return None,
+            }
+        }
+    };
+
+    Some(key)
+}
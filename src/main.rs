@@ -3,12 +3,30 @@ use std::io;
 use termion::raw::IntoRawMode;
 
 mod buffer;
+mod clipboard;
 mod cursor;
 mod editor;
+mod encoding;
 mod file_type;
+mod hex;
 mod highlighting;
+mod history;
+mod huge;
+mod keymap;
+mod keys;
 mod message;
+mod motion;
+mod operator;
+mod options;
+mod register;
+mod selection;
+mod substitute;
+mod swap;
+mod tab;
 mod terminal_utils;
+mod text_object;
+mod theme;
+mod window;
 
 fn main() {
     let _stdout = io::stdout().into_raw_mode().unwrap();
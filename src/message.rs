@@ -1,5 +1,8 @@
-use crate::terminal_utils::color_fg;
-use termion::color::{Reset, Rgb};
+use crate::{
+    terminal_utils::color_fg,
+    theme::{Group, Theme},
+};
+use termion::color::Reset;
 
 #[derive(Clone)]
 pub struct Message {
@@ -10,14 +13,12 @@ pub struct Message {
 #[derive(Clone)]
 pub enum MessageType {
     Normal,
+    /// Drawn like an error, but for something which didn't fail, so it doesn't stop macros.
+    Warning,
     Error,
 }
 
 impl Message {
-    pub fn new(kind: MessageType, message: String) -> Self {
-        Self { kind, message }
-    }
-
     pub fn new_normal(message: String) -> Self {
         Self {
             kind: MessageType::Normal,
@@ -25,6 +26,13 @@ impl Message {
         }
     }
 
+    pub fn new_warning(message: String) -> Self {
+        Self {
+            kind: MessageType::Warning,
+            message,
+        }
+    }
+
     pub fn new_error(message: String) -> Self {
         Self {
             kind: MessageType::Error,
@@ -42,13 +50,13 @@ impl Default for Message {
     }
 }
 
-impl std::fmt::Display for Message {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Message {
+    pub fn render(&self, theme: &Theme) -> String {
         use MessageType::*;
 
         match self.kind {
-            Normal => write!(f, "{}", color_fg(&self.message, Reset)),
-            Error => write!(f, "{}", color_fg(&self.message, Rgb(255, 0, 0))),
+            Normal => color_fg(&self.message, Reset),
+            Warning | Error => color_fg(&self.message, theme.color(Group::Error)),
         }
     }
 }
@@ -0,0 +1,607 @@
+use crate::{buffer::Buffer, cursor::Position};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A `f`, `F`, `t` or `T` search for a character on the current row, kept around so `;`
+/// and `,` can repeat it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FindChar {
+    pub target: char,
+    pub forward: bool,
+    pub till: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward {
+        big: bool,
+    },
+    WordBackward {
+        big: bool,
+    },
+    WordEnd {
+        big: bool,
+    },
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    Find(FindChar),
+    /// A `;` or `,`, which skips a match right next to the cursor for `t` and `T`.
+    RepeatFind(FindChar),
+}
+
+/// How the text between the cursor and the target of a motion is operated on.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MotionKind {
+    /// The target itself is left out.
+    Exclusive,
+    /// The target is part of the text.
+    Inclusive,
+    /// Every row between the cursor and the target is part of the text.
+    Linewise,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    LineBreak,
+    EmptyRow,
+    Word,
+    Punctuation,
+}
+
+/// Walks the graphemes of a buffer, treating the end of each row as a line break.
+struct Walker<'a> {
+    buffer: &'a Buffer,
+    position: Position,
+    row: Vec<char>,
+}
+
+impl FindChar {
+    pub fn reversed(self) -> Self {
+        Self {
+            forward: !self.forward,
+            ..self
+        }
+    }
+}
+
+impl Motion {
+    pub fn kind(&self) -> MotionKind {
+        use Motion::*;
+
+        match self {
+            Up | Down | FirstLine | LastLine => MotionKind::Linewise,
+            WordEnd { .. } => MotionKind::Inclusive,
+            Find(find) | RepeatFind(find) if find.forward => MotionKind::Inclusive,
+            _ => MotionKind::Exclusive,
+        }
+    }
+
+    /// Returns where the motion lands when applied `count` times from `from`, or `None`
+    /// when it can't move at all. `count` is `None` when no count was typed, which matters
+    /// for `gg` and `G`.
+    pub fn apply(&self, buffer: &Buffer, from: Position, count: Option<usize>) -> Option<Position> {
+        use Motion::*;
+
+        let repeat = count.unwrap_or(1).max(1);
+        let last_row = buffer.len().saturating_sub(1);
+        let row_len = |y: usize| buffer.row(y).map(|row| row.len()).unwrap_or_default();
+
+        let target = match self {
+            Left => {
+                if from.x == 0 {
+                    return None;
+                }
+                Position {
+                    x: from.x.saturating_sub(repeat),
+                    y: from.y,
+                }
+            }
+            Right => {
+                let len = row_len(from.y);
+                if from.x >= len {
+                    return None;
+                }
+                Position {
+                    x: (from.x + repeat).min(len),
+                    y: from.y,
+                }
+            }
+            Up | Down => {
+                let y = if *self == Up && from.y > 0 {
+                    from.y.saturating_sub(repeat)
+                } else if *self == Down && from.y < last_row {
+                    (from.y + repeat).min(last_row)
+                } else {
+                    return None;
+                };
+
+                // Keep the cursor on the same display column across wide characters.
+                let column = buffer
+                    .row(from.y)
+                    .map(|row| row.width_to(from.x))
+                    .unwrap_or_default();
+                let x = buffer
+                    .row(y)
+                    .map(|row| row.grapheme_at_column(column))
+                    .unwrap_or_default();
+                Position { x, y }
+            }
+            WordForward { big } => {
+                let mut walker = Walker::new(buffer, from);
+                for _ in 0..repeat {
+                    walker.word_forward(*big);
+                }
+                walker.position
+            }
+            WordBackward { big } => {
+                let mut walker = Walker::new(buffer, from);
+                for _ in 0..repeat {
+                    walker.word_backward(*big);
+                }
+                walker.position
+            }
+            WordEnd { big } => {
+                let mut walker = Walker::new(buffer, from);
+                for _ in 0..repeat {
+                    walker.word_end(*big);
+                }
+                walker.position
+            }
+            LineStart => Position { x: 0, y: from.y },
+            FirstNonBlank => Position {
+                x: first_non_blank(buffer, from.y),
+                y: from.y,
+            },
+            LineEnd => {
+                let y = (from.y + repeat - 1).min(last_row.max(from.y));
+                Position { x: row_len(y), y }
+            }
+            FirstLine | LastLine => {
+                let y = match count {
+                    Some(line) => line.saturating_sub(1).min(last_row),
+                    None if *self == FirstLine => 0,
+                    None => last_row,
+                };
+                Position {
+                    x: first_non_blank(buffer, y),
+                    y,
+                }
+            }
+            Find(find) => find.apply(buffer, from, repeat, false)?,
+            RepeatFind(find) => find.apply(buffer, from, repeat, true)?,
+        };
+
+        Some(target)
+    }
+}
+
+impl FindChar {
+    fn apply(
+        &self,
+        buffer: &Buffer,
+        from: Position,
+        count: usize,
+        repeated: bool,
+    ) -> Option<Position> {
+        let row = buffer.row(from.y)?;
+        let graphemes = row.as_str().graphemes(true).collect::<Vec<&str>>();
+        let matches = |grapheme: &&str| grapheme.starts_with(self.target);
+        let skip = usize::from(self.till && repeated);
+
+        let x = if self.forward {
+            let start = from.x + 1 + skip;
+            let (offset, _) = graphemes
+                .iter()
+                .enumerate()
+                .skip(start)
+                .filter(|(_, grapheme)| matches(grapheme))
+                .nth(count - 1)?;
+            if self.till {
+                offset - 1
+            } else {
+                offset
+            }
+        } else {
+            let end = from.x.saturating_sub(skip);
+            let (offset, _) = graphemes
+                .iter()
+                .enumerate()
+                .take(end)
+                .rev()
+                .filter(|(_, grapheme)| matches(grapheme))
+                .nth(count - 1)?;
+            if self.till {
+                offset + 1
+            } else {
+                offset
+            }
+        };
+
+        Some(Position { x, y: from.y })
+    }
+}
+
+impl<'a> Walker<'a> {
+    fn new(buffer: &'a Buffer, position: Position) -> Self {
+        let mut walker = Self {
+            buffer,
+            position,
+            row: Vec::new(),
+        };
+        walker.load_row();
+        walker
+    }
+
+    fn load_row(&mut self) {
+        self.row = self
+            .buffer
+            .row(self.position.y)
+            .map(|row| {
+                row.as_str()
+                    .graphemes(true)
+                    .map(|grapheme| grapheme.chars().next().unwrap_or_default())
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+
+    fn class(&self, big: bool) -> CharClass {
+        if self.row.is_empty() {
+            return CharClass::EmptyRow;
+        }
+
+        match self.row.get(self.position.x) {
+            None => CharClass::LineBreak,
+            Some(c) if c.is_whitespace() => CharClass::Blank,
+            Some(_) if big => CharClass::Word,
+            Some(c) if c.is_alphanumeric() || *c == '_' => CharClass::Word,
+            Some(_) => CharClass::Punctuation,
+        }
+    }
+
+    fn forward(&mut self) -> bool {
+        if self.position.x < self.row.len() {
+            self.position.x += 1;
+        } else if self.position.y + 1 < self.buffer.len() {
+            self.position = Position {
+                x: 0,
+                y: self.position.y + 1,
+            };
+            self.load_row();
+        } else {
+            return false;
+        }
+
+        true
+    }
+
+    fn backward(&mut self) -> bool {
+        if self.position.x > 0 {
+            self.position.x -= 1;
+        } else if self.position.y > 0 {
+            self.position.y -= 1;
+            self.load_row();
+            self.position.x = self.row.len();
+        } else {
+            return false;
+        }
+
+        true
+    }
+
+    fn is_blank(&self, big: bool) -> bool {
+        matches!(self.class(big), CharClass::Blank | CharClass::LineBreak)
+    }
+
+    /// Moves to the start of the next word. Empty rows count as words.
+    fn word_forward(&mut self, big: bool) {
+        let start = self.class(big);
+        match start {
+            CharClass::Word | CharClass::Punctuation => {
+                while self.class(big) == start {
+                    if !self.forward() {
+                        return;
+                    }
+                }
+            }
+            CharClass::EmptyRow => {
+                if !self.forward() {
+                    return;
+                }
+            }
+            CharClass::Blank | CharClass::LineBreak => (),
+        }
+
+        while self.is_blank(big) {
+            if !self.forward() {
+                return;
+            }
+        }
+    }
+
+    /// Moves to the start of the current word, or of the previous one when already there.
+    fn word_backward(&mut self, big: bool) {
+        if !self.backward() {
+            return;
+        }
+
+        while self.is_blank(big) {
+            if !self.backward() {
+                return;
+            }
+        }
+
+        let class = self.class(big);
+        if class == CharClass::EmptyRow {
+            return;
+        }
+
+        while self.backward() {
+            if self.class(big) != class {
+                self.forward();
+                return;
+            }
+        }
+    }
+
+    /// Moves to the end of the current word, or of the next one when already there.
+    fn word_end(&mut self, big: bool) {
+        if !self.forward() {
+            return;
+        }
+
+        while matches!(
+            self.class(big),
+            CharClass::Blank | CharClass::LineBreak | CharClass::EmptyRow
+        ) {
+            if !self.forward() {
+                return;
+            }
+        }
+
+        let class = self.class(big);
+        while self.forward() {
+            if self.class(big) != class {
+                self.backward();
+                return;
+            }
+        }
+    }
+}
+
+/// Returns where `cw` stops: like `e`, except that the word under the cursor is changed
+/// even when the cursor is already on its last grapheme. Returns `None` when the cursor is
+/// not on a word, in which case `cw` behaves like `dw`.
+pub fn change_word_end(
+    buffer: &Buffer,
+    from: Position,
+    count: Option<usize>,
+    big: bool,
+) -> Option<Position> {
+    let mut walker = Walker::new(buffer, from);
+    let class = walker.class(big);
+    if !matches!(class, CharClass::Word | CharClass::Punctuation) {
+        return None;
+    }
+
+    let mut repeat = count.unwrap_or(1).max(1);
+    let at_word_end = walker.row.get(from.x + 1).is_none() || {
+        walker.position.x += 1;
+        walker.class(big) != class
+    };
+    walker.position = from;
+    if at_word_end {
+        repeat -= 1;
+    }
+
+    for _ in 0..repeat {
+        walker.word_end(big);
+    }
+    Some(walker.position)
+}
+
+/// Returns the index of the first grapheme of row `y` that is not whitespace.
+pub fn first_non_blank(buffer: &Buffer, y: usize) -> usize {
+    buffer
+        .row(y)
+        .map(|row| {
+            row.as_str()
+                .graphemes(true)
+                .take_while(|grapheme| grapheme.chars().all(char::is_whitespace))
+                .count()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "foo.bar baz\n  qux\n\nend";
+
+    fn buffer() -> Buffer {
+        let mut buffer = Buffer::default();
+        buffer.insert_str(&Position::default(), TEXT);
+        buffer
+    }
+
+    /// Returns where `motion` lands from `x` on row `y` in [`TEXT`], as `(x, y)`.
+    fn apply(
+        motion: Motion,
+        (x, y): (usize, usize),
+        count: Option<usize>,
+    ) -> Option<(usize, usize)> {
+        motion
+            .apply(&buffer(), Position { x, y }, count)
+            .map(|position| (position.x, position.y))
+    }
+
+    /// Returns where `cw` stops from `x` on row `y`, or `None` when it works like `dw`.
+    fn change((x, y): (usize, usize), count: Option<usize>, big: bool) -> Option<(usize, usize)> {
+        change_word_end(&buffer(), Position { x, y }, count, big)
+            .map(|position| (position.x, position.y))
+    }
+
+    fn find(target: char, forward: bool, till: bool) -> FindChar {
+        FindChar {
+            target,
+            forward,
+            till,
+        }
+    }
+
+    #[test]
+    fn word_forward() {
+        let w = Motion::WordForward { big: false };
+        assert_eq!(apply(w, (0, 0), None), Some((3, 0)));
+        assert_eq!(apply(w, (3, 0), None), Some((4, 0)));
+        assert_eq!(apply(w, (4, 0), None), Some((8, 0)));
+        // The indent of the next row is skipped, but an empty row is a word.
+        assert_eq!(apply(w, (8, 0), None), Some((2, 1)));
+        assert_eq!(apply(w, (2, 1), None), Some((0, 2)));
+        assert_eq!(apply(w, (0, 2), None), Some((0, 3)));
+        assert_eq!(apply(w, (0, 0), Some(3)), Some((8, 0)));
+    }
+
+    #[test]
+    fn big_word_forward() {
+        let big_w = Motion::WordForward { big: true };
+        assert_eq!(apply(big_w, (0, 0), None), Some((8, 0)));
+        assert_eq!(apply(big_w, (5, 0), None), Some((8, 0)));
+    }
+
+    #[test]
+    fn word_backward() {
+        let b = Motion::WordBackward { big: false };
+        assert_eq!(apply(b, (8, 0), None), Some((4, 0)));
+        assert_eq!(apply(b, (4, 0), None), Some((3, 0)));
+        assert_eq!(apply(b, (6, 0), None), Some((4, 0)));
+        assert_eq!(apply(b, (0, 3), None), Some((0, 2)));
+        assert_eq!(apply(b, (0, 2), None), Some((2, 1)));
+        assert_eq!(apply(b, (0, 0), None), Some((0, 0)));
+
+        let big_b = Motion::WordBackward { big: true };
+        assert_eq!(apply(big_b, (8, 0), None), Some((0, 0)));
+        assert_eq!(apply(big_b, (2, 1), None), Some((8, 0)));
+    }
+
+    #[test]
+    fn word_end() {
+        let e = Motion::WordEnd { big: false };
+        assert_eq!(apply(e, (0, 0), None), Some((2, 0)));
+        assert_eq!(apply(e, (2, 0), None), Some((3, 0)));
+        assert_eq!(apply(e, (8, 0), None), Some((10, 0)));
+        // Line breaks, indents and empty rows are skipped.
+        assert_eq!(apply(e, (10, 0), None), Some((4, 1)));
+        assert_eq!(apply(e, (4, 1), None), Some((2, 3)));
+
+        let big_e = Motion::WordEnd { big: true };
+        assert_eq!(apply(big_e, (0, 0), None), Some((6, 0)));
+    }
+
+    #[test]
+    fn counts_past_the_end_of_the_buffer() {
+        // Forward motions stop past the last grapheme, where `$` leaves the cursor.
+        let w = Motion::WordForward { big: false };
+        assert_eq!(apply(w, (0, 0), Some(100)), Some((3, 3)));
+        assert_eq!(apply(w, (1, 3), None), Some((3, 3)));
+        let e = Motion::WordEnd { big: false };
+        assert_eq!(apply(e, (0, 0), Some(100)), Some((3, 3)));
+        let b = Motion::WordBackward { big: false };
+        assert_eq!(apply(b, (0, 3), Some(100)), Some((0, 0)));
+
+        assert_eq!(apply(Motion::Right, (0, 0), Some(100)), Some((11, 0)));
+        assert_eq!(apply(Motion::Right, (11, 0), None), None);
+        assert_eq!(apply(Motion::Left, (5, 0), Some(100)), Some((0, 0)));
+        assert_eq!(apply(Motion::Left, (0, 0), None), None);
+        assert_eq!(apply(Motion::Down, (0, 0), Some(100)), Some((0, 3)));
+        assert_eq!(apply(Motion::Down, (0, 3), None), None);
+        assert_eq!(apply(Motion::Up, (0, 3), Some(100)), Some((0, 0)));
+        assert_eq!(apply(Motion::LineEnd, (0, 0), Some(100)), Some((3, 3)));
+        assert_eq!(apply(Motion::LastLine, (0, 0), Some(100)), Some((0, 3)));
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(apply(Motion::LineEnd, (0, 0), Some(2)), Some((5, 1)));
+        assert_eq!(apply(Motion::FirstNonBlank, (4, 1), None), Some((2, 1)));
+        assert_eq!(apply(Motion::LineStart, (4, 1), None), Some((0, 1)));
+        assert_eq!(apply(Motion::FirstLine, (0, 3), Some(2)), Some((2, 1)));
+        assert_eq!(apply(Motion::FirstLine, (0, 3), None), Some((0, 0)));
+        assert_eq!(apply(Motion::LastLine, (0, 0), None), Some((0, 3)));
+        // The column is kept where the row is long enough.
+        assert_eq!(apply(Motion::Down, (4, 0), None), Some((4, 1)));
+        assert_eq!(apply(Motion::Down, (9, 0), None), Some((5, 1)));
+    }
+
+    #[test]
+    fn find_char() {
+        let f = Motion::Find(find('o', true, false));
+        assert_eq!(apply(f, (0, 0), None), Some((1, 0)));
+        assert_eq!(apply(f, (0, 0), Some(2)), Some((2, 0)));
+        assert_eq!(apply(f, (0, 0), Some(3)), None);
+
+        let big_f = Motion::Find(find('a', false, false));
+        assert_eq!(apply(big_f, (10, 0), None), Some((9, 0)));
+        assert_eq!(apply(big_f, (10, 0), Some(2)), Some((5, 0)));
+        assert_eq!(apply(big_f, (5, 0), None), None);
+    }
+
+    #[test]
+    fn till_char() {
+        let t = find('a', true, true);
+        assert_eq!(apply(Motion::Find(t), (0, 0), None), Some((4, 0)));
+        // Right before a match, `t` stays put while `;` goes on to the next one.
+        assert_eq!(apply(Motion::Find(t), (4, 0), None), Some((4, 0)));
+        assert_eq!(apply(Motion::RepeatFind(t), (4, 0), None), Some((8, 0)));
+
+        let big_t = find('a', false, true);
+        assert_eq!(apply(Motion::Find(big_t), (10, 0), None), Some((10, 0)));
+        assert_eq!(
+            apply(Motion::RepeatFind(big_t), (10, 0), None),
+            Some((6, 0))
+        );
+        assert_eq!(
+            apply(Motion::RepeatFind(big_t.reversed()), (0, 0), None),
+            Some((4, 0))
+        );
+    }
+
+    #[test]
+    fn change_word() {
+        assert_eq!(change((0, 0), None, false), Some((2, 0)));
+        // On the last grapheme of a word, only that word is changed.
+        assert_eq!(change((2, 0), None, false), Some((2, 0)));
+        assert_eq!(change((10, 0), None, false), Some((10, 0)));
+        assert_eq!(change((2, 0), Some(2), false), Some((3, 0)));
+        assert_eq!(change((0, 0), Some(2), false), Some((3, 0)));
+        assert_eq!(change((0, 0), None, true), Some((6, 0)));
+        assert_eq!(change((0, 3), Some(100), false), Some((3, 3)));
+    }
+
+    #[test]
+    fn change_word_on_blanks() {
+        // `cw` on white space, a line break or an empty row works like `dw`.
+        assert_eq!(change((7, 0), None, false), None);
+        assert_eq!(change((0, 1), None, false), None);
+        assert_eq!(change((11, 0), None, false), None);
+        assert_eq!(change((0, 2), None, false), None);
+    }
+
+    #[test]
+    fn kinds() {
+        assert!(Motion::Down.kind() == MotionKind::Linewise);
+        assert!(Motion::LastLine.kind() == MotionKind::Linewise);
+        assert!(Motion::WordEnd { big: false }.kind() == MotionKind::Inclusive);
+        assert!(Motion::Find(find('a', true, true)).kind() == MotionKind::Inclusive);
+        assert!(Motion::Find(find('a', false, false)).kind() == MotionKind::Exclusive);
+        assert!(Motion::WordForward { big: false }.kind() == MotionKind::Exclusive);
+    }
+}
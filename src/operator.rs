@@ -0,0 +1,108 @@
+use crate::{cursor::Position, register::RegisterKind, selection::Selection};
+
+/// An action applied to the text covered by a motion or a visual selection.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Dedent,
+    ToggleCase,
+    Lowercase,
+    Uppercase,
+}
+
+/// The text an operator is applied to.
+#[derive(Clone, Copy)]
+pub enum TextRange {
+    /// Characters from `start` up to, but not including, `end`.
+    Characters {
+        start: Position,
+        end: Position,
+    },
+    /// Rows `first` to `last`, both included, with their line breaks.
+    Lines {
+        first: usize,
+        last: usize,
+    },
+    Block(Selection),
+}
+
+impl Operator {
+    /// Returns the operator started by `c` in normal mode.
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'd' => Some(Self::Delete),
+            'c' => Some(Self::Change),
+            'y' => Some(Self::Yank),
+            '>' => Some(Self::Indent),
+            '<' => Some(Self::Dedent),
+            _ => None,
+        }
+    }
+
+    /// Returns the operator `c` applies to the selection in visual mode.
+    pub fn from_visual_char(c: char) -> Option<Self> {
+        match c {
+            'x' => Some(Self::Delete),
+            '~' => Some(Self::ToggleCase),
+            'u' => Some(Self::Lowercase),
+            'U' => Some(Self::Uppercase),
+            c => Self::from_char(c),
+        }
+    }
+}
+
+impl TextRange {
+    pub fn first_row(&self) -> usize {
+        match self {
+            Self::Characters { start, .. } => start.y,
+            Self::Lines { first, .. } => *first,
+            Self::Block(selection) => selection.start.y,
+        }
+    }
+
+    pub fn last_row(&self) -> usize {
+        match self {
+            Self::Characters { end, .. } => end.y,
+            Self::Lines { last, .. } => *last,
+            Self::Block(selection) => selection.end.y,
+        }
+    }
+
+    /// Returns where the range starts, which is where the cursor goes after most operators.
+    pub fn start(&self) -> Position {
+        match self {
+            Self::Characters { start, .. } => *start,
+            Self::Lines { first, .. } => Position { x: 0, y: *first },
+            Self::Block(selection) => selection.start,
+        }
+    }
+
+    /// Returns the graphemes of row `y` covered by the range as a `start..end` range, `end`
+    /// excluded.
+    pub fn row_range(&self, y: usize, row_len: usize) -> Option<(usize, usize)> {
+        if y < self.first_row() || y > self.last_row() {
+            return None;
+        }
+
+        match self {
+            Self::Characters { start, end } => {
+                let from = if y == start.y { start.x } else { 0 };
+                let to = if y == end.y { end.x } else { row_len };
+                Some((from.min(row_len), to.min(row_len)))
+            }
+            Self::Lines { .. } => Some((0, row_len)),
+            Self::Block(selection) => selection.row_range(y, row_len),
+        }
+    }
+
+    pub fn register_kind(&self) -> RegisterKind {
+        match self {
+            Self::Characters { .. } => RegisterKind::Character,
+            Self::Lines { .. } => RegisterKind::Line,
+            Self::Block(_) => RegisterKind::Block,
+        }
+    }
+}
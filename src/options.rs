@@ -0,0 +1,273 @@
+use crate::theme::ColorDepth;
+use std::{env, fmt, fs, io, path::PathBuf};
+
+/// What an option applies to. Buffer and window options also have a global value, which
+/// new buffers and windows start with.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Buffer,
+    Window,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Flag,
+    Number,
+    Text,
+}
+
+#[derive(Clone)]
+pub enum Value {
+    Flag(bool),
+    Number(usize),
+    Text(String),
+}
+
+pub struct OptionInfo {
+    pub name: &'static str,
+    /// The abbreviation of the name, if any.
+    pub short: Option<&'static str>,
+    pub scope: Scope,
+    pub kind: Kind,
+}
+
+/// Every option, in the order `:set all` lists them.
+pub const OPTIONS: &[OptionInfo] = &[
+    option("backup", Some("bk"), Scope::Global, Kind::Flag),
+    option("colordepth", None, Scope::Global, Kind::Text),
+    option("hlsearch", Some("hls"), Scope::Global, Kind::Flag),
+    option("mapleader", None, Scope::Global, Kind::Text),
+    option("timeoutlen", Some("tm"), Scope::Global, Kind::Number),
+    option("expandtab", Some("et"), Scope::Buffer, Kind::Flag),
+    option("fileencoding", Some("fenc"), Scope::Buffer, Kind::Text),
+    option("fileformat", Some("ff"), Scope::Buffer, Kind::Text),
+    option("readonly", Some("ro"), Scope::Buffer, Kind::Flag),
+    option("shiftwidth", Some("sw"), Scope::Buffer, Kind::Number),
+    option("syntax", Some("syn"), Scope::Buffer, Kind::Flag),
+    option("tabstop", Some("ts"), Scope::Buffer, Kind::Number),
+    option("scrolloff", Some("so"), Scope::Window, Kind::Number),
+];
+
+const fn option(
+    name: &'static str,
+    short: Option<&'static str>,
+    scope: Scope,
+    kind: Kind,
+) -> OptionInfo {
+    OptionInfo {
+        name,
+        short,
+        scope,
+        kind,
+    }
+}
+
+/// Finds the option called or abbreviated `name`.
+pub fn find(name: &str) -> Option<&'static OptionInfo> {
+    OPTIONS
+        .iter()
+        .find(|info| info.name == name || info.short == Some(name))
+}
+
+#[derive(Clone)]
+pub struct GlobalOptions {
+    /// Whether saving a file keeps its previous contents in a `~` file.
+    pub backup: bool,
+    /// How many colors the terminal can show, which the colors of the theme are brought
+    /// down to.
+    pub colordepth: ColorDepth,
+    /// Whether the matches of the last search are highlighted.
+    pub hlsearch: bool,
+    /// The keys `<leader>` stands for in the mappings made after it is set.
+    pub mapleader: String,
+    /// How many milliseconds to wait for the next key of a mapping.
+    pub timeoutlen: usize,
+}
+
+#[derive(Clone)]
+pub struct BufferOptions {
+    /// Whether Tab inserts spaces in insert mode.
+    pub expandtab: bool,
+    /// How many columns `>` and `<` indent by.
+    pub shiftwidth: usize,
+    /// Whether numbers and strings are highlighted.
+    pub syntax: bool,
+    /// How many columns a tab advances to.
+    pub tabstop: usize,
+}
+
+#[derive(Clone, Default)]
+pub struct WindowOptions {
+    /// How many rows are kept visible above and below the cursor.
+    pub scrolloff: usize,
+}
+
+impl Default for GlobalOptions {
+    fn default() -> Self {
+        Self {
+            backup: false,
+            colordepth: ColorDepth::detect(),
+            hlsearch: true,
+            mapleader: "\\".to_string(),
+            timeoutlen: 1000,
+        }
+    }
+}
+
+impl GlobalOptions {
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match name {
+            "backup" => Some(Value::Flag(self.backup)),
+            "colordepth" => Some(Value::Text(self.colordepth.to_string())),
+            "hlsearch" => Some(Value::Flag(self.hlsearch)),
+            "mapleader" => Some(Value::Text(self.mapleader.clone())),
+            "timeoutlen" => Some(Value::Number(self.timeoutlen)),
+            _ => None,
+        }
+    }
+
+    /// Sets the option called `name` to `value`, which is of the kind of the option.
+    pub fn set(&mut self, name: &str, value: Value) -> Result<(), String> {
+        match (name, value) {
+            ("backup", Value::Flag(flag)) => self.backup = flag,
+            ("colordepth", Value::Text(text)) => {
+                self.colordepth =
+                    ColorDepth::parse(&text).ok_or(format!("Invalid argument: {}", text))?
+            }
+            ("hlsearch", Value::Flag(flag)) => self.hlsearch = flag,
+            ("mapleader", Value::Text(text)) => self.mapleader = text,
+            ("timeoutlen", Value::Number(number)) => self.timeoutlen = number,
+            _ => return Err(format!("Invalid argument: {}", name)),
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for BufferOptions {
+    fn default() -> Self {
+        Self {
+            expandtab: false,
+            shiftwidth: 4,
+            syntax: true,
+            tabstop: 4,
+        }
+    }
+}
+
+impl BufferOptions {
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match name {
+            "expandtab" => Some(Value::Flag(self.expandtab)),
+            "shiftwidth" => Some(Value::Number(self.shiftwidth)),
+            "syntax" => Some(Value::Flag(self.syntax)),
+            "tabstop" => Some(Value::Number(self.tabstop)),
+            _ => None,
+        }
+    }
+
+    /// Sets the option called `name` to `value`, which is of the kind of the option.
+    /// Options that describe the file of a buffer have no global value and are refused.
+    pub fn set(&mut self, name: &str, value: Value) -> Result<(), String> {
+        match (name, value) {
+            ("expandtab", Value::Flag(flag)) => self.expandtab = flag,
+            ("syntax", Value::Flag(flag)) => self.syntax = flag,
+            ("shiftwidth" | "tabstop", Value::Number(0)) => {
+                return Err(format!("Argument must be positive: {}", name))
+            }
+            ("shiftwidth", Value::Number(number)) => self.shiftwidth = number,
+            ("tabstop", Value::Number(number)) => self.tabstop = number,
+            _ if self.get(name).is_none() => {
+                return Err(format!("Only a buffer can have this option: {}", name))
+            }
+            _ => return Err(format!("Invalid argument: {}", name)),
+        }
+
+        Ok(())
+    }
+}
+
+impl WindowOptions {
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match name {
+            "scrolloff" => Some(Value::Number(self.scrolloff)),
+            _ => None,
+        }
+    }
+
+    /// Sets the option called `name` to `value`, which is of the kind of the option.
+    pub fn set(&mut self, name: &str, value: Value) -> Result<(), String> {
+        match (name, value) {
+            ("scrolloff", Value::Number(number)) => self.scrolloff = number,
+            _ => return Err(format!("Invalid argument: {}", name)),
+        }
+
+        Ok(())
+    }
+}
+
+impl Value {
+    /// Parses the value given to an option of `kind` with `:set`.
+    pub fn parse(kind: Kind, text: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid argument: {}", text);
+
+        match kind {
+            Kind::Flag => match text {
+                "true" => Ok(Self::Flag(true)),
+                "false" => Ok(Self::Flag(false)),
+                _ => Err(invalid()),
+            },
+            Kind::Number => text.parse().map(Self::Number).map_err(|_| invalid()),
+            Kind::Text => Ok(Self::Text(text.to_string())),
+        }
+    }
+
+    /// Converts the value given to an option of `kind` in the config file.
+    pub fn from_toml(kind: Kind, value: &toml::Value) -> Result<Self, String> {
+        match (kind, value) {
+            (Kind::Flag, toml::Value::Boolean(flag)) => Ok(Self::Flag(*flag)),
+            (Kind::Number, toml::Value::Integer(number)) => usize::try_from(*number)
+                .map(Self::Number)
+                .map_err(|_| format!("Invalid argument: {}", number)),
+            (Kind::Text, toml::Value::String(text)) => Self::parse(kind, text),
+            _ => Err(format!("Invalid argument: {}", value)),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flag(flag) => write!(f, "{}", flag),
+            Self::Number(number) => write!(f, "{}", number),
+            Self::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Returns the directory of the config file and themes: `$XDG_CONFIG_HOME/edicode`, or
+/// `~/.config/edicode`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) if !config_home.is_empty() => PathBuf::from(config_home),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config_home.join("edicode"))
+}
+
+/// Reads the options set in the config file, which has none when it doesn't exist.
+pub fn read_config() -> Result<toml::Table, String> {
+    let Some(path) = config_dir().map(|dir| dir.join("config.toml")) else {
+        return Ok(toml::Table::new());
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => contents
+            .parse()
+            .map_err(|e: toml::de::Error| format!("{}: {}", path.display(), e.message())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(toml::Table::new()),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::{HashMap, VecDeque};
+
+const HISTORY_SIZE: usize = 9;
+
+/// How the contents of a register are put back into a buffer.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RegisterKind {
+    Character,
+    Line,
+    Block,
+}
+
+#[derive(Clone)]
+pub struct Register {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+/// Holds the unnamed register `""`, the last yank `"0`, the yank and delete history `"1` to
+/// `"9`, the named registers `"a` to `"z` and the last known contents of the clipboard
+/// registers `"+` and `"*`.
+#[derive(Default)]
+pub struct Registers {
+    unnamed: Option<Register>,
+    last_yank: Option<Register>,
+    history: VecDeque<Register>,
+    named: HashMap<char, Register>,
+    clipboard: HashMap<char, Register>,
+}
+
+impl Register {
+    pub fn new(text: String, kind: RegisterKind) -> Self {
+        Self { text, kind }
+    }
+}
+
+impl Registers {
+    /// Returns whether `name` can be used after `"` to select a register.
+    pub fn is_valid_name(name: char) -> bool {
+        name.is_ascii_alphanumeric() || matches!(name, '"' | '_' | '+' | '*')
+    }
+
+    pub fn get(&self, name: Option<char>) -> Option<&Register> {
+        match name {
+            None | Some('"') => self.unnamed.as_ref(),
+            Some('0') => self.last_yank.as_ref(),
+            Some(digit @ '1'..='9') => self.history.get(digit as usize - '1' as usize),
+            Some(selection @ ('+' | '*')) => self.clipboard.get(&selection),
+            Some(letter) if letter.is_ascii_alphabetic() => {
+                self.named.get(&letter.to_ascii_lowercase())
+            }
+            Some(_) => None,
+        }
+    }
+
+    /// Stores yanked text in `name`, the unnamed register and `"0`.
+    pub fn yank(&mut self, name: Option<char>, register: Register) {
+        if name == Some('_') {
+            return;
+        }
+
+        self.last_yank = Some(register.clone());
+        self.store(name, register);
+    }
+
+    /// Stores deleted text in `name` and the unnamed register.
+    pub fn delete(&mut self, name: Option<char>, register: Register) {
+        if name == Some('_') {
+            return;
+        }
+
+        self.store(name, register);
+    }
+
+    /// Stores a recorded macro in register `name`, appending to it for an uppercase name.
+    /// Unlike yanks, recording leaves the unnamed register and the history alone.
+    pub fn record(&mut self, name: char, text: String) {
+        let key = name.to_ascii_lowercase();
+        let register = Register::new(text, RegisterKind::Character);
+        let register = match self.named.get(&key) {
+            Some(existing) if name.is_ascii_uppercase() => append(existing, &register),
+            _ => register,
+        };
+        self.named.insert(key, register);
+    }
+
+    /// Updates a clipboard register with text read from the system clipboard. The kind of
+    /// the last register stored there is kept if the text did not change meanwhile.
+    pub fn set_clipboard(&mut self, name: char, text: String) {
+        if self
+            .clipboard
+            .get(&name)
+            .is_some_and(|register| register.text == text)
+        {
+            return;
+        }
+
+        let kind = if text.ends_with('\n') {
+            RegisterKind::Line
+        } else {
+            RegisterKind::Character
+        };
+        self.clipboard.insert(name, Register::new(text, kind));
+    }
+
+    fn store(&mut self, name: Option<char>, register: Register) {
+        let register = match name {
+            Some(letter) if letter.is_ascii_alphabetic() => {
+                let key = letter.to_ascii_lowercase();
+                let register = match self.named.get(&key) {
+                    // An uppercase name appends to the register instead of replacing it.
+                    Some(existing) if letter.is_ascii_uppercase() => append(existing, &register),
+                    _ => register,
+                };
+                self.named.insert(key, register.clone());
+                register
+            }
+            Some(selection @ ('+' | '*')) => {
+                self.clipboard.insert(selection, register.clone());
+                register
+            }
+            _ => register,
+        };
+
+        self.history.push_front(register.clone());
+        self.history.truncate(HISTORY_SIZE);
+        self.unnamed = Some(register);
+    }
+}
+
+fn append(existing: &Register, register: &Register) -> Register {
+    let mut text = existing.text.clone();
+    if register.kind == RegisterKind::Line && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(&register.text);
+
+    let kind = if existing.kind == RegisterKind::Line || register.kind == RegisterKind::Line {
+        RegisterKind::Line
+    } else {
+        existing.kind
+    };
+
+    Register { text, kind }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RegisterKind::*;
+
+    fn characters(text: &str) -> Register {
+        Register::new(text.to_string(), Character)
+    }
+
+    fn lines(text: &str) -> Register {
+        Register::new(text.to_string(), Line)
+    }
+
+    fn text(registers: &Registers, name: char) -> Option<&str> {
+        registers
+            .get(Some(name))
+            .map(|register| register.text.as_str())
+    }
+
+    fn kind(registers: &Registers, name: char) -> Option<RegisterKind> {
+        registers.get(Some(name)).map(|register| register.kind)
+    }
+
+    #[test]
+    fn valid_names() {
+        for name in ['a', 'Z', '0', '9', '"', '_', '+', '*'] {
+            assert!(Registers::is_valid_name(name), "{}", name);
+        }
+        for name in ['-', '.', ' ', 'é'] {
+            assert!(!Registers::is_valid_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn yanks_and_deletes_fill_the_unnamed_register() {
+        let mut registers = Registers::default();
+        assert!(registers.get(None).is_none());
+
+        registers.yank(None, characters("yanked"));
+        assert_eq!(text(&registers, '"'), Some("yanked"));
+        assert_eq!(registers.get(None).unwrap().text, "yanked");
+
+        registers.delete(None, lines("deleted\n"));
+        assert_eq!(text(&registers, '"'), Some("deleted\n"));
+        assert!(kind(&registers, '"') == Some(Line));
+    }
+
+    #[test]
+    fn last_yank_holds_only_yanks() {
+        let mut registers = Registers::default();
+        registers.delete(None, characters("deleted"));
+        assert!(registers.get(Some('0')).is_none());
+
+        registers.yank(None, characters("first"));
+        registers.delete(None, characters("deleted again"));
+        assert_eq!(text(&registers, '0'), Some("first"));
+
+        registers.yank(Some('a'), characters("second"));
+        assert_eq!(text(&registers, '0'), Some("second"));
+    }
+
+    #[test]
+    fn history_keeps_the_last_nine() {
+        let mut registers = Registers::default();
+        for index in 1..=12 {
+            registers.delete(None, characters(&index.to_string()));
+        }
+
+        assert_eq!(text(&registers, '1'), Some("12"));
+        assert_eq!(text(&registers, '2'), Some("11"));
+        assert_eq!(text(&registers, '9'), Some("4"));
+    }
+
+    #[test]
+    fn black_hole_register() {
+        let mut registers = Registers::default();
+        registers.yank(None, characters("kept"));
+        registers.yank(Some('_'), characters("yanked"));
+        registers.delete(Some('_'), characters("deleted"));
+
+        assert_eq!(text(&registers, '"'), Some("kept"));
+        assert_eq!(text(&registers, '0'), Some("kept"));
+        assert_eq!(text(&registers, '1'), Some("kept"));
+        assert!(registers.get(Some('2')).is_none());
+        assert!(registers.get(Some('_')).is_none());
+    }
+
+    #[test]
+    fn named_registers() {
+        let mut registers = Registers::default();
+        registers.yank(Some('a'), characters("one"));
+        registers.delete(Some('b'), characters("two"));
+
+        assert_eq!(text(&registers, 'a'), Some("one"));
+        assert_eq!(text(&registers, 'A'), Some("one"));
+        assert_eq!(text(&registers, 'b'), Some("two"));
+        assert_eq!(text(&registers, '"'), Some("two"));
+
+        registers.yank(Some('a'), characters("three"));
+        assert_eq!(text(&registers, 'a'), Some("three"));
+    }
+
+    #[test]
+    fn uppercase_names_append() {
+        let mut registers = Registers::default();
+        registers.yank(Some('A'), characters("new"));
+        assert_eq!(text(&registers, 'a'), Some("new"));
+
+        registers.yank(Some('A'), characters(" more"));
+        assert_eq!(text(&registers, 'a'), Some("new more"));
+        assert!(kind(&registers, 'a') == Some(Character));
+        // The unnamed register gets the whole register, not only what was appended.
+        assert_eq!(text(&registers, '"'), Some("new more"));
+    }
+
+    #[test]
+    fn appending_lines_makes_a_line_register() {
+        let mut registers = Registers::default();
+        registers.yank(Some('a'), characters("word"));
+        registers.delete(Some('A'), lines("line\n"));
+        assert_eq!(text(&registers, 'a'), Some("word\nline\n"));
+        assert!(kind(&registers, 'a') == Some(Line));
+
+        registers.yank(Some('b'), lines("line\n"));
+        registers.yank(Some('B'), characters("word"));
+        assert_eq!(text(&registers, 'b'), Some("line\nword"));
+        assert!(kind(&registers, 'b') == Some(Line));
+
+        registers.yank(Some('c'), Register::new("ab\ncd".to_string(), Block));
+        registers.yank(Some('C'), characters("ef"));
+        assert!(kind(&registers, 'c') == Some(Block));
+    }
+
+    #[test]
+    fn macros() {
+        let mut registers = Registers::default();
+        registers.yank(None, characters("yanked"));
+        registers.record('q', "dd".to_string());
+        registers.record('Q', "j".to_string());
+
+        assert_eq!(text(&registers, 'q'), Some("ddj"));
+        assert_eq!(text(&registers, '"'), Some("yanked"));
+        assert!(registers.get(Some('2')).is_none());
+
+        registers.record('q', "x".to_string());
+        assert_eq!(text(&registers, 'q'), Some("x"));
+    }
+
+    #[test]
+    fn clipboard_registers() {
+        let mut registers = Registers::default();
+        registers.yank(Some('+'), characters("line\n"));
+        assert!(kind(&registers, '+') == Some(Character));
+
+        // The kind is kept while the clipboard holds the same text.
+        registers.set_clipboard('+', "line\n".to_string());
+        assert!(kind(&registers, '+') == Some(Character));
+
+        registers.set_clipboard('+', "other\n".to_string());
+        assert!(kind(&registers, '+') == Some(Line));
+        registers.set_clipboard('*', "word".to_string());
+        assert!(kind(&registers, '*') == Some(Character));
+        assert_eq!(text(&registers, '+'), Some("other\n"));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{cursor::Position, register::RegisterKind};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SelectionKind {
+    Character,
+    Line,
+    Block,
+}
+
+/// A visual selection between an anchor and the cursor. `start` and `end` are ordered and
+/// both inclusive.
+#[derive(Clone, Copy)]
+pub struct Selection {
+    pub kind: SelectionKind,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Selection {
+    pub fn new(kind: SelectionKind, anchor: Position, cursor: Position) -> Self {
+        let (start, end) = match kind {
+            SelectionKind::Character | SelectionKind::Line => {
+                (anchor.min(cursor), anchor.max(cursor))
+            }
+            SelectionKind::Block => (
+                Position {
+                    x: anchor.x.min(cursor.x),
+                    y: anchor.y.min(cursor.y),
+                },
+                Position {
+                    x: anchor.x.max(cursor.x),
+                    y: anchor.y.max(cursor.y),
+                },
+            ),
+        };
+
+        Self { kind, start, end }
+    }
+
+    /// Returns the selected graphemes of row `y` as a `start..end` range, `end` excluded.
+    pub fn row_range(&self, y: usize, row_len: usize) -> Option<(usize, usize)> {
+        if y < self.start.y || y > self.end.y {
+            return None;
+        }
+
+        let (start, end) = match self.kind {
+            SelectionKind::Line => (0, row_len),
+            SelectionKind::Block => (self.start.x, self.end.x + 1),
+            SelectionKind::Character => {
+                let start = if y == self.start.y { self.start.x } else { 0 };
+                let end = if y == self.end.y {
+                    self.end.x + 1
+                } else {
+                    row_len
+                };
+                (start, end)
+            }
+        };
+
+        Some((start.min(row_len), end.min(row_len)))
+    }
+}
+
+impl From<SelectionKind> for RegisterKind {
+    fn from(value: SelectionKind) -> Self {
+        match value {
+            SelectionKind::Character => RegisterKind::Character,
+            SelectionKind::Line => RegisterKind::Line,
+            SelectionKind::Block => RegisterKind::Block,
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use regex::{Regex, RegexBuilder};
+
+/// A parsed `:s/pattern/replacement/flags` command.
+pub struct Substitution {
+    pub first_line: usize,
+    pub last_line: usize,
+    pub pattern: Regex,
+    pub replacement: String,
+    pub global: bool,
+    pub confirm: bool,
+}
+
+impl Substitution {
+    /// Parses `command` as a substitute command. Returns `None` when the command is not a
+    /// substitution at all, so other commands can be tried.
+    ///
+    /// `current_line` and `line_count` are used to resolve `.`, `$` and `%` in the range,
+    /// `last_pattern` is used when the pattern is left empty.
+    pub fn parse(
+        command: &str,
+        current_line: usize,
+        line_count: usize,
+        last_pattern: Option<&str>,
+    ) -> Option<Result<Self, String>> {
+        let (range, rest) = split_range(command);
+        let rest = rest
+            .strip_prefix("substitute")
+            .or_else(|| rest.strip_prefix('s'))?;
+
+        let delimiter = rest.chars().next()?;
+        if delimiter.is_alphanumeric() || delimiter.is_whitespace() || delimiter == '\\' {
+            return None;
+        }
+
+        Some(Self::parse_parts(
+            range,
+            &rest[delimiter.len_utf8()..],
+            delimiter,
+            current_line,
+            line_count,
+            last_pattern,
+        ))
+    }
+
+    fn parse_parts(
+        range: &str,
+        rest: &str,
+        delimiter: char,
+        current_line: usize,
+        line_count: usize,
+        last_pattern: Option<&str>,
+    ) -> Result<Self, String> {
+        let (first_line, last_line) = parse_range(range, current_line, line_count)?;
+
+        let parts = split_unescaped(rest, delimiter);
+        let pattern = parts.first().cloned().unwrap_or_default();
+        let replacement = parts.get(1).cloned().unwrap_or_default();
+        let flags = parts.get(2).cloned().unwrap_or_default();
+
+        let mut global = false;
+        let mut confirm = false;
+        let mut case_insensitive = false;
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'c' => confirm = true,
+                'i' => case_insensitive = true,
+                'I' => case_insensitive = false,
+                _ => return Err(format!("Invalid flag: {}", flag)),
+            }
+        }
+
+        let pattern = if pattern.is_empty() {
+            match last_pattern {
+                Some(last_pattern) => regex::escape(last_pattern),
+                None => return Err("No previous search pattern".to_string()),
+            }
+        } else {
+            pattern
+        };
+
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|e| format!("Invalid pattern: {}", e))?;
+
+        Ok(Self {
+            first_line,
+            last_line,
+            pattern,
+            replacement: convert_replacement(&replacement),
+            global,
+            confirm,
+        })
+    }
+}
+
+/// Splits the leading line range (`%`, `10,20`, `.,$`...) from the command name.
+fn split_range(command: &str) -> (&str, &str) {
+    let end = command
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '%' | '.' | '$' | ',' | '+' | '-')))
+        .unwrap_or(command.len());
+
+    command.split_at(end)
+}
+
+/// Resolves a range to zero-based inclusive line indices.
+fn parse_range(
+    range: &str,
+    current_line: usize,
+    line_count: usize,
+) -> Result<(usize, usize), String> {
+    let last = line_count.saturating_sub(1);
+
+    if range.is_empty() {
+        return Ok((current_line, current_line));
+    }
+
+    if range == "%" {
+        return Ok((0, last));
+    }
+
+    let (first, second) = match range.split_once(',') {
+        Some((first, second)) => (
+            parse_address(first, current_line, last)?,
+            parse_address(second, current_line, last)?,
+        ),
+        None => {
+            let address = parse_address(range, current_line, last)?;
+            (address, address)
+        }
+    };
+
+    if first > second {
+        return Err("Backwards range given".to_string());
+    }
+
+    if second > last {
+        return Err("Invalid range".to_string());
+    }
+
+    Ok((first, second))
+}
+
+fn parse_address(address: &str, current_line: usize, last: usize) -> Result<usize, String> {
+    let invalid = || format!("Invalid range: {}", address);
+
+    let (base, offset) = match address.find(['+', '-']) {
+        Some(index) => address.split_at(index),
+        None => (address, ""),
+    };
+
+    let base = match base {
+        "" | "." => current_line,
+        "$" => last,
+        number => number
+            .parse::<usize>()
+            .map_err(|_| invalid())?
+            .checked_sub(1)
+            .ok_or_else(invalid)?,
+    };
+
+    if offset.is_empty() {
+        return Ok(base);
+    }
+
+    let amount = offset[1..].parse::<usize>().unwrap_or(1);
+    if offset.starts_with('+') {
+        Ok(base + amount)
+    } else {
+        base.checked_sub(amount).ok_or_else(invalid)
+    }
+}
+
+/// Splits `text` on `delimiter`, skipping escaped delimiters and unescaping them.
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => parts.last_mut().unwrap().push(next),
+                Some(next) => {
+                    parts.last_mut().unwrap().push('\\');
+                    parts.last_mut().unwrap().push(next);
+                }
+                None => parts.last_mut().unwrap().push('\\'),
+            },
+            c if c == delimiter => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+
+    parts
+}
+
+/// Converts a vim-style replacement (`\1`, `&`, `\r`) to the syntax `Captures::expand` uses.
+/// Like in vim, `\r` splits the line while `\n` inserts a null character.
+fn convert_replacement(replacement: &str) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => result.push_str(&format!("${{{}}}", digit)),
+                Some('r') => result.push('\n'),
+                Some('n') => result.push('\0'),
+                Some('t') => result.push('\t'),
+                Some('&') => result.push('&'),
+                Some('$') => result.push_str("$$"),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            },
+            '&' => result.push_str("${0}"),
+            '$' => result.push_str("$$"),
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(command: &str) -> Result<Substitution, String> {
+        Substitution::parse(command, 2, 10, Some("a.b")).expect("not a substitution")
+    }
+
+    fn substitute(command: &str, text: &str) -> String {
+        let substitution = parse(command).unwrap();
+        let limit = if substitution.global { 0 } else { 1 };
+        substitution
+            .pattern
+            .replacen(text, limit, substitution.replacement.as_str())
+            .into_owned()
+    }
+
+    #[test]
+    fn other_commands_are_not_substitutions() {
+        for command in ["set number", "sp", "s", "s x", "saveas", "%d"] {
+            assert!(
+                Substitution::parse(command, 0, 1, None).is_none(),
+                "{}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let lines = |command: &str| {
+            let substitution = parse(command).unwrap();
+            (substitution.first_line, substitution.last_line)
+        };
+
+        assert_eq!(lines("s/a/b/"), (2, 2));
+        assert_eq!(lines("%s/a/b/"), (0, 9));
+        assert_eq!(lines("4s/a/b/"), (3, 3));
+        assert_eq!(lines("1,$s/a/b/"), (0, 9));
+        assert_eq!(lines(".,+3s/a/b/"), (2, 5));
+        assert_eq!(lines("-,.substitute/a/b/"), (1, 2));
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        assert_eq!(parse("5,4s/a/b/").err().unwrap(), "Backwards range given");
+        assert_eq!(parse("1,11s/a/b/").err().unwrap(), "Invalid range");
+        assert_eq!(parse("0s/a/b/").err().unwrap(), "Invalid range: 0");
+    }
+
+    #[test]
+    fn flags() {
+        let substitution = parse("s/a/b/gc").unwrap();
+        assert!(substitution.global && substitution.confirm);
+
+        let substitution = parse("s/a/b").unwrap();
+        assert!(!substitution.global && !substitution.confirm);
+
+        assert!(parse("s/a/b/i").unwrap().pattern.is_match("A"));
+        assert!(!parse("s/a/b/iI").unwrap().pattern.is_match("A"));
+        assert_eq!(parse("s/a/b/x").err().unwrap(), "Invalid flag: x");
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(parse("s/x\\/y/z/").unwrap().pattern.as_str(), "x/y");
+        assert_eq!(parse("s#x/y#z#").unwrap().pattern.as_str(), "x/y");
+        assert_eq!(parse("s//z/").unwrap().pattern.as_str(), "a\\.b");
+        assert_eq!(
+            Substitution::parse("s//z/", 0, 1, None)
+                .unwrap()
+                .err()
+                .unwrap(),
+            "No previous search pattern"
+        );
+        assert!(parse("s/(/z/")
+            .err()
+            .unwrap()
+            .starts_with("Invalid pattern"));
+    }
+
+    #[test]
+    fn replacements() {
+        assert_eq!(convert_replacement("\\2\\1"), "${2}${1}");
+        assert_eq!(convert_replacement("<&>\\&"), "<${0}>&");
+        assert_eq!(convert_replacement("$1\\$"), "$$1$$");
+        assert_eq!(convert_replacement("a\\rb\\nc\\td"), "a\nb\0c\td");
+        assert_eq!(convert_replacement("\\/\\"), "/\\");
+
+        assert_eq!(substitute("s/(a)(b)/\\2\\1/g", "abab"), "baba");
+        assert_eq!(substitute("s/a/[&]/", "aa"), "[a]a");
+        assert_eq!(substitute("s/,/\\r/g", "a,b,c"), "a\nb\nc");
+        assert_eq!(substitute("s/b/$0/", "abc"), "a$0c");
+    }
+}
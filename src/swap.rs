@@ -0,0 +1,266 @@
+use crate::buffer;
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{self, Path, PathBuf},
+    process,
+};
+
+const HEADER: &str = "edicode swap";
+const EXTENSIONS: [&str; 3] = ["swp", "swo", "swn"];
+/// Past this many differing lines on each side, a diff lists them all instead of matching
+/// them up.
+const MAX_DIFF_LINES: usize = 2000;
+
+/// What a swap file holds: the text of a buffer which had not been saved, and the process
+/// which was editing it.
+pub struct Swap {
+    pub pid: u32,
+    pub text: String,
+}
+
+impl Swap {
+    /// Returns whether the process which wrote the swap file is another one still running,
+    /// which means the file is being edited there.
+    pub fn is_in_use(&self) -> bool {
+        self.pid != process::id() && Path::new(&format!("/proc/{}", self.pid)).exists()
+    }
+}
+
+/// Returns the paths the swap file of `save_location` may have, by order of preference:
+/// `.name.swp`, then `.name.swo` and `.name.swn` when it is taken, next to the file and then
+/// in the state directory, for when the directory of the file can't be written to.
+pub fn candidates(save_location: &str) -> Vec<PathBuf> {
+    let path = path::absolute(save_location).unwrap_or_else(|_| PathBuf::from(save_location));
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut candidates = EXTENSIONS
+        .iter()
+        .map(|extension| path.with_file_name(format!(".{}.{}", name, extension)))
+        .collect::<Vec<PathBuf>>();
+
+    if let Some(directory) = state_directory() {
+        let escaped = path.to_string_lossy().replace('/', "%");
+        candidates.extend(
+            EXTENSIONS
+                .iter()
+                .map(|extension| directory.join(format!("{}.{}", escaped, extension))),
+        );
+    }
+
+    candidates
+}
+
+/// Returns the path of the swap file of the buffer `id` of this process when it has no
+/// file, which is in the state directory.
+fn unnamed_path(id: usize) -> Option<PathBuf> {
+    Some(state_directory()?.join(format!("unnamed-{}-{}.swp", process::id(), id)))
+}
+
+/// Returns the swap files left for buffers which had no file.
+pub fn find_unnamed() -> Vec<PathBuf> {
+    let Some(Ok(entries)) = state_directory().map(fs::read_dir) else {
+        return Vec::new();
+    };
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("unnamed-") && name.ends_with(".swp"))
+        })
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    paths
+}
+
+/// Returns the swap files left for `save_location`.
+pub fn find(save_location: &str) -> Vec<PathBuf> {
+    candidates(save_location)
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Writes `text` to the swap file of `save_location`, or of the buffer `id` when it has no
+/// file, and returns its path. `previous` is where it was last written, if anywhere, which is
+/// reused so other swap files are left alone. The swap file gets the permissions of the
+/// file, so that it doesn't let anyone else read it.
+pub fn write(
+    save_location: Option<&str>,
+    id: usize,
+    text: &str,
+    previous: Option<&Path>,
+) -> io::Result<PathBuf> {
+    let candidates = match save_location {
+        Some(save_location) => candidates(save_location),
+        None => unnamed_path(id).into_iter().collect(),
+    };
+    let previous = previous.filter(|previous| candidates.iter().any(|path| path == previous));
+    let paths = previous
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(candidates.iter().filter(|path| !path.exists()).cloned());
+
+    let contents = format!(
+        "{}\npid: {}\npath: {}\n\n{}",
+        HEADER,
+        process::id(),
+        save_location.unwrap_or_default(),
+        text
+    );
+
+    let mode = save_location
+        .and_then(|save_location| fs::metadata(save_location).ok())
+        .map(|metadata| metadata.permissions().mode() & 0o777)
+        .unwrap_or(0o600);
+
+    let mut error = io::Error::new(io::ErrorKind::AlreadyExists, "No swap file name left");
+    for path in paths {
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+
+        let temporary = buffer::temporary_path(&path);
+        match replace_swap_file(&path, &temporary, contents.as_bytes(), mode) {
+            Ok(()) => return Ok(path),
+            Err(e) => {
+                let _ = fs::remove_file(&temporary);
+                error = e;
+            }
+        }
+    }
+
+    Err(error)
+}
+
+/// Writes `contents` to `temporary` and renames it over `path`, so that a crash while
+/// writing never leaves a swap file cut short.
+fn replace_swap_file(
+    path: &Path,
+    temporary: &Path,
+    contents: &[u8],
+    mode: u32,
+) -> Result<(), io::Error> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+
+    fs::rename(temporary, path)
+}
+
+pub fn read(path: &Path) -> io::Result<Swap> {
+    let contents = fs::read_to_string(path)?;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Not an edicode swap file");
+
+    let (header, text) = contents.split_once("\n\n").ok_or_else(invalid)?;
+    let mut lines = header.lines();
+    if lines.next() != Some(HEADER) {
+        return Err(invalid());
+    }
+
+    let pid = lines
+        .find_map(|line| line.strip_prefix("pid: "))
+        .and_then(|pid| pid.parse().ok())
+        .ok_or_else(invalid)?;
+
+    Ok(Swap {
+        pid,
+        text: text.to_string(),
+    })
+}
+
+/// Returns the lines of `old` and `new`, those only in `old` starting with `-`, those only in
+/// `new` with `+`, and the ones in both with a space.
+pub fn diff(old: &str, new: &str) -> Vec<String> {
+    let old = old.lines().collect::<Vec<&str>>();
+    let new = new.lines().collect::<Vec<&str>>();
+
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut lines = old[..prefix]
+        .iter()
+        .map(|line| format!("  {}", line))
+        .collect::<Vec<String>>();
+
+    if old_middle.len() > MAX_DIFF_LINES || new_middle.len() > MAX_DIFF_LINES {
+        lines.extend(old_middle.iter().map(|line| format!("- {}", line)));
+        lines.extend(new_middle.iter().map(|line| format!("+ {}", line)));
+    } else {
+        lines.extend(diff_lines(old_middle, new_middle));
+    }
+
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| format!("  {}", line)),
+    );
+    lines
+}
+
+/// Matches up the lines of `old` and `new` along their longest common subsequence.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<String> {
+    // `common[i][j]` is the length of the longest common subsequence of `old[i..]` and
+    // `new[j..]`.
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+
+    lines
+}
+
+/// Returns `$XDG_STATE_HOME/edicode/swap`, or `~/.local/state/edicode/swap`.
+fn state_directory() -> Option<PathBuf> {
+    let state = match env::var_os("XDG_STATE_HOME") {
+        Some(state) if !state.is_empty() => PathBuf::from(state),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+    };
+
+    Some(state.join("edicode").join("swap"))
+}
@@ -0,0 +1,24 @@
+use crate::{
+    options::WindowOptions,
+    window::{Layout, Window},
+};
+
+/// A tab page, with its own layout of windows. The windows of the current tab are kept by
+/// the editor, so a tab only holds them while another one is shown.
+#[derive(Default)]
+pub struct Tab {
+    pub windows: Vec<Window>,
+    pub layout: Layout,
+    pub current_window: usize,
+}
+
+impl Tab {
+    /// Returns a tab with a single window showing `buffer`.
+    pub fn new(buffer: usize, options: WindowOptions) -> Self {
+        Self {
+            windows: vec![Window::new(buffer, options)],
+            layout: Layout::Window(0),
+            current_window: 0,
+        }
+    }
+}
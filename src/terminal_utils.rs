@@ -1,5 +1,10 @@
 use crate::cursor::Position;
-use termion::{clear::*, color::*, cursor::*};
+use std::{
+    io,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+use termion::{clear::*, color::*, cursor::*, event::Key, input::TermRead};
 
 pub fn hide_cursor() {
     print!("{}", Hide);
@@ -27,7 +32,7 @@ pub fn clear_line() {
     print!("{}", CurrentLine);
 }
 
-pub fn set_bg_color(color: Rgb) {
+pub fn set_bg_color(color: impl Color) {
     print!("{}", Bg(color));
 }
 
@@ -35,7 +40,7 @@ pub fn reset_bg_color() {
     print!("{}", Bg(Reset));
 }
 
-pub fn set_fg_color(color: Rgb) {
+pub fn set_fg_color(color: impl Color) {
     print!("{}", Fg(color));
 }
 
@@ -43,10 +48,59 @@ pub fn reset_fg_color() {
     print!("{}", Fg(Reset));
 }
 
-pub fn color_bg(string: impl std::fmt::Display, color: impl Color) -> String {
-    format!("{}{}{}", Bg(color), string, Bg(Reset))
-}
-
 pub fn color_fg(string: impl std::fmt::Display, color: impl Color) -> String {
     format!("{}{}{}", Fg(color), string, Fg(Reset))
 }
+
+/// Reads keys from the terminal on a thread of its own, so that waiting for one can time
+/// out.
+pub fn read_keys() -> Receiver<Result<Key, io::Error>> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for key in io::stdin().keys() {
+            if sender.send(key).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Asks the terminal to put `text` in the system clipboard with an OSC 52 escape sequence.
+/// Inside tmux the sequence is wrapped so it is passed through to the outer terminal.
+pub fn copy_to_clipboard(text: &str, primary: bool) {
+    let target = if primary { 'p' } else { 'c' };
+    let sequence = format!("\x1b]52;{};{}\x07", target, base64_encode(text.as_bytes()));
+
+    if std::env::var_os("TMUX").is_some() {
+        print!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"));
+    } else {
+        print!("{}", sequence);
+    }
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(ALPHABET[(n >> (18 - i * 6) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+
+    result
+}
@@ -0,0 +1,618 @@
+use crate::{
+    buffer::Buffer, cursor::Position, highlighting::HighlightingOptions, motion,
+    operator::TextRange,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A text object typed after `i` or `a` when an operator is pending or in visual mode.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TextObject {
+    Word { big: bool },
+    Sentence,
+    Paragraph,
+    Quote(char),
+    Bracket { open: char, close: char },
+    Tag,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+/// A span of rows flattened to one char per grapheme, rows being separated by `'\n'`.
+struct Text {
+    chars: Vec<char>,
+    positions: Vec<Position>,
+    end: Position,
+}
+
+impl TextObject {
+    /// Returns the object named by `c`. Quotes are the string delimiters of the file type,
+    /// or any of `"`, `'` and `` ` `` when the file type doesn't highlight strings.
+    pub fn from_char(c: char, options: &HighlightingOptions) -> Option<Self> {
+        let object = match c {
+            'w' => Self::Word { big: false },
+            'W' => Self::Word { big: true },
+            's' => Self::Sentence,
+            'p' => Self::Paragraph,
+            '(' | ')' | 'b' => Self::Bracket {
+                open: '(',
+                close: ')',
+            },
+            '{' | '}' | 'B' => Self::Bracket {
+                open: '{',
+                close: '}',
+            },
+            '[' | ']' => Self::Bracket {
+                open: '[',
+                close: ']',
+            },
+            '<' | '>' => Self::Bracket {
+                open: '<',
+                close: '>',
+            },
+            't' => Self::Tag,
+            c if options.is_string_delimiter(c) => Self::Quote(c),
+            '"' | '\'' | '`' if !options.highlight_strings() => Self::Quote(c),
+            _ => return None,
+        };
+
+        Some(object)
+    }
+
+    /// Returns the text covered by `count` objects around `cursor`. `inner` selects the `i`
+    /// variant, which leaves out the surrounding white space, quotes, brackets or tags.
+    pub fn range(
+        &self,
+        buffer: &Buffer,
+        cursor: Position,
+        count: usize,
+        inner: bool,
+    ) -> Option<TextRange> {
+        let count = count.max(1);
+
+        match self {
+            Self::Word { big } => word(buffer, cursor, count, inner, *big),
+            Self::Sentence => sentence(buffer, cursor, count, inner),
+            Self::Paragraph => paragraph(buffer, cursor, count, inner),
+            Self::Quote(quote) => self::quote(buffer, cursor, *quote, inner),
+            Self::Bracket { open, close } => bracket(buffer, cursor, count, *open, *close, inner),
+            Self::Tag => tag(buffer, cursor, count, inner),
+        }
+    }
+}
+
+impl Text {
+    fn new(buffer: &Buffer, first: usize, last: usize) -> Self {
+        let mut text = Self {
+            chars: Vec::new(),
+            positions: Vec::new(),
+            end: Position { x: 0, y: first },
+        };
+
+        for y in first..=last {
+            let Some(row) = buffer.row(y) else {
+                break;
+            };
+
+            for (x, grapheme) in row.as_str().graphemes(true).enumerate() {
+                text.chars.push(grapheme.chars().next().unwrap_or_default());
+                text.positions.push(Position { x, y });
+            }
+
+            text.end = Position { x: row.len(), y };
+            if y < last {
+                text.chars.push('\n');
+                text.positions.push(text.end);
+            }
+        }
+
+        text
+    }
+
+    fn whole(buffer: &Buffer) -> Self {
+        Self::new(buffer, 0, buffer.len().saturating_sub(1))
+    }
+
+    /// Returns the index of the grapheme at `at`, or `None` past the end of the text.
+    fn index(&self, at: Position) -> Option<usize> {
+        let index = self.positions.partition_point(|position| *position < at);
+        (index < self.chars.len()).then_some(index)
+    }
+
+    fn position(&self, index: usize) -> Position {
+        self.positions.get(index).copied().unwrap_or(self.end)
+    }
+
+    fn range(&self, start: usize, end: usize) -> TextRange {
+        TextRange::Characters {
+            start: self.position(start),
+            end: self.position(end),
+        }
+    }
+
+    fn is_blank(&self, index: usize) -> bool {
+        self.chars.get(index).is_some_and(|c| c.is_whitespace())
+    }
+}
+
+fn class(c: char, big: bool) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if big || c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// `iw` selects runs of word characters, punctuation or white space, counting each as an
+/// object. `aw` adds the white space after each word, or before the first one when there
+/// is none after the last.
+fn word(
+    buffer: &Buffer,
+    cursor: Position,
+    count: usize,
+    inner: bool,
+    big: bool,
+) -> Option<TextRange> {
+    let row = buffer.row(cursor.y)?;
+    let classes = row
+        .as_str()
+        .graphemes(true)
+        .map(|grapheme| class(grapheme.chars().next().unwrap_or_default(), big))
+        .collect::<Vec<CharClass>>();
+    if classes.is_empty() {
+        return None;
+    }
+
+    let len = classes.len();
+    let x = cursor.x.min(len - 1);
+    let run_end = |index: usize| {
+        let mut end = index;
+        while end < len && classes[end] == classes[index] {
+            end += 1;
+        }
+        end
+    };
+
+    let mut start = x;
+    while start > 0 && classes[start - 1] == classes[x] {
+        start -= 1;
+    }
+
+    let on_blank = classes[x] == CharClass::Blank;
+    let mut end = x;
+    for _ in 0..count {
+        if end >= len {
+            break;
+        }
+
+        end = run_end(end);
+        if !inner && end < len && (on_blank || classes[end] == CharClass::Blank) {
+            end = run_end(end);
+        }
+    }
+
+    if !inner && !on_blank && classes[end - 1] != CharClass::Blank {
+        // Leading white space is only taken when it isn't the indent of the row.
+        let mut leading = start;
+        while leading > 0 && classes[leading - 1] == CharClass::Blank {
+            leading -= 1;
+        }
+        if leading > 0 {
+            start = leading;
+        }
+    }
+
+    Some(TextRange::Characters {
+        start: Position {
+            x: start,
+            y: cursor.y,
+        },
+        end: Position {
+            x: end,
+            y: cursor.y,
+        },
+    })
+}
+
+fn is_blank_row(buffer: &Buffer, y: usize) -> bool {
+    buffer
+        .row(y)
+        .map(|row| row.as_str().trim().is_empty())
+        .unwrap_or(true)
+}
+
+/// Sentences end with `.`, `!` or `?`, optionally followed by closing brackets and quotes,
+/// then white space. They never cross a paragraph boundary.
+fn sentence(buffer: &Buffer, cursor: Position, count: usize, inner: bool) -> Option<TextRange> {
+    if is_blank_row(buffer, cursor.y) {
+        return None;
+    }
+
+    let mut first = cursor.y;
+    while first > 0 && !is_blank_row(buffer, first - 1) {
+        first -= 1;
+    }
+    let mut last = cursor.y;
+    while last + 1 < buffer.len() && !is_blank_row(buffer, last + 1) {
+        last += 1;
+    }
+
+    let text = Text::new(buffer, first, last);
+    let len = text.chars.len();
+    let cursor = text.index(cursor).unwrap_or(len.saturating_sub(1));
+
+    // Each sentence is split into its start, its end and the start of the next one.
+    let mut sentences = Vec::new();
+    let mut start = (0..len).find(|index| !text.is_blank(*index)).unwrap_or(len);
+    while start < len {
+        let mut end = start;
+        while end < len {
+            let c = text.chars[end];
+            end += 1;
+            if matches!(c, '.' | '!' | '?') {
+                while end < len && matches!(text.chars[end], ')' | ']' | '"' | '\'') {
+                    end += 1;
+                }
+                if end == len || text.is_blank(end) {
+                    break;
+                }
+            }
+        }
+
+        let mut next = end;
+        while next < len && text.is_blank(next) {
+            next += 1;
+        }
+        sentences.push((start, end, next));
+        start = next;
+    }
+
+    let current = sentences
+        .iter()
+        .position(|(_, _, next)| cursor < *next)
+        .unwrap_or(sentences.len().checked_sub(1)?);
+    let (start, end, next) = sentences[current];
+    let last = (current + count - 1).min(sentences.len() - 1);
+    let (_, last_end, last_next) = sentences[last];
+
+    if cursor >= end {
+        // On the white space between two sentences, `is` selects the white space and `as`
+        // the sentence after it as well.
+        return match sentences.get(current + 1) {
+            Some((_, next_end, _)) if !inner => Some(text.range(end, *next_end)),
+            _ => Some(text.range(end, next)),
+        };
+    }
+
+    if inner {
+        Some(text.range(start, last_end))
+    } else if last_next > last_end || current == 0 {
+        Some(text.range(start, last_next))
+    } else {
+        // Without white space after the last sentence, the one before the first is taken.
+        Some(text.range(sentences[current - 1].1, last_end))
+    }
+}
+
+/// `ip` selects blocks of non-blank or blank rows, counting each as an object. `ap` adds
+/// the blank rows after each paragraph, or before it when there are none after.
+fn paragraph(buffer: &Buffer, cursor: Position, count: usize, inner: bool) -> Option<TextRange> {
+    let len = buffer.len();
+    if len == 0 {
+        return None;
+    }
+
+    let y = cursor.y.min(len - 1);
+    let blank = is_blank_row(buffer, y);
+    let block_end = |y: usize| {
+        let blank = is_blank_row(buffer, y);
+        let mut end = y;
+        while end + 1 < len && is_blank_row(buffer, end + 1) == blank {
+            end += 1;
+        }
+        end
+    };
+
+    let mut first = y;
+    while first > 0 && is_blank_row(buffer, first - 1) == blank {
+        first -= 1;
+    }
+
+    let blocks = if inner { count } else { count * 2 };
+    let mut last = block_end(y);
+    let mut taken = 1;
+    while taken < blocks && last + 1 < len {
+        last = block_end(last + 1);
+        taken += 1;
+    }
+
+    if !inner && !blank && taken < blocks {
+        while first > 0 && is_blank_row(buffer, first - 1) {
+            first -= 1;
+        }
+    }
+
+    Some(TextRange::Lines { first, last })
+}
+
+/// Quotes are matched on the cursor row, skipping the ones escaped with a backslash. When
+/// the cursor is not inside a string, the first one after it is used.
+fn quote(buffer: &Buffer, cursor: Position, quote: char, inner: bool) -> Option<TextRange> {
+    let text = Text::new(buffer, cursor.y, cursor.y);
+    let chars = &text.chars;
+
+    let quotes = (0..chars.len())
+        .filter(|index| {
+            let backslashes = chars[..*index]
+                .iter()
+                .rev()
+                .take_while(|c| **c == '\\')
+                .count();
+            chars[*index] == quote && backslashes % 2 == 0
+        })
+        .collect::<Vec<usize>>();
+
+    let before = quotes.iter().filter(|index| **index < cursor.x).count();
+    let (open, close) = if quotes.get(before) == Some(&cursor.x) && before % 2 == 1 {
+        (quotes[before - 1], quotes[before])
+    } else if quotes.get(before) == Some(&cursor.x) || before % 2 == 0 {
+        (*quotes.get(before)?, *quotes.get(before + 1)?)
+    } else {
+        (quotes[before - 1], *quotes.get(before)?)
+    };
+
+    if inner {
+        return Some(text.range(open + 1, close));
+    }
+
+    let mut start = open;
+    let mut end = close + 1;
+    if text.is_blank(end) {
+        while text.is_blank(end) {
+            end += 1;
+        }
+    } else {
+        while start > 0 && text.is_blank(start - 1) {
+            start -= 1;
+        }
+    }
+
+    Some(text.range(start, end))
+}
+
+fn bracket(
+    buffer: &Buffer,
+    cursor: Position,
+    count: usize,
+    open: char,
+    close: char,
+    inner: bool,
+) -> Option<TextRange> {
+    let text = Text::whole(buffer);
+    let chars = &text.chars;
+    let index = text.index(cursor)?;
+
+    // Finds the unmatched opening bracket before `end`.
+    let find_open = |end: usize| {
+        let mut depth = 0;
+        for index in (0..end).rev() {
+            if chars[index] == close {
+                depth += 1;
+            } else if chars[index] == open {
+                if depth == 0 {
+                    return Some(index);
+                }
+                depth -= 1;
+            }
+        }
+        None
+    };
+
+    let mut start = if chars[index] == open {
+        index
+    } else {
+        find_open(index)?
+    };
+    for _ in 1..count {
+        start = find_open(start)?;
+    }
+
+    let mut depth = 0;
+    let end = (start + 1..chars.len()).find(|index| {
+        if chars[*index] == open {
+            depth += 1;
+        } else if chars[*index] == close {
+            if depth == 0 {
+                return true;
+            }
+            depth -= 1;
+        }
+        false
+    })?;
+
+    if !inner {
+        return Some(text.range(start, end + 1));
+    }
+
+    // Brackets on rows of their own leave whole rows in between, like a block of code.
+    let (open_at, close_at) = (text.position(start), text.position(end));
+    let opens_row = chars.get(start + 1) == Some(&'\n');
+    let closes_row = close_at.x <= motion::first_non_blank(buffer, close_at.y);
+    if opens_row && closes_row && close_at.y > open_at.y + 1 {
+        return Some(TextRange::Lines {
+            first: open_at.y + 1,
+            last: close_at.y - 1,
+        });
+    }
+
+    Some(text.range(start + 1, end))
+}
+
+/// Tags are paired by name, ignoring self-closing tags, comments and declarations.
+fn tag(buffer: &Buffer, cursor: Position, count: usize, inner: bool) -> Option<TextRange> {
+    let text = Text::whole(buffer);
+    let chars = &text.chars;
+    let index = text.index(cursor)?;
+
+    let mut open_tags: Vec<(String, usize, usize)> = Vec::new();
+    let mut pairs = Vec::new();
+    let mut start = 0;
+    while let Some(offset) = chars[start..].iter().position(|c| *c == '<') {
+        let tag_start = start + offset;
+        let Some(length) = chars[tag_start..].iter().position(|c| *c == '>') else {
+            break;
+        };
+        let tag_end = tag_start + length + 1;
+        let content = chars[tag_start + 1..tag_end - 1].iter().collect::<String>();
+        start = tag_end;
+
+        if let Some(name) = content.strip_prefix('/') {
+            let name = name.trim();
+            if let Some(open) = open_tags.iter().rposition(|(open, _, _)| open == name) {
+                let (_, open_start, open_end) = open_tags[open].clone();
+                open_tags.truncate(open);
+                pairs.push((open_start, open_end, tag_start, tag_end));
+            }
+        } else if !content.ends_with('/') && !content.starts_with(['!', '?']) {
+            let name = content.split_whitespace().next().unwrap_or_default();
+            open_tags.push((name.to_string(), tag_start, tag_end));
+        }
+    }
+
+    let mut enclosing = pairs
+        .into_iter()
+        .filter(|(open_start, _, _, close_end)| *open_start <= index && index < *close_end)
+        .collect::<Vec<(usize, usize, usize, usize)>>();
+    enclosing.sort_by_key(|(open_start, _, _, close_end)| close_end - open_start);
+
+    let (open_start, open_end, close_start, close_end) = *enclosing.get(count - 1)?;
+    if inner {
+        Some(text.range(open_end, close_start))
+    } else {
+        Some(text.range(open_start, close_end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_type::FileType;
+
+    /// Returns what typing `keys`, like `i(` or `2aw`, selects in `text` with the cursor at
+    /// `x` on row `y`. Whole rows are returned as their range.
+    fn select(text: &str, (x, y): (usize, usize), keys: &str) -> Option<String> {
+        let mut buffer = Buffer::default();
+        buffer.insert_str(&Position::default(), text);
+
+        let digits = keys.chars().take_while(char::is_ascii_digit).count();
+        let count = keys[..digits].parse().unwrap_or(1);
+        let mut chars = keys[digits..].chars();
+        let inner = chars.next() == Some('i');
+        let options = HighlightingOptions::from(FileType::PlainText);
+        let object = TextObject::from_char(chars.next()?, &options)?;
+
+        match object.range(&buffer, Position { x, y }, count, inner)? {
+            TextRange::Characters { start, end } => Some(buffer.text_range(&start, &end)),
+            TextRange::Lines { first, last } => Some(format!("rows {}-{}", first, last)),
+            TextRange::Block(_) => None,
+        }
+    }
+
+    #[test]
+    fn words() {
+        let text = "foo bar  baz";
+        assert_eq!(select(text, (1, 0), "iw").unwrap(), "foo");
+        assert_eq!(select(text, (1, 0), "aw").unwrap(), "foo ");
+        assert_eq!(select(text, (5, 0), "aw").unwrap(), "bar  ");
+        assert_eq!(select(text, (10, 0), "aw").unwrap(), "  baz");
+        assert_eq!(select(text, (1, 0), "2iw").unwrap(), "foo ");
+        assert_eq!(select(text, (1, 0), "2aw").unwrap(), "foo bar  ");
+        assert_eq!(select(text, (3, 0), "iw").unwrap(), " ");
+        assert_eq!(select(text, (3, 0), "aw").unwrap(), " bar");
+    }
+
+    #[test]
+    fn big_words() {
+        let text = "  foo.bar(baz) x";
+        assert_eq!(select(text, (2, 0), "iw").unwrap(), "foo");
+        assert_eq!(select(text, (5, 0), "iw").unwrap(), ".");
+        assert_eq!(select(text, (2, 0), "iW").unwrap(), "foo.bar(baz)");
+        // The indent of the row is not taken, even without white space after.
+        assert_eq!(select("  foo", (2, 0), "aw").unwrap(), "foo");
+    }
+
+    #[test]
+    fn sentences() {
+        let text = "One. Two three! Four?";
+        assert_eq!(select(text, (6, 0), "is").unwrap(), "Two three!");
+        assert_eq!(select(text, (6, 0), "as").unwrap(), "Two three! ");
+        assert_eq!(select(text, (17, 0), "as").unwrap(), " Four?");
+        assert_eq!(select(text, (0, 0), "2is").unwrap(), "One. Two three!");
+        assert_eq!(select(text, (4, 0), "is").unwrap(), " ");
+        assert_eq!(select("(Yes.) No.", (1, 0), "is").unwrap(), "(Yes.)");
+        assert_eq!(
+            select("One.\nTwo.\n\nThree.", (0, 1), "as").unwrap(),
+            "\nTwo."
+        );
+    }
+
+    #[test]
+    fn paragraphs() {
+        let text = "a\nb\n\nc";
+        assert_eq!(select(text, (0, 0), "ip").unwrap(), "rows 0-1");
+        assert_eq!(select(text, (0, 0), "ap").unwrap(), "rows 0-2");
+        assert_eq!(select(text, (0, 3), "ap").unwrap(), "rows 2-3");
+        assert_eq!(select(text, (0, 2), "ip").unwrap(), "rows 2-2");
+        assert_eq!(select(text, (0, 0), "3ip").unwrap(), "rows 0-3");
+    }
+
+    #[test]
+    fn quotes() {
+        let text = "say \"hi there\" now";
+        assert_eq!(select(text, (6, 0), "i\"").unwrap(), "hi there");
+        assert_eq!(select(text, (6, 0), "a\"").unwrap(), "\"hi there\" ");
+        assert_eq!(select(text, (4, 0), "i\"").unwrap(), "hi there");
+        assert_eq!(select(text, (13, 0), "i\"").unwrap(), "hi there");
+        assert_eq!(select(text, (0, 0), "i\"").unwrap(), "hi there");
+        assert_eq!(select(text, (16, 0), "i\""), None);
+        assert_eq!(select("'a\\'b' c", (1, 0), "i'").unwrap(), "a\\'b");
+        assert_eq!(select("x = `y`", (5, 0), "a`").unwrap(), " `y`");
+    }
+
+    #[test]
+    fn brackets() {
+        let text = "f(a, (b), c)";
+        assert_eq!(select(text, (6, 0), "i(").unwrap(), "b");
+        assert_eq!(select(text, (6, 0), "a)").unwrap(), "(b)");
+        assert_eq!(select(text, (6, 0), "2ib").unwrap(), "a, (b), c");
+        assert_eq!(select(text, (1, 0), "i(").unwrap(), "a, (b), c");
+        assert_eq!(select(text, (0, 0), "i("), None);
+        assert_eq!(select("[a]", (1, 0), "a[").unwrap(), "[a]");
+        assert_eq!(select("<a>", (1, 0), "i>").unwrap(), "a");
+    }
+
+    #[test]
+    fn blocks() {
+        let text = "fn f() {\n    x;\n}";
+        assert_eq!(select(text, (4, 1), "iB").unwrap(), "rows 1-1");
+        assert_eq!(select(text, (4, 1), "a{").unwrap(), "{\n    x;\n}");
+        assert_eq!(select("{ x;\n}", (2, 0), "i{").unwrap(), " x;\n");
+    }
+
+    #[test]
+    fn tags() {
+        let text = "<a href=\"x\"><b>x</b> <br/>y</a>";
+        assert_eq!(select(text, (15, 0), "it").unwrap(), "x");
+        assert_eq!(select(text, (15, 0), "at").unwrap(), "<b>x</b>");
+        assert_eq!(select(text, (15, 0), "2it").unwrap(), "<b>x</b> <br/>y");
+        assert_eq!(select(text, (26, 0), "it").unwrap(), "<b>x</b> <br/>y");
+        assert_eq!(select(text, (1, 0), "at").unwrap(), text);
+        assert_eq!(select("<a>x</b>", (3, 0), "it"), None);
+    }
+}
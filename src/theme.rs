@@ -0,0 +1,422 @@
+use crate::options;
+use std::{env, fmt, fs, io};
+use termion::color::{AnsiValue, Color, Rgb};
+
+/// The things a theme gives a color to, named `scope.name` in theme files.
+#[derive(Clone, Copy)]
+pub enum Group {
+    Text,
+    Number,
+    String,
+    Match,
+    Selection,
+    StatusBg,
+    StatusFg,
+    StatusInactiveBg,
+    Separator,
+    Error,
+}
+
+impl Group {
+    const ALL: [Group; 10] = [
+        Group::Text,
+        Group::Number,
+        Group::String,
+        Group::Match,
+        Group::Selection,
+        Group::StatusBg,
+        Group::StatusFg,
+        Group::StatusInactiveBg,
+        Group::Separator,
+        Group::Error,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Group::Text => "syntax.text",
+            Group::Number => "syntax.number",
+            Group::String => "syntax.string",
+            Group::Match => "syntax.match",
+            Group::Selection => "selection.bg",
+            Group::StatusBg => "status.bg",
+            Group::StatusFg => "status.fg",
+            Group::StatusInactiveBg => "status.inactive_bg",
+            Group::Separator => "ui.separator",
+            Group::Error => "message.error",
+        }
+    }
+
+    /// Returns the color of the group in the default theme, which themes fall back to.
+    fn default_color(self) -> Rgb {
+        match self {
+            Group::Text => Rgb(255, 255, 255),
+            Group::Number => Rgb(220, 163, 163),
+            Group::String => Rgb(211, 54, 130),
+            Group::Match => Rgb(38, 139, 210),
+            Group::Selection => Rgb(68, 84, 110),
+            Group::StatusBg => Rgb(52, 120, 198),
+            Group::StatusFg => Rgb(255, 255, 255),
+            Group::StatusInactiveBg | Group::Separator => Rgb(60, 60, 60),
+            Group::Error => Rgb(255, 0, 0),
+        }
+    }
+}
+
+/// The options which colored the status bar before themes did, with the group each one
+/// now sets in the current theme.
+const DEPRECATED_OPTIONS: [(&str, Group); 3] = [
+    ("statusbg", Group::StatusBg),
+    ("statusfg", Group::StatusFg),
+    ("statusncbg", Group::StatusInactiveBg),
+];
+
+/// Returns the group set by the deprecated option `name`, if it is one.
+pub fn deprecated_option(name: &str) -> Option<Group> {
+    DEPRECATED_OPTIONS
+        .iter()
+        .find(|(option, _)| *option == name)
+        .map(|(_, group)| *group)
+}
+
+/// How many colors the terminal can show.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorDepth {
+    TrueColor,
+    Colors256,
+    Colors16,
+}
+
+impl ColorDepth {
+    /// Guesses the depth of the terminal from `$COLORTERM` and `$TERM`. Most terminals have
+    /// 256 colors at least, so only those known for having 16, such as the Linux console,
+    /// screen without `-256color` and the vt ones, are given 16.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        let base = term.split('-').next().unwrap_or_default();
+
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            Self::TrueColor
+        } else if term.contains("256color") {
+            Self::Colors256
+        } else if matches!(base, "linux" | "screen" | "ansi" | "cons25" | "dumb")
+            || base.starts_with("vt")
+        {
+            Self::Colors16
+        } else {
+            Self::Colors256
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "truecolor" | "24bit" => Some(Self::TrueColor),
+            "256" => Some(Self::Colors256),
+            "16" => Some(Self::Colors16),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ColorDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TrueColor => write!(f, "truecolor"),
+            Self::Colors256 => write!(f, "256"),
+            Self::Colors16 => write!(f, "16"),
+        }
+    }
+}
+
+/// A color of a theme, written with the closest one the terminal can show.
+#[derive(Clone, Copy, Debug)]
+pub struct TermColor {
+    rgb: Rgb,
+    depth: ColorDepth,
+}
+
+/// The colors of the 16-color palette, as xterm shows them by default.
+const ANSI_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The levels of each component in the color cube of the 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(Rgb(r, g, b): Rgb, (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let component = |a: u8, b: u8| (i32::from(a) - i32::from(b)).unsigned_abs().pow(2);
+    component(r, r2) + component(g, g2) + component(b, b2)
+}
+
+/// Returns the index of the closest color of the 16-color palette.
+fn to_ansi16(color: Rgb) -> u8 {
+    (0..ANSI_COLORS.len())
+        .min_by_key(|index| distance(color, ANSI_COLORS[*index]))
+        .unwrap_or_default() as u8
+}
+
+/// Returns the index of the closest color of the 256-color palette, from either its color
+/// cube or its grays.
+fn to_ansi256(color: Rgb) -> u8 {
+    let level = |component: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|index| CUBE_LEVELS[*index].abs_diff(component))
+            .unwrap_or_default()
+    };
+    let Rgb(r, g, b) = color;
+    let (r, g, b) = (level(r), level(g), level(b));
+    let cube = (CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+
+    // The grays go from 8 to 238 by steps of 10.
+    let average = (u32::from(color.0) + u32::from(color.1) + u32::from(color.2)) / 3;
+    let gray = (average.saturating_sub(3) / 10).min(23);
+    let gray_level = (8 + gray * 10) as u8;
+
+    if distance(color, (gray_level, gray_level, gray_level)) < distance(color, cube) {
+        232 + gray as u8
+    } else {
+        (16 + 36 * r + 6 * g + b) as u8
+    }
+}
+
+impl Color for TermColor {
+    fn write_fg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.depth {
+            ColorDepth::TrueColor => self.rgb.write_fg(f),
+            ColorDepth::Colors256 => AnsiValue(to_ansi256(self.rgb)).write_fg(f),
+            // Written as the original codes, which terminals without 256 colors understand.
+            ColorDepth::Colors16 => match to_ansi16(self.rgb) {
+                index @ 0..=7 => write!(f, "\x1b[{}m", 30 + index),
+                index => write!(f, "\x1b[{}m", 90 + index - 8),
+            },
+        }
+    }
+
+    fn write_bg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.depth {
+            ColorDepth::TrueColor => self.rgb.write_bg(f),
+            ColorDepth::Colors256 => AnsiValue(to_ansi256(self.rgb)).write_bg(f),
+            ColorDepth::Colors16 => match to_ansi16(self.rgb) {
+                index @ 0..=7 => write!(f, "\x1b[{}m", 40 + index),
+                index => write!(f, "\x1b[{}m", 100 + index - 8),
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Theme {
+    pub name: String,
+    colors: [Rgb; Group::ALL.len()],
+    depth: ColorDepth,
+}
+
+impl Theme {
+    /// Returns the built-in theme, which is named `default`.
+    pub fn new(depth: ColorDepth) -> Self {
+        Self {
+            name: "default".to_string(),
+            colors: Group::ALL.map(Group::default_color),
+            depth,
+        }
+    }
+
+    /// Loads the theme called `name` from `themes/name.toml` in the config directory. Theme
+    /// files map the names of groups to colors written `#rrggbb`, for instance
+    /// `number = "#dca3a3"` in a `[syntax]` table, and the groups they leave out keep the
+    /// colors of the default theme.
+    pub fn load(name: &str, depth: ColorDepth) -> Result<Self, String> {
+        // Names are those of files in the themes directory, not paths out of it.
+        if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+            return Err(format!("Invalid color scheme name: {}", name));
+        }
+
+        let mut theme = Self::new(depth);
+        let path =
+            options::config_dir().map(|dir| dir.join("themes").join(format!("{}.toml", name)));
+        let contents = match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(contents)) => contents,
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(format!("{}: {}", name, e))
+            }
+            _ if name == "default" => return Ok(theme),
+            _ => return Err(format!("Cannot find color scheme '{}'", name)),
+        };
+
+        let table: toml::Table = contents
+            .parse()
+            .map_err(|e: toml::de::Error| format!("{}: {}", name, e.message()))?;
+        for (scope, colors) in &table {
+            let colors = colors
+                .as_table()
+                .ok_or(format!("{}: Unknown group: {}", name, scope))?;
+            for (key, color) in colors {
+                let group_name = format!("{}.{}", scope, key);
+                let index = Group::ALL
+                    .iter()
+                    .position(|group| group.name() == group_name)
+                    .ok_or(format!("{}: Unknown group: {}", name, group_name))?;
+                theme.colors[index] = color
+                    .as_str()
+                    .and_then(parse_color)
+                    .ok_or(format!("{}: Invalid color: {}", name, color))?;
+            }
+        }
+
+        theme.name = name.to_string();
+        Ok(theme)
+    }
+
+    pub fn color(&self, group: Group) -> TermColor {
+        TermColor {
+            rgb: self.colors[group as usize],
+            depth: self.depth,
+        }
+    }
+
+    pub fn set_depth(&mut self, depth: ColorDepth) {
+        self.depth = depth;
+    }
+
+    /// Sets the color of `group` to the one written `#rrggbb` in `text`.
+    pub fn set_color(&mut self, group: Group, text: &str) -> Result<(), String> {
+        self.colors[group as usize] =
+            parse_color(text).ok_or(format!("Invalid argument: {}", text))?;
+        Ok(())
+    }
+
+    /// Returns the color of `group` written `#rrggbb`.
+    pub fn describe_color(&self, group: Group) -> String {
+        let Rgb(r, g, b) = self.colors[group as usize];
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+fn parse_color(text: &str) -> Option<Rgb> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let component = |start: usize| u8::from_str_radix(&hex[start..start + 2], 16).ok();
+    Some(Rgb(component(0)?, component(2)?, component(4)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(text: &str) -> Option<(u8, u8, u8)> {
+        parse_color(text).map(|Rgb(r, g, b)| (r, g, b))
+    }
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(rgb("#dca3a3"), Some((220, 163, 163)));
+        assert_eq!(rgb("#00FFff"), Some((0, 255, 255)));
+    }
+
+    #[test]
+    fn parse_invalid_colors() {
+        assert_eq!(rgb("dca3a3"), None);
+        assert_eq!(rgb("#dca3a"), None);
+        assert_eq!(rgb("#dca3a3a"), None);
+        assert_eq!(rgb("#dcg3a3"), None);
+        assert_eq!(rgb("#+1+1+1"), None);
+        assert_eq!(rgb("#ééé"), None);
+        assert_eq!(rgb(""), None);
+    }
+
+    #[test]
+    fn cube_colors() {
+        assert_eq!(to_ansi256(Rgb(0, 0, 0)), 16);
+        assert_eq!(to_ansi256(Rgb(255, 0, 0)), 196);
+        assert_eq!(to_ansi256(Rgb(95, 135, 175)), 67);
+        assert_eq!(to_ansi256(Rgb(135, 135, 135)), 102);
+        assert_eq!(to_ansi256(Rgb(255, 255, 255)), 231);
+        // Components halfway between two levels go to the lower one.
+        assert_eq!(to_ansi256(Rgb(115, 0, 0)), 52);
+        assert_eq!(to_ansi256(Rgb(116, 0, 0)), 88);
+    }
+
+    #[test]
+    fn gray_colors() {
+        assert_eq!(to_ansi256(Rgb(8, 8, 8)), 232);
+        assert_eq!(to_ansi256(Rgb(18, 18, 18)), 233);
+        assert_eq!(to_ansi256(Rgb(128, 128, 128)), 244);
+        assert_eq!(to_ansi256(Rgb(238, 238, 238)), 255);
+        // Past the last gray, white is closer.
+        assert_eq!(to_ansi256(Rgb(250, 250, 250)), 231);
+    }
+
+    #[test]
+    fn ansi16_colors() {
+        assert_eq!(to_ansi16(Rgb(0, 0, 0)), 0);
+        assert_eq!(to_ansi16(Rgb(205, 0, 0)), 1);
+        assert_eq!(to_ansi16(Rgb(0, 0, 200)), 4);
+        assert_eq!(to_ansi16(Rgb(200, 200, 200)), 7);
+        assert_eq!(to_ansi16(Rgb(128, 128, 128)), 8);
+        assert_eq!(to_ansi16(Rgb(255, 0, 0)), 9);
+        assert_eq!(to_ansi16(Rgb(92, 92, 255)), 12);
+        assert_eq!(to_ansi16(Rgb(255, 255, 255)), 15);
+    }
+
+    #[test]
+    fn color_depths() {
+        for depth in [
+            ColorDepth::TrueColor,
+            ColorDepth::Colors256,
+            ColorDepth::Colors16,
+        ] {
+            assert_eq!(ColorDepth::parse(&depth.to_string()), Some(depth));
+        }
+        assert_eq!(ColorDepth::parse("24bit"), Some(ColorDepth::TrueColor));
+        assert_eq!(ColorDepth::parse("8"), None);
+    }
+
+    #[test]
+    fn theme_colors() {
+        let mut theme = Theme::new(ColorDepth::TrueColor);
+        assert_eq!(theme.describe_color(Group::Number), "#dca3a3");
+        theme.set_color(Group::Number, "#0A0b0c").unwrap();
+        assert_eq!(theme.describe_color(Group::Number), "#0a0b0c");
+        assert!(theme.set_color(Group::Number, "0a0b0c").is_err());
+        assert_eq!(theme.describe_color(Group::Number), "#0a0b0c");
+    }
+
+    #[test]
+    fn invalid_theme_names() {
+        for name in ["", "../default", "themes/default", "a\\b", ".."] {
+            let error = Theme::load(name, ColorDepth::TrueColor).err().unwrap();
+            assert_eq!(error, format!("Invalid color scheme name: {}", name));
+        }
+    }
+
+    #[test]
+    fn deprecated_options() {
+        assert_eq!(
+            deprecated_option("statusbg").map(Group::name),
+            Some("status.bg")
+        );
+        assert_eq!(
+            deprecated_option("statusncbg").map(Group::name),
+            Some("status.inactive_bg")
+        );
+        assert!(deprecated_option("number").is_none());
+    }
+}
@@ -0,0 +1,676 @@
+use crate::{cursor::Position, options::WindowOptions};
+use std::collections::HashMap;
+
+/// The smallest height of a window, its status line included, and the smallest width.
+const MIN_HEIGHT: usize = 2;
+const MIN_WIDTH: usize = 1;
+
+/// A view on a buffer with its own cursor and scroll, so several windows can show the same
+/// buffer at different places.
+#[derive(Clone, Default)]
+pub struct Window {
+    pub buffer: usize,
+    pub cursor: Position,
+    pub scroll: Position,
+    pub visual_anchor: Position,
+    pub options: WindowOptions,
+    /// Where the cursor and the scroll were in the buffers previously shown in the window.
+    positions: HashMap<usize, (Position, Position)>,
+}
+
+/// `Horizontal` stacks windows on top of each other, like `:split` does, and `Vertical`
+/// puts them side by side, like `:vsplit` does.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SplitDirection {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Down,
+    Up,
+    Right,
+}
+
+/// A part of the screen, in cells. The area of a window includes its status line.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A tree of splits whose leaves are windows, referred to by their index in the editor.
+/// `sizes` holds the height or width of each child, which is shared out proportionally if
+/// the screen doesn't match their sum.
+pub enum Layout {
+    Window(usize),
+    Split {
+        direction: SplitDirection,
+        children: Vec<Layout>,
+        sizes: Vec<usize>,
+    },
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::Window(0)
+    }
+}
+
+impl Window {
+    pub fn new(buffer: usize, options: WindowOptions) -> Self {
+        Self {
+            buffer,
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Shows `buffer` in the window, back where the cursor was the last time it was shown.
+    pub fn show_buffer(&mut self, buffer: usize) {
+        self.positions
+            .insert(self.buffer, (self.cursor, self.scroll));
+        let (cursor, scroll) = self.positions.remove(&buffer).unwrap_or_default();
+
+        self.buffer = buffer;
+        self.cursor = cursor;
+        self.scroll = scroll;
+    }
+
+    /// Forgets about the buffer at `index`, which is being removed, shifting the indices of
+    /// the ones after it. The window shows `replacement`, an index once `index` is removed,
+    /// if it was showing that buffer.
+    pub fn forget_buffer(&mut self, index: usize, replacement: usize) {
+        self.positions = self
+            .positions
+            .drain()
+            .filter(|(buffer, _)| *buffer != index)
+            .map(|(buffer, positions)| {
+                if buffer > index {
+                    (buffer - 1, positions)
+                } else {
+                    (buffer, positions)
+                }
+            })
+            .collect();
+
+        if self.buffer == index {
+            let (cursor, scroll) = self.positions.remove(&replacement).unwrap_or_default();
+            self.buffer = replacement;
+            self.cursor = cursor;
+            self.scroll = scroll;
+        } else if self.buffer > index {
+            self.buffer -= 1;
+        }
+    }
+}
+
+impl Rect {
+    fn extent(&self, direction: SplitDirection) -> usize {
+        match direction {
+            SplitDirection::Horizontal => self.height,
+            SplitDirection::Vertical => self.width,
+        }
+    }
+
+    fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+impl Layout {
+    /// Returns the area of each window when the layout fills `rect`.
+    pub fn arrange(&self, rect: Rect) -> Vec<(usize, Rect)> {
+        match self {
+            Self::Window(window) => vec![(*window, rect)],
+            Self::Split {
+                direction,
+                children,
+                sizes,
+            } => children
+                .iter()
+                .zip(split_rect(rect, *direction, sizes))
+                .flat_map(|(child, rect)| child.arrange(rect))
+                .collect(),
+        }
+    }
+
+    /// Returns the columns drawn between windows split vertically, as one-cell wide areas.
+    pub fn separators(&self, rect: Rect) -> Vec<Rect> {
+        let Self::Split {
+            direction,
+            children,
+            sizes,
+        } = self
+        else {
+            return Vec::new();
+        };
+
+        let rects = split_rect(rect, *direction, sizes);
+        let mut separators = children
+            .iter()
+            .zip(rects.iter())
+            .flat_map(|(child, rect)| child.separators(*rect))
+            .collect::<Vec<Rect>>();
+
+        if *direction == SplitDirection::Vertical {
+            separators.extend(rects.iter().take(rects.len() - 1).map(|rect| Rect {
+                x: rect.x + rect.width,
+                y: rect.y,
+                width: 1,
+                height: rect.height,
+            }));
+        }
+
+        separators
+    }
+
+    /// Splits `target`, whose area is `rect`, in two, putting `window` above it or to its
+    /// left. Returns `false` when there is not enough room.
+    pub fn split(
+        &mut self,
+        target: usize,
+        window: usize,
+        direction: SplitDirection,
+        rect: Rect,
+    ) -> bool {
+        let extent = rect.extent(direction);
+        let min = min_extent(direction);
+        // A vertical split takes a column for the separator.
+        let needed = match direction {
+            SplitDirection::Horizontal => 2 * min,
+            SplitDirection::Vertical => 2 * min + 1,
+        };
+        if extent < needed {
+            return false;
+        }
+
+        let available = match direction {
+            SplitDirection::Horizontal => extent,
+            SplitDirection::Vertical => extent - 1,
+        };
+        let first = available / 2;
+        let second = available - first;
+
+        if let Some((
+            Self::Split {
+                direction: parent_direction,
+                children,
+                sizes,
+            },
+            index,
+        )) = self.parent_of(target)
+        {
+            if *parent_direction == direction {
+                children.insert(index, Self::Window(window));
+                sizes[index] = second;
+                sizes.insert(index, first);
+                return true;
+            }
+        }
+
+        if let Some(leaf) = self.find_mut(target) {
+            *leaf = Self::Split {
+                direction,
+                children: vec![Self::Window(window), Self::Window(target)],
+                sizes: vec![first, second],
+            };
+        }
+
+        true
+    }
+
+    /// Removes `window` from the layout, giving its room to a neighbour, and shifts the
+    /// indices of the windows after it.
+    pub fn remove(&mut self, window: usize) {
+        if let Some((
+            Self::Split {
+                children, sizes, ..
+            },
+            index,
+        )) = self.parent_of(window)
+        {
+            children.remove(index);
+            let size = sizes.remove(index);
+            let neighbour = index.saturating_sub(1).min(sizes.len() - 1);
+            sizes[neighbour] += size;
+        }
+
+        self.collapse();
+        self.shift_from(window);
+    }
+
+    /// Makes `window` the only one in the layout.
+    pub fn only(&mut self, window: usize) {
+        *self = Self::Window(window);
+    }
+
+    /// Grows `window` by `delta` cells in `direction`, taking the room from its neighbour.
+    pub fn resize(&mut self, window: usize, direction: SplitDirection, delta: isize) {
+        let Some((Self::Split { sizes, .. }, index)) = self.ancestor_split(window, direction)
+        else {
+            return;
+        };
+
+        let neighbour = if index + 1 < sizes.len() {
+            index + 1
+        } else {
+            index - 1
+        };
+        let min = min_extent(direction) as isize;
+        let delta = delta
+            .min(sizes[neighbour] as isize - min)
+            .max(min - sizes[index] as isize);
+
+        sizes[index] = (sizes[index] as isize + delta) as usize;
+        sizes[neighbour] = (sizes[neighbour] as isize - delta) as usize;
+    }
+
+    /// Sets the height or the width of `window` to `size` cells, as far as its neighbour
+    /// allows it.
+    pub fn set_size(&mut self, window: usize, direction: SplitDirection, size: usize) {
+        let current = match self.ancestor_split(window, direction) {
+            Some((Self::Split { sizes, .. }, index)) => sizes[index],
+            _ => return,
+        };
+
+        self.resize(window, direction, size as isize - current as isize);
+    }
+
+    /// Gives all the windows of each split the same size, in a layout filling `rect`.
+    pub fn equalize(&mut self, rect: Rect) {
+        if let Self::Split {
+            direction,
+            children,
+            sizes,
+        } = self
+        {
+            let available = match direction {
+                SplitDirection::Horizontal => rect.height,
+                SplitDirection::Vertical => rect.width.saturating_sub(children.len() - 1),
+            };
+            let count = children.len();
+            *sizes = (0..count)
+                .map(|index| available * (index + 1) / count - available * index / count)
+                .collect();
+
+            let rects = split_rect(rect, *direction, sizes);
+            for (child, rect) in children.iter_mut().zip(rects) {
+                child.equalize(rect);
+            }
+        }
+    }
+
+    /// Returns the window next to `window` in `direction`, preferring the one facing the
+    /// cursor, which is at `cursor` on screen.
+    pub fn neighbour(
+        &self,
+        window: usize,
+        direction: Direction,
+        cursor: Position,
+        rect: Rect,
+    ) -> Option<usize> {
+        let windows = self.arrange(rect);
+        let (_, current) = windows.iter().find(|(index, _)| *index == window)?;
+
+        // The point just past the edge of the window, facing the cursor.
+        let (x, y) = match direction {
+            Direction::Left => (current.x.checked_sub(2)?, cursor.y),
+            Direction::Right => (current.x + current.width + 1, cursor.y),
+            Direction::Up => (cursor.x, current.y.checked_sub(1)?),
+            Direction::Down => (cursor.x, current.y + current.height),
+        };
+
+        windows
+            .iter()
+            .find(|(_, rect)| rect.contains(x, y))
+            .map(|(index, _)| *index)
+    }
+
+    pub fn windows(&self) -> Vec<usize> {
+        match self {
+            Self::Window(window) => vec![*window],
+            Self::Split { children, .. } => children.iter().flat_map(Self::windows).collect(),
+        }
+    }
+
+    fn find_mut(&mut self, window: usize) -> Option<&mut Self> {
+        let path = self.path_to(window)?;
+        Some(self.node_mut(&path))
+    }
+
+    /// Returns the indices of the children leading from the root to `window`.
+    fn path_to(&self, window: usize) -> Option<Vec<usize>> {
+        match self {
+            Self::Window(index) => (*index == window).then(Vec::new),
+            Self::Split { children, .. } => {
+                children.iter().enumerate().find_map(|(index, child)| {
+                    let mut path = child.path_to(window)?;
+                    path.insert(0, index);
+                    Some(path)
+                })
+            }
+        }
+    }
+
+    fn node(&self, path: &[usize]) -> &Self {
+        match (self, path.split_first()) {
+            (Self::Split { children, .. }, Some((index, rest))) => children[*index].node(rest),
+            _ => self,
+        }
+    }
+
+    fn node_mut(&mut self, path: &[usize]) -> &mut Self {
+        match (self, path.split_first()) {
+            (Self::Split { children, .. }, Some((index, rest))) => children[*index].node_mut(rest),
+            (node, _) => node,
+        }
+    }
+
+    /// Returns the split directly holding `window` and the index of the window in it.
+    fn parent_of(&mut self, window: usize) -> Option<(&mut Self, usize)> {
+        let path = self.path_to(window)?;
+        let (index, parent) = path.split_last()?;
+        Some((self.node_mut(parent), *index))
+    }
+
+    /// Returns the innermost split in `direction` holding `window`, and the index of the
+    /// child holding it.
+    fn ancestor_split(
+        &mut self,
+        window: usize,
+        direction: SplitDirection,
+    ) -> Option<(&mut Self, usize)> {
+        let path = self.path_to(window)?;
+        let depth = (0..path.len()).rev().find(|depth| {
+            matches!(
+                self.node(&path[..*depth]),
+                Self::Split { direction: split_direction, .. } if *split_direction == direction
+            )
+        })?;
+
+        Some((self.node_mut(&path[..depth]), path[depth]))
+    }
+
+    /// Replaces splits left with a single child by that child.
+    fn collapse(&mut self) {
+        if let Self::Split { children, .. } = self {
+            children.iter_mut().for_each(Self::collapse);
+            if children.len() == 1 {
+                *self = children.remove(0);
+            }
+        }
+    }
+
+    fn shift_from(&mut self, removed: usize) {
+        match self {
+            Self::Window(index) if *index > removed => *index -= 1,
+            Self::Window(_) => (),
+            Self::Split { children, .. } => children
+                .iter_mut()
+                .for_each(|child| child.shift_from(removed)),
+        }
+    }
+}
+
+fn min_extent(direction: SplitDirection) -> usize {
+    match direction {
+        SplitDirection::Horizontal => MIN_HEIGHT,
+        SplitDirection::Vertical => MIN_WIDTH,
+    }
+}
+
+/// Shares `rect` out between children of the given `sizes`, leaving a column between them
+/// when they are side by side.
+fn split_rect(rect: Rect, direction: SplitDirection, sizes: &[usize]) -> Vec<Rect> {
+    let separators = match direction {
+        SplitDirection::Horizontal => 0,
+        SplitDirection::Vertical => sizes.len().saturating_sub(1),
+    };
+    let available = rect.extent(direction).saturating_sub(separators);
+    let total = sizes.iter().sum::<usize>().max(1);
+
+    let mut rects = Vec::new();
+    let mut sum = 0;
+    let mut offset = 0;
+    for size in sizes {
+        let start = available * sum / total;
+        sum += size;
+        let end = available * sum / total;
+
+        rects.push(match direction {
+            SplitDirection::Horizontal => Rect {
+                y: rect.y + offset,
+                height: end - start,
+                ..rect
+            },
+            SplitDirection::Vertical => Rect {
+                x: rect.x + offset,
+                width: end - start,
+                ..rect
+            },
+        });
+        offset += end - start;
+        if direction == SplitDirection::Vertical {
+            offset += 1;
+        }
+    }
+
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 80,
+        height: 24,
+    };
+
+    /// Returns the index of each window with its area, as `(window, x, y, width, height)`.
+    fn areas(layout: &Layout) -> Vec<(usize, usize, usize, usize, usize)> {
+        layout
+            .arrange(SCREEN)
+            .into_iter()
+            .map(|(window, rect)| (window, rect.x, rect.y, rect.width, rect.height))
+            .collect()
+    }
+
+    fn area(layout: &Layout, window: usize) -> Rect {
+        let windows = layout.arrange(SCREEN);
+        windows
+            .into_iter()
+            .find(|(index, _)| *index == window)
+            .unwrap()
+            .1
+    }
+
+    /// Returns a layout of `count` windows split in `direction` with `:split` or `:vsplit`,
+    /// the last one made being at the top or on the left.
+    fn split(count: usize, direction: SplitDirection) -> Layout {
+        let mut layout = Layout::default();
+        for window in 1..count {
+            assert!(layout.split(0, window, direction, area(&layout, 0)));
+        }
+        layout
+    }
+
+    #[test]
+    fn split_horizontally() {
+        let layout = split(2, SplitDirection::Horizontal);
+        assert_eq!(areas(&layout), [(1, 0, 0, 80, 12), (0, 0, 12, 80, 12)]);
+        assert!(layout.separators(SCREEN).is_empty());
+    }
+
+    #[test]
+    fn split_vertically() {
+        let layout = split(2, SplitDirection::Vertical);
+        assert_eq!(areas(&layout), [(1, 0, 0, 39, 24), (0, 40, 0, 40, 24)]);
+
+        let separators = layout.separators(SCREEN);
+        assert_eq!(separators.len(), 1);
+        assert!(
+            separators[0]
+                == Rect {
+                    x: 39,
+                    y: 0,
+                    width: 1,
+                    height: 24
+                }
+        );
+    }
+
+    #[test]
+    fn splits_in_the_same_direction_share_a_parent() {
+        let layout = split(3, SplitDirection::Horizontal);
+        assert_eq!(
+            areas(&layout),
+            [(1, 0, 0, 80, 12), (2, 0, 12, 80, 6), (0, 0, 18, 80, 6)]
+        );
+    }
+
+    #[test]
+    fn nested_splits() {
+        let mut layout = split(2, SplitDirection::Horizontal);
+        assert!(layout.split(0, 2, SplitDirection::Vertical, area(&layout, 0)));
+        assert_eq!(
+            areas(&layout),
+            [(1, 0, 0, 80, 12), (2, 0, 12, 39, 12), (0, 40, 12, 40, 12)]
+        );
+        assert_eq!(layout.separators(SCREEN).len(), 1);
+    }
+
+    #[test]
+    fn split_without_room() {
+        let mut layout = Layout::default();
+        let rect = Rect {
+            height: 3,
+            ..SCREEN
+        };
+        assert!(!layout.split(0, 1, SplitDirection::Horizontal, rect));
+        assert_eq!(layout.windows(), [0]);
+
+        let rect = Rect { width: 2, ..SCREEN };
+        assert!(!layout.split(0, 1, SplitDirection::Vertical, rect));
+        let rect = Rect { width: 3, ..SCREEN };
+        assert!(layout.split(0, 1, SplitDirection::Vertical, rect));
+    }
+
+    #[test]
+    fn remove_gives_room_to_a_neighbour() {
+        let mut layout = split(3, SplitDirection::Horizontal);
+        layout.remove(1);
+        // Windows 2 and 0 remain, window 2 being shifted to 1.
+        assert_eq!(areas(&layout), [(1, 0, 0, 80, 18), (0, 0, 18, 80, 6)]);
+
+        layout.remove(0);
+        assert!(matches!(layout, Layout::Window(0)));
+        assert_eq!(areas(&layout), [(0, 0, 0, 80, 24)]);
+    }
+
+    #[test]
+    fn remove_collapses_nested_splits() {
+        let mut layout = split(2, SplitDirection::Horizontal);
+        layout.split(0, 2, SplitDirection::Vertical, area(&layout, 0));
+        layout.remove(2);
+        assert_eq!(areas(&layout), [(1, 0, 0, 80, 12), (0, 0, 12, 80, 12)]);
+    }
+
+    #[test]
+    fn only() {
+        let mut layout = split(3, SplitDirection::Vertical);
+        layout.only(2);
+        assert_eq!(areas(&layout), [(2, 0, 0, 80, 24)]);
+    }
+
+    #[test]
+    fn resize() {
+        let mut layout = split(2, SplitDirection::Horizontal);
+        layout.resize(1, SplitDirection::Horizontal, 3);
+        assert_eq!(areas(&layout), [(1, 0, 0, 80, 15), (0, 0, 15, 80, 9)]);
+
+        layout.resize(0, SplitDirection::Horizontal, 1);
+        assert_eq!(areas(&layout), [(1, 0, 0, 80, 14), (0, 0, 14, 80, 10)]);
+
+        // Neither window gets smaller than its status line and a row.
+        layout.resize(1, SplitDirection::Horizontal, 100);
+        assert_eq!(areas(&layout), [(1, 0, 0, 80, 22), (0, 0, 22, 80, 2)]);
+        layout.resize(1, SplitDirection::Horizontal, -100);
+        assert_eq!(areas(&layout), [(1, 0, 0, 80, 2), (0, 0, 2, 80, 22)]);
+
+        // There is no vertical split to resize.
+        layout.resize(1, SplitDirection::Vertical, 5);
+        assert_eq!(areas(&layout), [(1, 0, 0, 80, 2), (0, 0, 2, 80, 22)]);
+    }
+
+    #[test]
+    fn resize_the_enclosing_split() {
+        let mut layout = split(2, SplitDirection::Vertical);
+        layout.split(0, 2, SplitDirection::Horizontal, area(&layout, 0));
+        layout.resize(2, SplitDirection::Vertical, -9);
+        assert_eq!(
+            areas(&layout),
+            [(1, 0, 0, 48, 24), (2, 49, 0, 31, 12), (0, 49, 12, 31, 12)]
+        );
+
+        layout.set_size(2, SplitDirection::Vertical, 60);
+        assert_eq!(
+            areas(&layout),
+            [(1, 0, 0, 19, 24), (2, 20, 0, 60, 12), (0, 20, 12, 60, 12)]
+        );
+    }
+
+    #[test]
+    fn set_size() {
+        let mut layout = split(2, SplitDirection::Horizontal);
+        layout.set_size(0, SplitDirection::Horizontal, 5);
+        assert_eq!(areas(&layout), [(1, 0, 0, 80, 19), (0, 0, 19, 80, 5)]);
+    }
+
+    #[test]
+    fn equalize() {
+        let mut layout = split(3, SplitDirection::Vertical);
+        layout.equalize(SCREEN);
+        assert_eq!(
+            areas(&layout),
+            [(1, 0, 0, 26, 24), (2, 27, 0, 26, 24), (0, 54, 0, 26, 24)]
+        );
+    }
+
+    #[test]
+    fn neighbours() {
+        let mut layout = split(2, SplitDirection::Vertical);
+        layout.split(0, 2, SplitDirection::Horizontal, area(&layout, 0));
+        let cursor = Position { x: 50, y: 5 };
+
+        assert_eq!(
+            layout.neighbour(1, Direction::Right, cursor, SCREEN),
+            Some(2)
+        );
+        let cursor = Position { x: 10, y: 20 };
+        assert_eq!(
+            layout.neighbour(1, Direction::Right, cursor, SCREEN),
+            Some(0)
+        );
+        assert_eq!(layout.neighbour(1, Direction::Left, cursor, SCREEN), None);
+        assert_eq!(
+            layout.neighbour(0, Direction::Left, cursor, SCREEN),
+            Some(1)
+        );
+
+        let cursor = Position { x: 60, y: 20 };
+        assert_eq!(
+            layout.neighbour(2, Direction::Down, cursor, SCREEN),
+            Some(0)
+        );
+        assert_eq!(layout.neighbour(0, Direction::Up, cursor, SCREEN), Some(2));
+        assert_eq!(layout.neighbour(0, Direction::Down, cursor, SCREEN), None);
+    }
+}